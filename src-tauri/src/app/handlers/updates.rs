@@ -90,9 +90,8 @@ pub async fn set_update_check_disabled(disabled: bool) -> Result<(), String> {
     let mut config = APP_CONFIG
         .lock()
         .map_err(|e| format!("Failed to acquire config lock: {e}"))?;
-    config.update_check_disabled = Some(disabled);
     config
-        .save()
+        .update(|config| config.update_check_disabled = Some(disabled))
         .map_err(|e| format!("Failed to save config: {e}"))?;
     Ok(())
 }
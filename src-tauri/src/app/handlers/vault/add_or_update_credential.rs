@@ -6,6 +6,7 @@ use typeshare::typeshare;
 use crate::app::AppState;
 use crate::app::handlers::app_errors::{AppError, ErrorContext};
 use crate::app::handlers::vault::with_unlocked_vault;
+use crate::secrets::vaults::CredentialKind;
 
 #[derive(Deserialize)]
 #[typeshare]
@@ -14,6 +15,7 @@ pub struct AddOrUpdateCredentialRequest {
     pub item_key: String,
     pub credential_key: String,
     pub title: String,
+    pub kind: Option<CredentialKind>,
     pub value: String,
 }
 
@@ -27,11 +29,12 @@ pub async fn add_or_update_credential(
         item_key,
         credential_key,
         title,
+        kind,
         value,
     } = request;
 
     with_unlocked_vault(&state, &vault_key, |vw| {
-        vw.add_secret(&item_key, &credential_key, &title, value.into())
+        vw.add_secret(&item_key, &credential_key, &title, kind, value.into())
             .error_context("Failed to add/update credential.")
     })
 }
@@ -3,7 +3,9 @@ use std::collections::BTreeMap;
 use serde::Serialize;
 use typeshare::typeshare;
 
-use crate::secrets::vaults::{Error, VaultItemCredentialOverview, VaultItemOverview, VaultWrapper};
+use crate::secrets::vaults::{
+    CredentialKind, Error, VaultItemCredentialOverview, VaultItemOverview, VaultWrapper,
+};
 
 // VaultSchema is the serialized form of VaultWrapper, with decrypted item
 // titles and credential titles, but without credential values. Used for sending
@@ -39,6 +41,7 @@ pub struct VaultItemSchema {
     #[typeshare(serialized_as = "string")]
     id: uuid::Uuid,
     title: String,
+    tags: Vec<String>,
     #[typeshare(serialized_as = "HashMap<String, VaultItemCredentialSchema>")]
     credentials: BTreeMap<String, VaultItemCredentialSchema>,
 }
@@ -48,6 +51,7 @@ impl From<&VaultItemOverview> for VaultItemSchema {
         Self {
             id: item.id,
             title: item.title.clone(),
+            tags: item.tags.clone(),
             credentials: item
                 .credentials
                 .values()
@@ -63,6 +67,10 @@ pub struct VaultItemCredentialSchema {
     #[typeshare(serialized_as = "String")]
     id: uuid::Uuid,
     title: String,
+    kind: CredentialKind,
+    #[serde(with = "time::serde::rfc3339::option")]
+    #[typeshare(serialized_as = "Option<String>")]
+    expires_at: Option<time::OffsetDateTime>,
 }
 
 impl From<&VaultItemCredentialOverview> for VaultItemCredentialSchema {
@@ -70,6 +78,8 @@ impl From<&VaultItemCredentialOverview> for VaultItemCredentialSchema {
         Self {
             id: cred.id,
             title: cred.title.clone(),
+            kind: cred.kind,
+            expires_at: cred.expires_at,
         }
     }
 }
@@ -1,14 +1,17 @@
 use std::collections::HashMap;
-use std::os::unix::process::CommandExt;
-use std::process::Command;
+use std::io;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Command, ExitStatus};
 
 use anyhow::{anyhow, bail};
 use clap::Parser;
 use glob::glob;
 use itertools::Itertools;
+use tokio::signal::unix::{SignalKind, signal};
 
-use crate::core::interpolate::interpolate_secrets;
+use crate::core::interpolate::{Interpolator, failure_report};
 use crate::secrets::vaults::VaultsManager;
+use crate::secrets::vaults::reference::find_references;
 
 #[derive(Parser, Debug)]
 pub struct ExecCommand {
@@ -18,6 +21,16 @@ pub struct ExecCommand {
     #[arg(long = "env-file", short = 'e')]
     pub env_files: Vec<String>,
 
+    /// Vault for references without one (axo:///item/credential), instead of
+    /// AXO_DEFAULT_VAULT or the default vault
+    #[arg(long)]
+    pub vault: Option<String>,
+
+    /// Run the command even if references can't be resolved, with NOT_FOUND or
+    /// ERROR in their place
+    #[arg(long)]
+    pub lenient: bool,
+
     /// Command to execute with interpolated environment.
     #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
     pub command: Vec<String>,
@@ -29,10 +42,25 @@ impl ExecCommand {
     // FOO='axo://...' ap exec -- sh -c 'echo $FOO'
     // ap exec --env-file /tmp/test.env -- printenv FOO
     pub async fn execute(&self) -> ! {
-        let env = self.try_prepare_env().await.unwrap_or_else(|e| {
+        let mut vaults = VaultsManager::new();
+        if let Some(vault) = &self.vault {
+            vaults.set_default_vault(vault.clone());
+        }
+        if let Err(e) = vaults.enable_attachment_files() {
             eprintln!("error: {e}");
             std::process::exit(1);
-        });
+        }
+        let env = match self.try_prepare_env(&mut vaults).await {
+            Ok(env) => env,
+            Err(e) => {
+                // removes attachment files written for ?file references
+                drop(vaults);
+                eprintln!("error: {e}");
+                std::process::exit(1);
+            },
+        };
+        let attachment_files = vaults.take_attachment_files();
+        drop(vaults);
 
         let (program, args) = self.command.split_first().unwrap();
         let mut command = Command::new(program);
@@ -42,6 +70,25 @@ impl ExecCommand {
         // interpolated values from env files)
         command.env_clear().envs(&env);
 
+        // ?file references wrote decrypted attachments to temporary files, which
+        // must outlive the command and be removed afterwards, so run it as a
+        // child process instead of replacing this one.
+        if attachment_files
+            .as_ref()
+            .is_some_and(|files| !files.is_empty())
+        {
+            let status = run_child(command).await;
+            drop(attachment_files);
+            match status {
+                Ok(status) => std::process::exit(exit_code(status)),
+                Err(err) => {
+                    eprintln!("error: Failed to execute '{program}': {err}");
+                    std::process::exit(1);
+                },
+            }
+        }
+        drop(attachment_files);
+
         // exec the command, replacing this process.
         let err = command.exec();
 
@@ -50,7 +97,10 @@ impl ExecCommand {
         std::process::exit(1);
     }
 
-    pub async fn try_prepare_env(&self) -> Result<HashMap<String, String>, anyhow::Error> {
+    pub async fn try_prepare_env(
+        &self,
+        vaults: &mut VaultsManager,
+    ) -> Result<HashMap<String, String>, anyhow::Error> {
         let mut env_vars: HashMap<String, String> = std::env::vars().collect();
         for pattern in &self.env_files {
             let paths = glob(pattern).map_err(|e| anyhow!("Invalid pattern '{pattern}': {e}"))?;
@@ -75,12 +125,57 @@ impl ExecCommand {
         }
 
         // Interpolate axo:// references in every environment value
-        let mut vaults = VaultsManager::new();
+        let mut interpolator = Interpolator::new(vaults);
+        interpolator.unlock_vaults(env_vars.values().flat_map(|v| find_references(v)));
         let interpolated_env: HashMap<String, String> = env_vars
             .into_iter()
-            .map(|(k, v)| (k, interpolate_secrets(&v, &mut vaults)))
+            .map(|(k, v)| (k, interpolator.interpolate(&v)))
             .collect();
 
+        let failures = interpolator.failures();
+        if !failures.is_empty() && !self.lenient {
+            bail!(
+                "{}\nNot running the command (use --lenient to run it anyway)",
+                failure_report(&failures)
+            );
+        }
         Ok(interpolated_env)
     }
 }
+
+// run the command as a child process, without letting SIGINT, SIGTERM or SIGHUP
+// end this process before it has removed the attachment files
+async fn run_child(command: Command) -> io::Result<ExitStatus> {
+    // handlers are installed before spawning, so there is no window in which a
+    // signal still has its default action
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    let mut child = tokio::process::Command::from(command).spawn()?;
+    let pid = child.id().map(|pid| pid as libc::pid_t);
+    let forward = |signal: libc::c_int| {
+        if let Some(pid) = pid {
+            unsafe { libc::kill(pid, signal) };
+        }
+    };
+    loop {
+        tokio::select! {
+            status = child.wait() => return status,
+            // the terminal sends Ctrl-C to the child as well, as it's in the
+            // same process group
+            _ = interrupt.recv() => {},
+            _ = terminate.recv() => forward(libc::SIGTERM),
+            _ = hangup.recv() => forward(libc::SIGHUP),
+        }
+    }
+}
+
+// exit code of a child process, following the shell convention of 128 + signal
+// for processes killed by a signal
+fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
@@ -0,0 +1,243 @@
+use std::fs::{self, OpenOptions};
+use std::io::{IsTerminal, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use clap::{Parser, Subcommand, ValueEnum, ValueHint};
+use color_print::cprintln;
+use secrecy::ExposeSecret;
+
+use crate::core::atomic_file::write_atomic;
+use crate::secrets::vaults::encrypted_file::{FileFormat, decrypt_file, encrypt_file};
+use crate::secrets::vaults::{AttachmentFiles, MemberKey, local_member_key};
+
+#[derive(Parser, Debug)]
+#[command(flatten_help = true, help_template = "{usage-heading} {usage}")]
+pub struct FileCommand {
+    #[command(subcommand)]
+    subcommand: FileSubcommand,
+
+    /// File format (default: from the file extension)
+    #[arg(long, value_enum, global = true)]
+    format: Option<FileFormatArg>,
+}
+
+#[derive(Subcommand, Debug)]
+enum FileSubcommand {
+    /// Encrypt the values of a YAML, JSON, TOML or .env file in place
+    Encrypt {
+        #[arg(value_hint = ValueHint::FilePath)]
+        path: PathBuf,
+
+        /// Also encrypt for this public key ("ecdsa-sha2-nistp256 ..." or
+        /// "age1..."), can be repeated
+        #[arg(long, short = 'r', value_name = "PUBLIC_KEY")]
+        recipient: Vec<String>,
+
+        /// Don't encrypt for the local vault key (requires --recipient)
+        #[arg(long, requires = "recipient")]
+        no_local_key: bool,
+
+        /// Write the encrypted file here instead of replacing PATH
+        #[arg(long, short = 'o', value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
+    },
+
+    /// Decrypt a file encrypted with `ap file encrypt` in place
+    Decrypt {
+        #[arg(value_hint = ValueHint::FilePath)]
+        path: PathBuf,
+
+        /// Write the decrypted file here (created with 0600 permissions)
+        /// instead of replacing PATH, or - for stdout
+        #[arg(long, short = 'o', value_hint = ValueHint::FilePath)]
+        output: Option<PathBuf>,
+    },
+
+    /// Decrypt a file into a temporary file, open it in $EDITOR and encrypt
+    /// the changes
+    Edit {
+        #[arg(value_hint = ValueHint::FilePath)]
+        path: PathBuf,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum FileFormatArg {
+    Yaml,
+    Json,
+    Toml,
+    Dotenv,
+}
+
+impl FileCommand {
+    pub async fn execute(&self) {
+        let result = match &self.subcommand {
+            FileSubcommand::Encrypt {
+                path,
+                recipient,
+                no_local_key,
+                output,
+            } => self.cmd_encrypt(path, recipient, *no_local_key, output.as_deref()),
+            FileSubcommand::Decrypt { path, output } => self.cmd_decrypt(path, output.as_deref()),
+            FileSubcommand::Edit { path } => self.cmd_edit(path),
+        };
+        if let Err(e) = result {
+            cprintln!("<red>Error:</red> {e}");
+            std::process::exit(1);
+        }
+    }
+
+    fn file_format(&self, path: &Path) -> Result<FileFormat, String> {
+        match self.format {
+            Some(FileFormatArg::Yaml) => Ok(FileFormat::Yaml),
+            Some(FileFormatArg::Json) => Ok(FileFormat::Json),
+            Some(FileFormatArg::Toml) => Ok(FileFormat::Toml),
+            Some(FileFormatArg::Dotenv) => Ok(FileFormat::Dotenv),
+            None => FileFormat::from_path(path).ok_or_else(|| {
+                format!(
+                    "Unknown file format for {}, set one with --format",
+                    path.display()
+                )
+            }),
+        }
+    }
+
+    fn cmd_encrypt(
+        &self,
+        path: &Path,
+        recipients: &[String],
+        no_local_key: bool,
+        output: Option<&Path>,
+    ) -> Result<(), String> {
+        let format = self.file_format(path)?;
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+
+        let mut member_keys = recipients
+            .iter()
+            .map(|recipient| recipient.parse::<MemberKey>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        if !no_local_key {
+            let local_key =
+                local_member_key().map_err(|e| format!("Failed to get vault key: {e}"))?;
+            member_keys.insert(0, local_key);
+        }
+        let encrypted = encrypt_file(&contents, format, &member_keys)
+            .map_err(|e| format!("Failed to encrypt {}: {e}", path.display()))?;
+
+        let output = output.unwrap_or(path);
+        write_atomic(output, encrypted.as_bytes())
+            .map_err(|e| format!("Failed to write {}: {e}", output.display()))?;
+        cprintln!(
+            "Encrypted <blue>{}</blue> for {} keys",
+            output.display(),
+            member_keys.len()
+        );
+        Ok(())
+    }
+
+    fn cmd_decrypt(&self, path: &Path, output: Option<&Path>) -> Result<(), String> {
+        let format = self.file_format(path)?;
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let decrypted = decrypt_file(&contents, format)
+            .map_err(|e| format!("Failed to decrypt {}: {e}", path.display()))?;
+        let plaintext = decrypted.plaintext.expose_secret().as_bytes();
+
+        match output {
+            Some(output) if output == Path::new("-") => std::io::stdout()
+                .write_all(plaintext)
+                .map_err(|e| format!("Failed to write output: {e}"))?,
+            Some(output) => {
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(output)
+                    .map_err(|e| format!("Failed to create {}: {e}", output.display()))?;
+                file.write_all(plaintext)
+                    .map_err(|e| format!("Failed to write {}: {e}", output.display()))?;
+            },
+            None => {
+                write_atomic(path, plaintext)
+                    .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+                cprintln!("Decrypted <blue>{}</blue>", path.display());
+            },
+        }
+        Ok(())
+    }
+
+    fn cmd_edit(&self, path: &Path) -> Result<(), String> {
+        let format = self.file_format(path)?;
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        let decrypted = decrypt_file(&contents, format)
+            .map_err(|e| format!("Failed to decrypt {}: {e}", path.display()))?;
+
+        // the temporary file keeps the file name, so editors pick the right
+        // syntax, and is removed when tmp_files is dropped
+        let tmp_files = AttachmentFiles::create()
+            .map_err(|e| format!("Failed to create temporary directory: {e}"))?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let tmp_path = tmp_files
+            .write(&file_name, decrypted.plaintext.expose_secret().as_bytes())
+            .map_err(|e| format!("Failed to write temporary file: {e}"))?;
+
+        loop {
+            run_editor(&tmp_path)?;
+            let edited = fs::read_to_string(&tmp_path)
+                .map_err(|e| format!("Failed to read temporary file: {e}"))?;
+            if edited == *decrypted.plaintext.expose_secret() {
+                cprintln!("No changes to <blue>{}</blue>", path.display());
+                return Ok(());
+            }
+            match decrypted.encrypt(&edited, format) {
+                Ok(encrypted) => {
+                    write_atomic(path, encrypted.as_bytes())
+                        .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+                    cprintln!("Encrypted <blue>{}</blue>", path.display());
+                    return Ok(());
+                },
+                // e.g. a syntax error: reopen the editor rather than losing the
+                // changes
+                Err(e) => {
+                    cprintln!("<red>Error:</red> {e}");
+                    let reopen = std::io::stdin().is_terminal()
+                        && inquire::Confirm::new("Edit the file again?")
+                            .with_default(true)
+                            .prompt()
+                            .unwrap_or(false);
+                    if !reopen {
+                        return Err(format!("{} was not changed", path.display()));
+                    }
+                },
+            }
+        }
+    }
+}
+
+// runs $VISUAL or $EDITOR (which may include arguments, e.g. "code --wait"),
+// or vi
+fn run_editor(path: &Path) -> Result<(), String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let args = shlex::split(&editor)
+        .filter(|args| !args.is_empty())
+        .ok_or_else(|| format!("Invalid editor command: {editor}"))?;
+    let status = Command::new(&args[0])
+        .args(&args[1..])
+        .arg(path)
+        .status()
+        .map_err(|e| format!("Failed to run {editor}: {e}"))?;
+    if !status.success() {
+        return Err(format!("{editor} exited with {status}"));
+    }
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use clap::{Parser, ValueEnum};
+use color_print::{ceprintln, cprintln};
+use secrecy::ExposeSecret;
+
+use crate::core::dirs::vaults_dir;
+use crate::core::password_generator::{
+    CharacterClass, Generated, generate_passphrase, generate_password,
+};
+use crate::secrets::vaults::VaultWrapper;
+use crate::secrets::vaults::reference::{ItemReference, ReferenceQuery};
+
+#[derive(Parser, Debug)]
+pub struct GenerateCommand {
+    /// Number of characters
+    #[arg(long, short = 'l', default_value_t = 24, conflicts_with = "words")]
+    length: usize,
+
+    /// Character classes to use, each at least once (default: all of them)
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "words")]
+    charset: Vec<CharacterClassArg>,
+
+    /// Generate a passphrase of this many random words instead
+    #[arg(long)]
+    words: Option<usize>,
+
+    /// Separator between the words of a passphrase
+    #[arg(long, default_value = "-", requires = "words")]
+    separator: String,
+
+    /// Store the value in this credential (reference or
+    /// {item_key}/{credential_key}) instead of printing it
+    #[arg(long, value_name = "REFERENCE")]
+    store: Option<ItemReference>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum CharacterClassArg {
+    /// a-z
+    Lower,
+    /// A-Z
+    Upper,
+    /// 0-9
+    Digits,
+    /// !#%&*+-.:=?@^_~
+    Symbols,
+}
+
+impl GenerateCommand {
+    pub async fn execute(&self) {
+        if let Err(e) = self.generate() {
+            cprintln!("<red>Error:</red> {e}");
+            std::process::exit(1);
+        }
+    }
+
+    fn generate(&self) -> Result<(), String> {
+        let generated = match self.words {
+            Some(words) => generate_passphrase(words, &self.separator)?,
+            None => generate_password(self.length, &self.character_classes())?,
+        };
+        match &self.store {
+            Some(item_reference) => self.store(item_reference, generated),
+            None => {
+                println!("{}", generated.value.expose_secret());
+                ceprintln!("<dim>~{:.0} bits of entropy</dim>", generated.entropy);
+                Ok(())
+            },
+        }
+    }
+
+    fn character_classes(&self) -> Vec<CharacterClass> {
+        if self.charset.is_empty() {
+            return vec![
+                CharacterClass::Lower,
+                CharacterClass::Upper,
+                CharacterClass::Digits,
+                CharacterClass::Symbols,
+            ];
+        }
+        self.charset
+            .iter()
+            .map(|class| match class {
+                CharacterClassArg::Lower => CharacterClass::Lower,
+                CharacterClassArg::Upper => CharacterClass::Upper,
+                CharacterClassArg::Digits => CharacterClass::Digits,
+                CharacterClassArg::Symbols => CharacterClass::Symbols,
+            })
+            .collect()
+    }
+
+    // writes the value to the vault without printing it
+    fn store(&self, item_reference: &ItemReference, generated: Generated) -> Result<(), String> {
+        let Some(cred_key) = &item_reference.credential else {
+            return Err("Credential key must be specified".to_string());
+        };
+        if item_reference.query != ReferenceQuery::default() {
+            return Err(format!(
+                "Generated values can't be stored with ?{}",
+                item_reference.query
+            ));
+        }
+        let mut vw = VaultWrapper::load(&vaults_dir(), item_reference.vault.clone())
+            .map_err(|e| format!("Failed to load vault: {e}"))?;
+        vw.unlock_for_update()
+            .map_err(|e| format!("Failed to unlock vault: {e}"))?;
+
+        // existing items and credentials keep their titles
+        let item_key = vw
+            .add_item(&item_reference.item, &item_reference.item)
+            .map_err(|e| format!("Failed to add item: {e}"))?
+            .key
+            .clone();
+        let cred_title = vw
+            .get_secret_overview(&item_key, cred_key)
+            .map_err(|e| format!("Failed to get credential: {e}"))?
+            .map_or_else(|| cred_key.clone(), |cred| cred.title.clone());
+        vw.add_secret(&item_key, cred_key, &cred_title, None, generated.value)
+            .map_err(|e| format!("Failed to add secret: {e}"))?;
+        vw.save()
+            .map_err(|e| format!("Failed to save vault: {e}"))?;
+
+        cprintln!(
+            "Stored generated value in <blue>axo://{}/{item_key}/{cred_key}</blue> <dim>(~{:.0} bits of entropy)</dim>",
+            vw.key,
+            generated.entropy
+        );
+        Ok(())
+    }
+}
@@ -3,9 +3,11 @@ use std::path::PathBuf;
 
 use clap::Parser;
 
-use crate::core::interpolate::interpolate_secrets;
+use crate::core::atomic_file::write_atomic_with_mode;
+use crate::core::interpolate::{Interpolator, failure_report};
 use crate::core::read_input::read_file_or_stdin;
 use crate::secrets::vaults::VaultsManager;
+use crate::secrets::vaults::reference::find_references;
 
 #[derive(Parser, Debug)]
 pub struct InjectCommand {
@@ -13,9 +15,25 @@ pub struct InjectCommand {
     #[arg(long = "input", short = 'i')]
     pub input_file: Option<PathBuf>,
 
-    /// Output file path. If not provided, the result will be printed to stdout.
+    /// Output file path (created with 0600 permissions). If not provided, the
+    /// result will be printed to stdout.
     #[arg(long = "output", short = 'o')]
     pub output_file: Option<PathBuf>,
+
+    /// Vault for references without one (axo:///item/credential), instead of
+    /// AXO_DEFAULT_VAULT or the default vault
+    #[arg(long)]
+    pub vault: Option<String>,
+
+    /// Render the input as a template, e.g. {{ axo "vault/item/cred" | base64
+    /// }}, instead of only replacing axo:// references.
+    #[arg(long, short = 't')]
+    pub template: bool,
+
+    /// Write the output even if references can't be resolved, with NOT_FOUND
+    /// or ERROR in their place
+    #[arg(long)]
+    pub lenient: bool,
 }
 
 impl InjectCommand {
@@ -24,18 +42,42 @@ impl InjectCommand {
             Ok(data) => String::from_utf8_lossy(&data).to_string(),
             Err(e) => {
                 eprintln!("error: {e}");
-                return;
+                std::process::exit(1);
             },
         };
 
         let mut vaults = VaultsManager::new();
-        let output_data = interpolate_secrets(&input_data, &mut vaults);
+        if let Some(vault) = &self.vault {
+            vaults.set_default_vault(vault.clone());
+        }
+        let mut interpolator = Interpolator::new(&mut vaults);
+        let output_data = if self.template {
+            match interpolator.render_template(&input_data) {
+                Ok(output_data) => output_data,
+                Err(e) => {
+                    eprintln!("error: Invalid template: {e}");
+                    std::process::exit(1);
+                },
+            }
+        } else {
+            interpolator.unlock_vaults(find_references(&input_data));
+            interpolator.interpolate(&input_data)
+        };
+        let failures = interpolator.failures();
+        if !failures.is_empty() && !self.lenient {
+            eprintln!(
+                "error: {}\nNo output written (use --lenient to write it anyway)",
+                failure_report(&failures)
+            );
+            std::process::exit(1);
+        }
         if let Some(output_path) = &self.output_file {
-            if let Err(e) = std::fs::write(output_path, output_data) {
+            if let Err(e) = write_atomic_with_mode(output_path, output_data.as_bytes(), 0o600) {
                 eprintln!(
                     "error: Failed to write output file {}: {e}",
                     output_path.display()
                 );
+                std::process::exit(1);
             }
         } else {
             match io::stdout().write_all(output_data.as_bytes()) {
@@ -43,7 +85,8 @@ impl InjectCommand {
                     let _ = io::stdout().flush();
                 },
                 Err(e) => {
-                    eprintln!("error:Failed to write to stdout: {}", e);
+                    eprintln!("error: Failed to write to stdout: {e}");
+                    std::process::exit(1);
                 },
             }
         }
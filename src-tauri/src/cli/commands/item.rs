@@ -1,14 +1,26 @@
-use std::io::{IsTerminal, Read};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{IsTerminal, Read, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use clap::{Parser, Subcommand};
-use color_print::{cformat, cprintln};
+use color_print::{ceprintln, cformat, cprintln};
 use inquire::Password;
-use regex::Regex;
-use secrecy::{ExposeSecret, SecretString};
+use secrecy::{ExposeSecret, SecretBox, SecretString};
+use time::format_description::well_known::{Iso8601, Rfc3339};
+use time::{Date, Duration, OffsetDateTime};
 
+use crate::core::atomic_file::write_atomic;
 use crate::core::dirs::vaults_dir;
-use crate::secrets::vaults::VaultWrapper;
+use crate::core::env_file::replace_env_values;
+use crate::secrets::vaults::importers::{derived_key, unique_key};
+use crate::secrets::vaults::reference::ItemReference;
+use crate::secrets::vaults::{CredentialKind, DEFAULT_VAULT, VaultWrapper, VaultsManager};
+
+// attachments larger than this are stored in sidecar files by default
+const SIDECAR_ATTACHMENT_THRESHOLD: usize = 64 * 1024;
 
 #[derive(Parser, Debug)]
 #[command(flatten_help = true, help_template = "{usage-heading} {usage}")]
@@ -20,38 +32,50 @@ pub struct ItemCommand {
     vault: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct ItemReference {
-    pub vault: Option<String>,
-    pub item: String,
-    pub credential: Option<String>,
-}
+/// Expiry given to `ap item set --expires`: an RFC 3339 timestamp, a date
+/// (YYYY-MM-DD, midnight UTC), a duration from now (e.g. 90d) or `never` to
+/// clear the expiry.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Expiry(Option<OffsetDateTime>);
 
-impl FromStr for ItemReference {
+impl FromStr for Expiry {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let axo_url_re =
-        Regex::new(r"^(?:axo://(?P<vault>[a-zA-Z0-9-_]+)/)?(?P<item>[a-zA-Z0-9-_]+)(?:/(?P<credential>[a-zA-Z0-9-_]+)?)").unwrap();
-        if let Some(captures) = axo_url_re.captures(s) {
-            let vault = captures.name("vault").map(|m| m.as_str().to_string());
-            let item = captures
-                .name("item")
-                .ok_or_else(|| format!("Missing item in reference: {}", s))?
-                .as_str()
-                .to_string();
-            let credential = captures.name("credential").map(|m| m.as_str().to_string());
-            Ok(ItemReference {
-                vault,
-                item,
-                credential,
-            })
-        } else {
-            Err(format!("Invalid item reference: {s}"))
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("never") || s.eq_ignore_ascii_case("none") {
+            return Ok(Expiry(None));
+        }
+        if let Ok(timestamp) = OffsetDateTime::parse(s, &Rfc3339) {
+            return Ok(Expiry(Some(timestamp)));
+        }
+        if let Ok(date) = Date::parse(s, &Iso8601::DATE) {
+            return Ok(Expiry(Some(date.midnight().assume_utc())));
+        }
+        match parse_duration(s) {
+            Ok(duration) => Ok(Expiry(Some(OffsetDateTime::now_utc() + duration))),
+            Err(_) => Err(format!(
+                "Invalid expiry: {s} (expected RFC 3339, YYYY-MM-DD, a duration like 90d, or never)"
+            )),
         }
     }
 }
 
+/// Parse a duration such as 12h, 14d or 2w.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let invalid = || format!("Invalid duration: {s} (expected e.g. 12h, 14d or 2w)");
+    let unit_start = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (amount, unit) = s.split_at(unit_start);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    match unit {
+        "h" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(invalid()),
+    }
+}
+
 #[derive(Subcommand, Debug)]
 enum ItemSubcommand {
     /// List items in the vault
@@ -67,7 +91,156 @@ enum ItemSubcommand {
     Set {
         item_reference: ItemReference,
         secret_value: Option<SecretString>,
+
+        /// Kind of credential: password (default), username, url, note,
+        /// totp_seed or ssh_key
+        #[arg(long)]
+        kind: Option<CredentialKind>,
+
+        /// Expiry: RFC 3339 timestamp, YYYY-MM-DD, duration from now (e.g.
+        /// 90d) or never
+        #[arg(long)]
+        expires: Option<Expiry>,
+    },
+
+    /// Store the values of a dotenv file as credentials of one item
+    ImportEnv {
+        path: PathBuf,
+
+        /// Item key to store the values in (created if it doesn't exist)
+        #[arg(long)]
+        item: String,
+
+        /// Replace each imported value in the file with its axo:// reference
+        #[arg(long)]
+        rewrite: bool,
+
+        /// Overwrite credentials the item already has (their previous values
+        /// are kept in history)
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// List credentials that have expired or expire soon
+    Expiring {
+        /// Include credentials expiring within this duration (e.g. 12h, 14d,
+        /// 2w)
+        #[arg(long, default_value = "14d", value_parser = parse_duration)]
+        within: Duration,
+
+        /// Check every vault instead of just the selected one
+        #[arg(long)]
+        all_vaults: bool,
+    },
+
+    /// Search credentials in all vaults by title, key or tag
+    Search {
+        /// Search terms, matched fuzzily; use #tag to match a tag exactly
+        #[arg(required = true)]
+        query: Vec<String>,
+    },
+
+    /// Add or remove tags of an item by reference or item_key
+    Tag {
+        item_reference: ItemReference,
+        tags: Vec<String>,
+
+        /// Remove the given tags instead of adding them
+        #[arg(long)]
+        remove: bool,
     },
+
+    /// Rename an item or credential by reference, item_key or
+    /// {item_key}/{credential_key}
+    Rename {
+        item_reference: ItemReference,
+        new_key: String,
+
+        /// Leave an alias so references to the old item key keep resolving
+        #[arg(long)]
+        alias: bool,
+    },
+
+    /// Move an item to another vault by reference or item_key
+    Move {
+        item_reference: ItemReference,
+
+        /// Vault key of the vault to move the item to
+        #[arg(long)]
+        to_vault: String,
+
+        /// Item key in the target vault (defaults to the current key)
+        #[arg(long = "as")]
+        new_key: Option<String>,
+
+        /// Leave an alias so references to the old location keep resolving
+        #[arg(long)]
+        alias: bool,
+    },
+
+    /// Copy an item by reference or item_key, to another vault or under a new
+    /// key
+    Copy {
+        item_reference: ItemReference,
+
+        /// Vault key of the vault to copy the item to (defaults to the item's
+        /// vault)
+        #[arg(long)]
+        to_vault: Option<String>,
+
+        /// Item key of the copy (defaults to the current key)
+        #[arg(long = "as")]
+        new_key: Option<String>,
+    },
+
+    /// Attach a file to an item, by reference or {item_key}/{attachment_key}
+    Attach {
+        item_reference: ItemReference,
+        path: PathBuf,
+
+        /// Store the encrypted file next to the vault file instead of inside
+        /// it (the default for files over 64 KiB)
+        #[arg(long, conflicts_with = "inline")]
+        sidecar: bool,
+
+        /// Store the encrypted file inside the vault file
+        #[arg(long)]
+        inline: bool,
+    },
+
+    /// Write an attachment to a file or stdout, by reference or
+    /// {item_key}/{attachment_key}
+    Extract {
+        item_reference: ItemReference,
+
+        /// File to write the attachment to (created with 0600 permissions; must
+        /// not already exist)
+        #[arg(long, short = 'o')]
+        output: Option<PathBuf>,
+    },
+
+    /// Remove an attachment, by reference or {item_key}/{attachment_key}
+    Detach { item_reference: ItemReference },
+
+    /// List previous values of a credential by reference or
+    /// {item_key}/{credential_key}
+    History { item_reference: ItemReference },
+
+    /// Restore a previous value of a credential by reference or
+    /// {item_key}/{credential_key}
+    Rollback {
+        item_reference: ItemReference,
+
+        /// Version to restore (see `ap item history`)
+        #[arg(long)]
+        version: u32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Transfer {
+    Copy,
+    Move { alias: bool },
 }
 
 impl ItemCommand {
@@ -87,10 +260,134 @@ impl ItemCommand {
             ItemSubcommand::Set {
                 item_reference,
                 secret_value,
+                kind,
+                expires,
             } => {
-                self.cmd_set_item(item_reference, secret_value.clone())
+                self.cmd_set_item(item_reference, secret_value.clone(), *kind, *expires)
                     .expect("Failed to set item");
             },
+            ItemSubcommand::ImportEnv {
+                path,
+                item,
+                rewrite,
+                force,
+            } => {
+                if let Err(e) = self.cmd_import_env(path, item, *rewrite, *force) {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            ItemSubcommand::Expiring { within, all_vaults } => {
+                if let Err(e) = self.cmd_expiring(*within, *all_vaults) {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            ItemSubcommand::Search { query } => {
+                if let Err(e) = self.cmd_search(&query.join(" ")) {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            ItemSubcommand::Tag {
+                item_reference,
+                tags,
+                remove,
+            } => {
+                if let Err(e) = self.cmd_tag_item(item_reference, tags, *remove) {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            ItemSubcommand::Rename {
+                item_reference,
+                new_key,
+                alias,
+            } => {
+                if let Err(e) = self.cmd_rename_item(item_reference, new_key, *alias) {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            ItemSubcommand::Move {
+                item_reference,
+                to_vault,
+                new_key,
+                alias,
+            } => {
+                let result = self.cmd_transfer_item(
+                    item_reference,
+                    Some(to_vault.clone()),
+                    new_key.clone(),
+                    Transfer::Move { alias: *alias },
+                );
+                if let Err(e) = result {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            ItemSubcommand::Copy {
+                item_reference,
+                to_vault,
+                new_key,
+            } => {
+                let result = self.cmd_transfer_item(
+                    item_reference,
+                    to_vault.clone(),
+                    new_key.clone(),
+                    Transfer::Copy,
+                );
+                if let Err(e) = result {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            ItemSubcommand::Attach {
+                item_reference,
+                path,
+                sidecar,
+                inline,
+            } => {
+                let sidecar = match (*sidecar, *inline) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                };
+                if let Err(e) = self.cmd_attach(item_reference, path, sidecar) {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            ItemSubcommand::Extract {
+                item_reference,
+                output,
+            } => {
+                if let Err(e) = self.cmd_extract(item_reference, output.as_deref()) {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            ItemSubcommand::Detach { item_reference } => {
+                if let Err(e) = self.cmd_detach(item_reference) {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            ItemSubcommand::History { item_reference } => {
+                if let Err(e) = self.cmd_item_history(item_reference) {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            ItemSubcommand::Rollback {
+                item_reference,
+                version,
+            } => {
+                if let Err(e) = self.cmd_rollback_item(item_reference, *version) {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
         }
     }
 
@@ -102,6 +399,32 @@ impl ItemCommand {
         Ok(vw)
     }
 
+    // like unlock_vault, but holds the vault file lock until the vault is saved
+    fn unlock_vault_for_update(vault_key: Option<String>) -> Result<VaultWrapper, String> {
+        let mut vw = VaultWrapper::load(&vaults_dir(), vault_key)
+            .map_err(|e| format!("Failed to load vault: {e}"))?;
+        vw.unlock_for_update()
+            .map_err(|e| format!("Failed to unlock vault: {e}"))?;
+        Ok(vw)
+    }
+
+    // unlocks every vault, skipping (with a warning) vaults that can't be
+    // unlocked so they don't hide results from the others
+    fn unlock_all_vaults() -> VaultsManager {
+        let mut vm = VaultsManager::new();
+        let mut vault_keys = vm.iter_vault_keys().collect::<Vec<_>>();
+        vault_keys.sort();
+        for vault_key in vault_keys {
+            let Some(vw) = vm.get_vault_mut(&vault_key) else {
+                continue;
+            };
+            if let Err(e) = vw.unlock() {
+                ceprintln!("<yellow>Skipping vault {vault_key}:</yellow> {e}");
+            }
+        }
+        vm
+    }
+
     fn cmd_get_item(&self, item_reference: &ItemReference) -> Result<(), String> {
         let item_reference = item_reference.clone();
         let vw = Self::unlock_vault(item_reference.vault.or_else(|| self.vault.clone()))?;
@@ -122,8 +445,16 @@ impl ItemCommand {
                 }
                 for (cred_key, cred) in &item.credentials {
                     cprintln!(
-                        "{} {cred_key} <dim>axo://{vault_key}/{item_key}/{cred_key}</dim>",
+                        "{} {cred_key} <dim>({}) axo://{vault_key}/{item_key}/{cred_key}</dim>",
                         cred.title,
+                        cred.kind,
+                    );
+                }
+                for attachment in item.attachments.values() {
+                    cprintln!(
+                        "{} <dim>(attachment) axo://{vault_key}/{item_key}/{}?file</dim>",
+                        attachment.file_name,
+                        attachment.key,
                     );
                 }
             },
@@ -138,9 +469,18 @@ impl ItemCommand {
                 };
                 cprintln!("<green>Credential</green>: {}", credential_key);
                 cprintln!("<green>Title</green>: {}", credential.title);
+                cprintln!("<green>Kind</green>: {}", credential.kind);
                 cprintln!(
                     "<green>Reference</green>: axo://{vault_key}/{item_key}/{credential_key}"
                 );
+                // concealed values are only printed by `ap item read`
+                if !credential.kind.is_concealed()
+                    && let Some(value) = vw
+                        .get_secret(&item_key, &credential_key)
+                        .map_err(|e| format!("Failed to get secret: {e}"))?
+                {
+                    cprintln!("<green>Value</green>: {}", value.expose_secret());
+                }
             },
         }
         Ok(())
@@ -151,17 +491,25 @@ impl ItemCommand {
     }
 
     pub fn cmd_read(item_reference: &ItemReference, vault: Option<String>) -> Result<(), String> {
-        let item_reference = item_reference.clone();
-        let vw = Self::unlock_vault(item_reference.vault.or(vault))?;
-        let item_key = item_reference.item;
-
-        let Some(credential_key) = item_reference.credential else {
+        if item_reference.credential.is_none() {
             return Err("Credential key must be specified".to_string());
+        }
+        // resolved like references in ap exec and ap inject, so that query
+        // modifiers apply
+        let reference = ItemReference {
+            vault: Some(
+                item_reference
+                    .vault
+                    .clone()
+                    .or(vault)
+                    .unwrap_or_else(|| DEFAULT_VAULT.to_string()),
+            ),
+            ..item_reference.clone()
         };
-
-        match vw.get_secret(&item_key, &credential_key) {
+        let mut vm = VaultsManager::new();
+        match vm.get_secret_by_reference(&reference) {
             Ok(Some(secret)) => {
-                println!("{}", secret.expose_secret());
+                println!("{secret}");
             },
             Ok(None) => {
                 // no-op
@@ -173,6 +521,131 @@ impl ItemCommand {
         Ok(())
     }
 
+    pub fn cmd_totp(
+        item_reference: &ItemReference,
+        vault: Option<String>,
+        show_remaining: bool,
+    ) -> Result<(), String> {
+        let item_reference = item_reference.clone();
+        let mut vw = Self::unlock_vault_for_update(item_reference.vault.or(vault))?;
+        let item_key = item_reference.item;
+
+        let Some(credential_key) = item_reference.credential else {
+            return Err("Credential key must be specified".to_string());
+        };
+
+        let otp = vw
+            .generate_otp(&item_key, &credential_key)
+            .map_err(|e| format!("Failed to generate code: {e}"))?;
+        match otp.seconds_remaining {
+            Some(remaining) if show_remaining => {
+                println!("{}", otp.code);
+                ceprintln!("<dim>expires in {remaining}s</dim>");
+            },
+            Some(_) => println!("{}", otp.code),
+            None => {
+                // HOTP: the counter was advanced and must be saved before the code is used
+                vw.save()
+                    .map_err(|e| format!("Failed to save vault: {e}"))?;
+                println!("{}", otp.code);
+            },
+        }
+        Ok(())
+    }
+
+    fn cmd_attach(
+        &self,
+        item_reference: &ItemReference,
+        path: &Path,
+        sidecar: Option<bool>,
+    ) -> Result<(), String> {
+        let item_reference = item_reference.clone();
+        let Some(attachment_key) = item_reference.credential else {
+            return Err("Attachment key must be specified".to_string());
+        };
+        let data = SecretBox::new(Box::new(
+            fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?,
+        ));
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| attachment_key.clone());
+        let sidecar = sidecar.unwrap_or(data.expose_secret().len() > SIDECAR_ATTACHMENT_THRESHOLD);
+
+        let mut vw =
+            Self::unlock_vault_for_update(item_reference.vault.or_else(|| self.vault.clone()))?;
+        let item_key = item_reference.item;
+        vw.add_attachment(
+            &item_key,
+            &attachment_key,
+            &file_name,
+            data.expose_secret(),
+            sidecar,
+        )
+        .map_err(|e| format!("Failed to add attachment: {e}"))?;
+        vw.save()
+            .map_err(|e| format!("Failed to save vault: {e}"))?;
+
+        println!(
+            "Added attachment: axo://{}/{item_key}/{attachment_key}",
+            vw.key
+        );
+        Ok(())
+    }
+
+    fn cmd_extract(
+        &self,
+        item_reference: &ItemReference,
+        output: Option<&Path>,
+    ) -> Result<(), String> {
+        let item_reference = item_reference.clone();
+        let Some(attachment_key) = item_reference.credential else {
+            return Err("Attachment key must be specified".to_string());
+        };
+        let vw = Self::unlock_vault(item_reference.vault.or_else(|| self.vault.clone()))?;
+        let data = vw
+            .get_attachment(&item_reference.item, &attachment_key)
+            .map_err(|e| format!("Failed to get attachment: {e}"))?;
+
+        match output {
+            Some(path) => {
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(path)
+                    .map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+                file.write_all(data.expose_secret())
+                    .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+            },
+            None => {
+                std::io::stdout()
+                    .write_all(data.expose_secret())
+                    .map_err(|e| format!("Failed to write attachment: {e}"))?;
+            },
+        }
+        Ok(())
+    }
+
+    fn cmd_detach(&self, item_reference: &ItemReference) -> Result<(), String> {
+        let item_reference = item_reference.clone();
+        let Some(attachment_key) = item_reference.credential else {
+            return Err("Attachment key must be specified".to_string());
+        };
+        let mut vw =
+            Self::unlock_vault_for_update(item_reference.vault.or_else(|| self.vault.clone()))?;
+        let item_key = item_reference.item;
+        vw.delete_attachment(&item_key, &attachment_key)
+            .map_err(|e| format!("Failed to remove attachment: {e}"))?;
+        vw.save()
+            .map_err(|e| format!("Failed to save vault: {e}"))?;
+        println!(
+            "Removed attachment: axo://{}/{item_key}/{attachment_key}",
+            vw.key
+        );
+        Ok(())
+    }
+
     fn cmd_list_items(&self) -> Result<(), String> {
         let vw = Self::unlock_vault(self.vault.clone())?;
         let vault_key = vw.key.clone();
@@ -199,6 +672,17 @@ impl ItemCommand {
             println!("<no items>");
         }
 
+        let aliases = vw
+            .list_item_aliases()
+            .map_err(|e| format!("Failed to list aliases: {e}"))?;
+        for alias in aliases {
+            cprintln!(
+                "  <dim>axo://{vault_key}/{} -> {}</dim>",
+                alias.key,
+                alias.target
+            );
+        }
+
         Ok(())
     }
 
@@ -206,14 +690,19 @@ impl ItemCommand {
         &self,
         item_reference: &ItemReference,
         secret_value: Option<SecretString>,
+        kind: Option<CredentialKind>,
+        expires: Option<Expiry>,
     ) -> Result<(), String> {
         let item_reference = item_reference.clone();
-        let mut vw = Self::unlock_vault(item_reference.vault.or_else(|| self.vault.clone()))?;
-
         let item_key = item_reference.item;
         let Some(credential_key) = item_reference.credential else {
             return Err("Credential key must be specified".to_string());
         };
+        let mut vw = VaultWrapper::load(
+            &vaults_dir(),
+            item_reference.vault.or_else(|| self.vault.clone()),
+        )
+        .map_err(|e| format!("Failed to load vault: {e}"))?;
 
         let secret = match secret_value {
             Some(value) => value,
@@ -232,8 +721,15 @@ impl ItemCommand {
             },
         };
 
-        vw.add_secret(&item_key, &credential_key, &credential_key, secret)
-            .expect("Failed to add secret");
+        // locked after prompting, so other commands aren't blocked meanwhile
+        vw.unlock_for_update()
+            .map_err(|e| format!("Failed to unlock vault: {e}"))?;
+        vw.add_secret(&item_key, &credential_key, &credential_key, kind, secret)
+            .map_err(|e| format!("Failed to add secret: {e}"))?;
+        if let Some(Expiry(expires_at)) = expires {
+            vw.set_secret_expiry(&item_key, &credential_key, expires_at)
+                .map_err(|e| format!("Failed to set expiry: {e}"))?;
+        }
 
         vw.save().expect("Failed to save vault");
 
@@ -244,39 +740,444 @@ impl ItemCommand {
 
         Ok(())
     }
+
+    fn cmd_import_env(
+        &self,
+        path: &Path,
+        item_key: &str,
+        rewrite: bool,
+        force: bool,
+    ) -> Result<(), String> {
+        let display_path = path.display();
+        let vars = dotenvy::from_path_iter(path)
+            .and_then(|vars| vars.collect::<Result<Vec<(String, String)>, _>>())
+            .map_err(|e| format!("Failed to read env file {display_path}: {e}"))?;
+        // a variable assigned more than once takes its last value, as in ap exec
+        let mut values: Vec<(String, String)> = Vec::new();
+        for (name, value) in vars {
+            match values.iter_mut().find(|(existing, _)| *existing == name) {
+                Some(existing) => existing.1 = value,
+                None => values.push((name, value)),
+            }
+        }
+
+        let mut vw = Self::unlock_vault_for_update(self.vault.clone())?;
+        let (item_title, existing_keys) = match vw
+            .get_item_overview(item_key)
+            .map_err(|e| format!("Failed to get item: {e}"))?
+        {
+            Some(item) => (
+                item.title.clone(),
+                item.credentials
+                    .values()
+                    .map(|cred| cred.key.clone())
+                    .collect::<HashSet<_>>(),
+            ),
+            None => (item_key.to_string(), HashSet::new()),
+        };
+        let item_key = vw
+            .add_item(item_key, &item_title)
+            .map_err(|e| format!("Failed to add item: {e}"))?
+            .key
+            .clone();
+
+        // credential keys are derived from variable names, e.g. DATABASE_URL is
+        // stored as database_url and titled DATABASE_URL
+        let mut cred_keys = HashSet::new();
+        let mut imports = Vec::new();
+        for (name, value) in values {
+            if value.trim_start().starts_with("axo://") {
+                cprintln!("  <dim>{name} is already a reference</dim>");
+                continue;
+            }
+            if value.is_empty() {
+                cprintln!("  <dim>{name} is empty</dim>");
+                continue;
+            }
+            let cred_key = unique_key(derived_key(&name, "env"), &mut cred_keys);
+            imports.push((name, cred_key, value));
+        }
+
+        // check before adding anything, so a refused import changes nothing
+        let conflicts = imports
+            .iter()
+            .filter(|(_, cred_key, _)| existing_keys.contains(cred_key))
+            .map(|(name, _, _)| name.as_str())
+            .collect::<Vec<_>>();
+        if !conflicts.is_empty() && !force {
+            return Err(format!(
+                "Item {item_key} already has credentials for {} (use --force to overwrite them)",
+                conflicts.join(", ")
+            ));
+        }
+
+        let mut references = HashMap::new();
+        for (name, cred_key, value) in imports {
+            vw.add_secret(&item_key, &cred_key, &name, None, value.into())
+                .map_err(|e| format!("Failed to add {name}: {e}"))?;
+            let reference = format!("axo://{}/{item_key}/{cred_key}", vw.key);
+            cprintln!("  {name} <dim>-></dim> <blue>{reference}</blue>");
+            references.insert(name, reference);
+        }
+        if references.is_empty() {
+            cprintln!("No values to import from {display_path}");
+            return Ok(());
+        }
+        vw.save()
+            .map_err(|e| format!("Failed to save vault: {e}"))?;
+        cprintln!(
+            "Imported {} values into <blue>axo://{}/{item_key}</blue>",
+            references.len(),
+            vw.key
+        );
+
+        // only rewrite the file once the values are safely stored in the vault
+        if rewrite {
+            let contents = fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read env file {display_path}: {e}"))?;
+            write_atomic(path, replace_env_values(&contents, &references).as_bytes())
+                .map_err(|e| format!("Failed to write env file {display_path}: {e}"))?;
+            cprintln!("Replaced the values in {display_path} with references");
+        }
+        Ok(())
+    }
+
+    fn cmd_expiring(&self, within: Duration, all_vaults: bool) -> Result<(), String> {
+        let vm;
+        let selected_vault;
+        let vaults: Vec<&VaultWrapper> = if all_vaults {
+            vm = Self::unlock_all_vaults();
+            vm.iter_vaults()
+                .map(|(_, vw)| vw)
+                .filter(|vw| vw.is_unlocked())
+                .collect()
+        } else {
+            selected_vault = Self::unlock_vault(self.vault.clone())?;
+            vec![&selected_vault]
+        };
+
+        let now = OffsetDateTime::now_utc();
+        let mut expiring = Vec::new();
+        for vw in vaults {
+            let items = vw
+                .list_items()
+                .map_err(|e| format!("Failed to list items: {e}"))?;
+            for item in items {
+                for cred in item.credentials.values() {
+                    if let Some(expires_at) = cred.expires_at
+                        && expires_at - now <= within
+                    {
+                        let url = format!("axo://{}/{}/{}", vw.key, item.key, cred.key);
+                        expiring.push((expires_at, url));
+                    }
+                }
+            }
+        }
+        expiring.sort();
+
+        if expiring.is_empty() {
+            cprintln!("<dim><<no expiring credentials>></dim>");
+        }
+        for (expires_at, url) in expiring {
+            let date = expires_at.date();
+            if expires_at <= now {
+                cprintln!("<red>expired {date}</red>  {url}");
+            } else {
+                cprintln!("<yellow>expires {date}</yellow>  {url}");
+            }
+        }
+        Ok(())
+    }
+
+    fn cmd_search(&self, query: &str) -> Result<(), String> {
+        let vm = Self::unlock_all_vaults();
+        let matches = vm.search(query);
+        if matches.is_empty() {
+            return Err(format!("No credentials match {query:?}"));
+        }
+        for m in matches {
+            let tags = m
+                .tags
+                .iter()
+                .map(|tag| format!(" #{tag}"))
+                .collect::<String>();
+            cprintln!(
+                "{} / {} <dim>{}{tags}</dim>",
+                m.item_title,
+                m.cred_title,
+                m.url()
+            );
+        }
+        Ok(())
+    }
+
+    fn cmd_tag_item(
+        &self,
+        item_reference: &ItemReference,
+        tags: &[String],
+        remove: bool,
+    ) -> Result<(), String> {
+        let item_reference = item_reference.clone();
+        let mut vw =
+            Self::unlock_vault_for_update(item_reference.vault.or_else(|| self.vault.clone()))?;
+        let item_key = item_reference.item;
+        let Some(item) = vw
+            .get_item_overview(&item_key)
+            .map_err(|e| format!("Failed to get item: {e}"))?
+        else {
+            return Err(cformat!(
+                "<blue>{item_key}</blue> not found in vault <blue>{}</blue>",
+                vw.key
+            ));
+        };
+
+        let mut new_tags = item.tags.clone();
+        if remove {
+            new_tags.retain(|tag| !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)));
+        } else {
+            new_tags.extend(tags.iter().cloned());
+        }
+        vw.set_item_tags(&item_key, &new_tags)
+            .map_err(|e| format!("Failed to set tags: {e}"))?;
+        vw.save()
+            .map_err(|e| format!("Failed to save vault: {e}"))?;
+
+        let item = vw
+            .get_item_overview(&item_key)
+            .map_err(|e| format!("Failed to get item: {e}"))?
+            .ok_or_else(|| format!("Item not found: {item_key}"))?;
+        if item.tags.is_empty() {
+            cprintln!("<dim><<no tags>></dim>");
+        }
+        for tag in &item.tags {
+            println!("#{tag}");
+        }
+        Ok(())
+    }
+
+    fn cmd_rename_item(
+        &self,
+        item_reference: &ItemReference,
+        new_key: &str,
+        alias: bool,
+    ) -> Result<(), String> {
+        let item_reference = item_reference.clone();
+        let mut vw =
+            Self::unlock_vault_for_update(item_reference.vault.or_else(|| self.vault.clone()))?;
+        let vault_key = vw.key.clone();
+        let item_key = item_reference.item;
+
+        match item_reference.credential {
+            Some(credential_key) => {
+                if alias {
+                    return Err("Aliases can only be left for items".to_string());
+                }
+                vw.rename_item_credential(&item_key, &credential_key, new_key)
+                    .map_err(|e| format!("Failed to rename credential: {e}"))?;
+                vw.save()
+                    .map_err(|e| format!("Failed to save vault: {e}"))?;
+                cprintln!(
+                    "Renamed axo://{vault_key}/{item_key}/{credential_key} to <blue>axo://{vault_key}/{item_key}/{new_key}</blue>"
+                );
+            },
+            None => {
+                vw.rename_item(&item_key, new_key)
+                    .map_err(|e| format!("Failed to rename item: {e}"))?;
+                if alias {
+                    vw.add_item_alias(&item_key, &format!("axo://{vault_key}/{new_key}"))
+                        .map_err(|e| format!("Failed to add alias: {e}"))?;
+                }
+                vw.save()
+                    .map_err(|e| format!("Failed to save vault: {e}"))?;
+                cprintln!(
+                    "Renamed axo://{vault_key}/{item_key} to <blue>axo://{vault_key}/{new_key}</blue>"
+                );
+            },
+        }
+        Ok(())
+    }
+
+    // copy or move an item, re-encrypting it for the target vault (see
+    // Vault::import_item)
+    fn cmd_transfer_item(
+        &self,
+        item_reference: &ItemReference,
+        to_vault: Option<String>,
+        new_key: Option<String>,
+        transfer: Transfer,
+    ) -> Result<(), String> {
+        let item_reference = item_reference.clone();
+        if item_reference.credential.is_some() {
+            return Err("Reference an item, not a credential, to copy or move it".to_string());
+        }
+        let mut source = VaultWrapper::load(
+            &vaults_dir(),
+            item_reference.vault.or_else(|| self.vault.clone()),
+        )
+        .map_err(|e| format!("Failed to load vault: {e}"))?;
+        let source_key = source.key.clone();
+        let target_key = to_vault.unwrap_or_else(|| source_key.clone());
+        let mut target = match target_key == source_key {
+            true => None,
+            false => Some(
+                VaultWrapper::load(&vaults_dir(), Some(target_key.clone()))
+                    .map_err(|e| format!("Failed to load vault {target_key}: {e}"))?,
+            ),
+        };
+        // lock in a fixed order, so that transfers in opposite directions
+        // can't deadlock waiting for each other's vault
+        let mut locking = std::iter::once(&mut source)
+            .chain(target.as_mut())
+            .collect::<Vec<_>>();
+        locking.sort_by(|a, b| a.key.cmp(&b.key));
+        for vw in locking {
+            vw.unlock_for_update()
+                .map_err(|e| format!("Failed to unlock vault {}: {e}", vw.key))?;
+        }
+        let item_key = item_reference.item;
+        let new_key = new_key.unwrap_or_else(|| item_key.clone());
+        let from_url = format!("axo://{source_key}/{item_key}");
+        let to_url = format!("axo://{target_key}/{new_key}");
+
+        let item = source
+            .export_item(&item_key)
+            .map_err(|e| format!("Failed to read item: {e}"))?;
+
+        match target.as_mut() {
+            // the vault file is already locked, so update it in place
+            None => {
+                match transfer {
+                    Transfer::Copy => source.import_item(&new_key, item),
+                    Transfer::Move { alias } => {
+                        source
+                            .rename_item(&item_key, &new_key)
+                            .and_then(|_| match alias {
+                                true => source.add_item_alias(&item_key, &to_url),
+                                false => Ok(()),
+                            })
+                    },
+                }
+                .map_err(|e| format!("Failed to update item: {e}"))?;
+            },
+            Some(target) => {
+                target
+                    .import_item(&new_key, item)
+                    .map_err(|e| format!("Failed to add item to {target_key}: {e}"))?;
+                // save the target first, so a failure can't lose the item
+                target
+                    .save()
+                    .map_err(|e| format!("Failed to save vault {target_key}: {e}"))?;
+
+                if let Transfer::Move { alias } = transfer {
+                    source
+                        .delete_item(&item_key)
+                        .map_err(|e| format!("Failed to remove item: {e}"))?;
+                    if alias {
+                        source
+                            .add_item_alias(&item_key, &to_url)
+                            .map_err(|e| format!("Failed to add alias: {e}"))?;
+                    }
+                }
+            },
+        }
+        source
+            .save()
+            .map_err(|e| format!("Failed to save vault {source_key}: {e}"))?;
+
+        let action = match transfer {
+            Transfer::Copy => "Copied",
+            Transfer::Move { .. } => "Moved",
+        };
+        cprintln!("{action} {from_url} to <blue>{to_url}</blue>");
+        Ok(())
+    }
+
+    fn cmd_item_history(&self, item_reference: &ItemReference) -> Result<(), String> {
+        let item_reference = item_reference.clone();
+        let vw = Self::unlock_vault(item_reference.vault.or_else(|| self.vault.clone()))?;
+
+        let item_key = item_reference.item;
+        let Some(credential_key) = item_reference.credential else {
+            return Err("Credential key must be specified".to_string());
+        };
+
+        let history = vw
+            .get_secret_history(&item_key, &credential_key)
+            .map_err(|e| format!("Failed to get history: {e}"))?;
+        cprintln!(
+            "<green>Reference</green>: axo://{}/{item_key}/{credential_key}",
+            vw.key
+        );
+        if history.is_empty() {
+            cprintln!("<dim><<no history>></dim>");
+        }
+        for entry in history.iter().rev() {
+            let replaced_at = entry
+                .replaced_at
+                .format(&Rfc3339)
+                .unwrap_or_else(|_| entry.replaced_at.to_string());
+            cprintln!(
+                "  version {} <dim>replaced at {replaced_at}</dim>",
+                entry.version
+            );
+        }
+        Ok(())
+    }
+
+    fn cmd_rollback_item(
+        &self,
+        item_reference: &ItemReference,
+        version: u32,
+    ) -> Result<(), String> {
+        let item_reference = item_reference.clone();
+        let mut vw =
+            Self::unlock_vault_for_update(item_reference.vault.or_else(|| self.vault.clone()))?;
+
+        let item_key = item_reference.item;
+        let Some(credential_key) = item_reference.credential else {
+            return Err("Credential key must be specified".to_string());
+        };
+
+        vw.rollback_secret(&item_key, &credential_key, version)
+            .map_err(|e| format!("Failed to roll back secret: {e}"))?;
+        vw.save()
+            .map_err(|e| format!("Failed to save vault: {e}"))?;
+
+        println!(
+            "Restored version {version} of axo://{}/{}/{}",
+            vw.key, item_key, credential_key
+        );
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     #[test]
-    fn test_item_reference_from_str() {
-        let test_cases = vec![
-            (
-                "axo://my_vault/my_item/my_credential",
-                ItemReference {
-                    vault: Some("my_vault".to_string()),
-                    item: "my_item".to_string(),
-                    credential: Some("my_credential".to_string()),
-                },
-            ),
-            (
-                "item456/cred789",
-                ItemReference {
-                    vault: None,
-                    item: "item456".to_string(),
-                    credential: Some("cred789".to_string()),
-                },
-            ),
-        ];
-
-        for (reference_str, expected) in test_cases {
-            let item_ref =
-                ItemReference::from_str(reference_str).expect("Failed to parse item reference");
-            assert_eq!(
-                item_ref, expected,
-                "Failed parsing reference: {reference_str}",
-            );
-        }
+    fn test_expiry_from_str() {
+        assert_eq!(Expiry::from_str("never"), Ok(Expiry(None)));
+        assert_eq!(
+            Expiry::from_str("2030-01-02"),
+            Ok(Expiry(Some(
+                OffsetDateTime::parse("2030-01-02T00:00:00Z", &Rfc3339).unwrap()
+            )))
+        );
+        assert_eq!(
+            Expiry::from_str("2030-01-02T03:04:05+01:00"),
+            Ok(Expiry(Some(
+                OffsetDateTime::parse("2030-01-02T02:04:05Z", &Rfc3339).unwrap()
+            )))
+        );
+        let Ok(Expiry(Some(expires_at))) = Expiry::from_str("90d") else {
+            panic!("relative expiry should parse");
+        };
+        assert!(expires_at > OffsetDateTime::now_utc() + Duration::days(89));
+        assert!(Expiry::from_str("soon").is_err());
+
+        assert_eq!(parse_duration("12h"), Ok(Duration::hours(12)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::weeks(2)));
+        assert!(parse_duration("14").is_err());
+        assert!(parse_duration("d").is_err());
     }
 }
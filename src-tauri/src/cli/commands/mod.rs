@@ -1,5 +1,7 @@
 pub mod age;
 pub mod exec;
+pub mod file;
+pub mod generate;
 pub mod inject;
 pub mod item;
 pub mod keychain;
@@ -1,5 +1,10 @@
+mod diff;
 mod export;
 mod import;
+mod import_from;
+mod member;
+mod render;
+mod rotate_key;
 mod utils;
 
 use std::path::PathBuf;
@@ -7,8 +12,13 @@ use std::path::PathBuf;
 use clap::{Parser, Subcommand};
 use color_print::cprintln;
 
+use crate::cli::commands::vault::diff::VaultDiffCommand;
 use crate::cli::commands::vault::export::VaultExportCommand;
 use crate::cli::commands::vault::import::VaultImportCommand;
+use crate::cli::commands::vault::import_from::VaultImportFromCommand;
+use crate::cli::commands::vault::member::VaultMemberCommand;
+use crate::cli::commands::vault::render::VaultRenderCommand;
+use crate::cli::commands::vault::rotate_key::VaultRotateKeyCommand;
 use crate::core::config::APP_CONFIG;
 use crate::secrets::vaults::{VaultWrapper, VaultsManager};
 
@@ -32,6 +42,23 @@ enum VaultSubcommand {
 
     /// Import a vault from an export file
     Import(VaultImportCommand),
+
+    /// Import items from a Bitwarden, 1Password, KeePass or CSV export
+    ImportFrom(VaultImportFromCommand),
+
+    /// Write credentials in plaintext as dotenv, JSON, YAML or a Kubernetes
+    /// Secret
+    Render(VaultRenderCommand),
+
+    /// Show items and credentials that differ between two vaults or export
+    /// files, without printing secrets
+    Diff(VaultDiffCommand),
+
+    /// Commands for sharing a vault with other members
+    Member(VaultMemberCommand),
+
+    /// Generate a new file key and re-encrypt the vault with it
+    RotateKey(VaultRotateKeyCommand),
 }
 
 impl VaultCommand {
@@ -56,6 +83,36 @@ impl VaultCommand {
                     std::process::exit(1);
                 }
             },
+            VaultSubcommand::ImportFrom(vault_import_from_cmd) => {
+                if let Err(e) = vault_import_from_cmd.execute() {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            VaultSubcommand::Render(vault_render_cmd) => {
+                if let Err(e) = vault_render_cmd.execute() {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            VaultSubcommand::Diff(vault_diff_cmd) => {
+                if let Err(e) = vault_diff_cmd.execute() {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            VaultSubcommand::Member(vault_member_cmd) => {
+                if let Err(e) = vault_member_cmd.execute() {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            VaultSubcommand::RotateKey(vault_rotate_key_cmd) => {
+                if let Err(e) = vault_rotate_key_cmd.execute() {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
         }
         std::process::exit(0);
     }
@@ -0,0 +1,114 @@
+use std::path::Path;
+
+use clap::{Parser, ValueHint};
+use color_print::cprintln;
+
+use crate::cli::commands::vault::import::ImportIdentityFlags;
+use crate::secrets::vaults::diff::{VaultChange, VaultSnapshot, diff_vaults};
+use crate::secrets::vaults::vault_export::{is_export_file, open_export};
+use crate::secrets::vaults::{VaultWrapper, VaultsManager};
+
+#[derive(Parser, Debug)]
+pub struct VaultDiffCommand {
+    /// Vault key, vault file or export file to compare from
+    #[arg(value_hint = ValueHint::FilePath)]
+    a: String,
+
+    /// Vault key, vault file or export file to compare to
+    #[arg(value_hint = ValueHint::FilePath)]
+    b: String,
+
+    // to decrypt export files
+    #[command(flatten)]
+    import_encryption: ImportIdentityFlags,
+}
+
+impl VaultDiffCommand {
+    pub fn execute(&self) -> Result<(), String> {
+        let vm = VaultsManager::new();
+        let a = self.snapshot(&vm, &self.a)?;
+        let b = self.snapshot(&vm, &self.b)?;
+
+        let changes = diff_vaults(&a, &b);
+        for change in &changes {
+            print_change(change);
+        }
+        match changes.len() {
+            0 => cprintln!(
+                "No differences between <blue>{}</blue> and <blue>{}</blue>",
+                self.a,
+                self.b
+            ),
+            n => cprintln!(
+                "{n} differences between <blue>{}</blue> and <blue>{}</blue>",
+                self.a,
+                self.b
+            ),
+        }
+        Ok(())
+    }
+
+    // a vault key takes precedence over a file with the same name
+    fn snapshot(&self, vm: &VaultsManager, vault: &str) -> Result<VaultSnapshot, String> {
+        let vw = match vm.get_vault(vault) {
+            Some(vw) => self.unlock(Some(vw.key.clone()), &vw.path)?,
+            None => {
+                let path = Path::new(vault);
+                if !path.is_file() {
+                    return Err(format!("Vault not found: {vault}"));
+                }
+                if is_export_file(path) {
+                    let identity = (&self.import_encryption).try_into()?;
+                    open_export(path, &identity)
+                        .map_err(|e| format!("Failed to open export {vault}: {e}"))?
+                } else {
+                    self.unlock(None, path)?
+                }
+            },
+        };
+        VaultSnapshot::from_vault(&vw).map_err(|e| format!("Failed to read vault {vault}: {e}"))
+    }
+
+    fn unlock(&self, vault_key: Option<String>, path: &Path) -> Result<VaultWrapper, String> {
+        let mut vw = VaultWrapper::load_from_path(vault_key, path)
+            .map_err(|e| format!("Not a valid vault file: {e}"))?;
+        vw.unlock()
+            .map_err(|e| format!("Failed to unlock vault {}: {e}", vw.key))?;
+        Ok(vw)
+    }
+}
+
+fn print_change(change: &VaultChange) {
+    match change {
+        VaultChange::ItemAdded { id, key } => {
+            cprintln!("<green>+ item {key}</green> <dim>{id}</dim>")
+        },
+        VaultChange::ItemRemoved { id, key } => {
+            cprintln!("<red>- item {key}</red> <dim>{id}</dim>")
+        },
+        VaultChange::ItemRenamed {
+            id,
+            old_key,
+            new_key,
+        } => cprintln!("<yellow>~ item {old_key} -> {new_key}</yellow> <dim>{id}</dim>"),
+        VaultChange::CredentialAdded { item_key, id, key } => {
+            cprintln!("<green>+ credential {item_key}/{key}</green> <dim>{id}</dim>")
+        },
+        VaultChange::CredentialRemoved { item_key, id, key } => {
+            cprintln!("<red>- credential {item_key}/{key}</red> <dim>{id}</dim>")
+        },
+        VaultChange::CredentialRenamed {
+            item_key,
+            id,
+            old_key,
+            new_key,
+        } => cprintln!(
+            "<yellow>~ credential {item_key}/{old_key} -> {item_key}/{new_key}</yellow> <dim>{id}</dim>"
+        ),
+        VaultChange::CredentialChanged { item_key, id, key } => {
+            cprintln!(
+                "<yellow>~ credential {item_key}/{key}: value changed</yellow> <dim>{id}</dim>"
+            )
+        },
+    }
+}
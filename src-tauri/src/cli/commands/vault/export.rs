@@ -1,12 +1,10 @@
-use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use clap::{Parser, ValueHint};
 use color_print::cprintln;
-use inquire::Select;
 
 use crate::age::recipients::resolve_recipient;
-use crate::cli::commands::vault::utils::prompt_passphrase;
+use crate::cli::commands::vault::utils::{prompt_passphrase, select_vault};
 use crate::secrets::vaults::VaultsManager;
 use crate::secrets::vaults::vault_export::ExportMode;
 
@@ -103,19 +101,3 @@ impl TryInto<ExportMode> for &ExportModeFlags {
         Ok(ExportMode::Passphrase(passphrase))
     }
 }
-
-fn select_vault(vault_labels: BTreeMap<String, String>) -> Result<String, String> {
-    if vault_labels.is_empty() {
-        return Err("No vaults found".to_string());
-    }
-    let labels = vault_labels.keys().cloned().collect::<Vec<_>>();
-    let selected = Select::new("Select a vault:", labels)
-        .prompt()
-        .map_err(|e| format!("Vault selection cancelled: {e}"))?
-        .to_string();
-
-    vault_labels
-        .get(&selected)
-        .cloned()
-        .ok_or_else(|| "Failed to resolve selected vault".to_string())
-}
@@ -37,7 +37,7 @@ impl VaultImportCommand {
 
 #[derive(Parser, Debug, Default)]
 #[group(required = false, multiple = false)]
-struct ImportIdentityFlags {
+pub(super) struct ImportIdentityFlags {
     /// Decrypt with a passphrase (prompted if not given)
     #[arg(long)]
     passphrase: Option<String>,
@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum, ValueHint};
+use color_print::cprintln;
+
+use crate::cli::commands::vault::utils::select_vault;
+use crate::secrets::vaults::VaultsManager;
+use crate::secrets::vaults::importers::{CsvMapping, ImportFormat, plan_import, read_entries};
+
+#[derive(Parser, Debug)]
+pub struct VaultImportFromCommand {
+    /// Format of the export file
+    #[arg(value_enum)]
+    format: ImportFormatArg,
+
+    /// Path to the export file (unencrypted)
+    #[arg(value_hint = ValueHint::FilePath)]
+    path: PathBuf,
+
+    /// Vault key of vault to import into (will prompt if not given)
+    #[arg(long)]
+    vault: Option<String>,
+
+    /// Show what would be imported without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// CSV only: column holding the item title (default: title, name or item)
+    #[arg(long, value_name = "COLUMN")]
+    title_column: Option<String>,
+
+    /// CSV only: import COLUMN as credential KEY, can be repeated (default:
+    /// every column, keyed by its header)
+    #[arg(long = "map", value_name = "COLUMN=KEY", value_parser = parse_column_mapping)]
+    columns: Vec<(String, String)>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ImportFormatArg {
+    /// Bitwarden JSON export (unencrypted)
+    Bitwarden,
+    /// 1Password .1pux export
+    #[value(name = "1password")]
+    OnePassword,
+    /// KeePass 2 XML export
+    Keepass,
+    /// CSV with a header row
+    Csv,
+}
+
+fn parse_column_mapping(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((column, key)) if !column.trim().is_empty() && !key.trim().is_empty() => {
+            Ok((column.trim().to_string(), key.trim().to_string()))
+        },
+        _ => Err(format!("Invalid column mapping: {s} (expected COLUMN=KEY)")),
+    }
+}
+
+impl VaultImportFromCommand {
+    pub fn execute(&self) -> Result<(), String> {
+        let format = match self.format {
+            ImportFormatArg::Bitwarden => ImportFormat::Bitwarden,
+            ImportFormatArg::OnePassword => ImportFormat::OnePassword,
+            ImportFormatArg::Keepass => ImportFormat::KeePass,
+            ImportFormatArg::Csv => ImportFormat::Csv(CsvMapping {
+                title_column: self.title_column.clone(),
+                columns: self.columns.clone(),
+            }),
+        };
+        let entries = read_entries(&format, &self.path)
+            .map_err(|e| format!("Failed to read {}: {e}", self.path.display()))?;
+
+        let mut vm = VaultsManager::new();
+        let vault_key = match self.vault {
+            Some(ref k) => k.to_string(),
+            None => select_vault(vm.vault_labels())?,
+        };
+        let vw = vm
+            .get_vault_mut(&vault_key)
+            .ok_or_else(|| format!("Vault not found: {vault_key}"))?;
+        if self.dry_run {
+            vw.unlock()
+        } else {
+            vw.unlock_for_update()
+        }
+        .map_err(|e| format!("Failed to unlock vault: {e}"))?;
+
+        // existing items and aliases are never overwritten
+        let mut existing_keys = vw
+            .list_items()
+            .map_err(|e| format!("Failed to list items: {e}"))?
+            .into_iter()
+            .map(|item| item.key.clone())
+            .collect::<Vec<_>>();
+        existing_keys.extend(
+            vw.list_item_aliases()
+                .map_err(|e| format!("Failed to list aliases: {e}"))?
+                .into_iter()
+                .map(|alias| alias.key.clone()),
+        );
+        let plan = plan_import(entries, existing_keys);
+        let items = plan.items;
+
+        for imported in &items {
+            let item = &imported.item;
+            if item.tags.is_empty() {
+                cprintln!("  <blue>{}</blue> {}", item.key, item.title);
+            } else {
+                let tags = item.tags.iter().map(|tag| format!("#{tag}"));
+                cprintln!(
+                    "  <blue>{}</blue> {} <dim>{}</dim>",
+                    item.key,
+                    item.title,
+                    tags.collect::<Vec<_>>().join(" ")
+                );
+            }
+            for cred in item.credentials.values() {
+                cprintln!("    {} <dim>({})</dim>", cred.key, cred.kind);
+            }
+        }
+        let num_credentials = items
+            .iter()
+            .map(|imported| imported.item.credentials.len())
+            .sum::<usize>();
+        cprintln!(
+            "<green>{} items</green> with {num_credentials} credentials",
+            items.len()
+        );
+        if !plan.empty.is_empty() {
+            let skipped = plan.empty.len();
+            cprintln!("<dim>Skipped {skipped} entries without any values</dim>");
+        }
+        if !plan.rejected.is_empty() {
+            let (fields, entries): (Vec<_>, Vec<_>) = plan
+                .rejected
+                .iter()
+                .partition(|rejected| rejected.field.is_some());
+            cprintln!(
+                "<yellow>Could not import {} entries and {} fields:</yellow>",
+                entries.len(),
+                fields.len()
+            );
+            for rejected in entries.iter().chain(&fields) {
+                match &rejected.field {
+                    Some(field) => println!("  {} ({field}): {}", rejected.title, rejected.reason),
+                    None => println!("  {}: {}", rejected.title, rejected.reason),
+                }
+            }
+        }
+        if self.dry_run {
+            cprintln!("<dim>Dry run, nothing was written to vault {vault_key}</dim>");
+            return Ok(());
+        }
+
+        let num_items = items.len();
+        vw.import_items(items)
+            .map_err(|e| format!("Failed to import items: {e}"))?;
+        vw.save()
+            .map_err(|e| format!("Failed to save vault: {e}"))?;
+        cprintln!("Imported {num_items} items into vault <blue>{vault_key}</blue>");
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, ValueHint};
+use color_print::cprintln;
+
+use crate::cli::commands::vault::utils::select_vault;
+use crate::secrets::vaults::{MemberKey, VaultsManager, create_local_member_key, local_member_key};
+
+#[derive(Parser, Debug)]
+#[command(flatten_help = true, help_template = "{usage-heading} {usage}")]
+pub struct VaultMemberCommand {
+    #[command(subcommand)]
+    subcommand: VaultMemberSubcommand,
+
+    /// Vault key of the vault (will prompt if not given)
+    #[arg(long, global = true)]
+    vault: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum VaultMemberSubcommand {
+    /// List members of the vault
+    List,
+
+    /// Add a member by public key ("ecdsa-sha2-nistp256 ..." or "age1...")
+    Add {
+        public_key: String,
+
+        /// Store members in a separate file next to the vault
+        #[arg(long, value_hint = ValueHint::FilePath)]
+        members_file: Option<PathBuf>,
+    },
+
+    /// Remove a member by public key and rotate the vault's file key
+    Remove { public_key: String },
+
+    /// Show your own public key, to share with other vault members
+    Key,
+}
+
+impl VaultMemberCommand {
+    pub fn execute(&self) -> Result<(), String> {
+        if let VaultMemberSubcommand::Key = &self.subcommand {
+            let member_key =
+                create_local_member_key().map_err(|e| format!("Failed to get vault key: {e}"))?;
+            println!("{member_key}");
+            return Ok(());
+        }
+
+        let mut vm = VaultsManager::new();
+        let vault_key = match self.vault {
+            Some(ref k) => k.to_string(),
+            None => select_vault(vm.vault_labels())?,
+        };
+
+        let vw = vm
+            .get_vault_mut(&vault_key)
+            .ok_or_else(|| format!("Vault not found: {vault_key}"))?;
+
+        vw.unlock_for_update()
+            .map_err(|e| format!("Failed to unlock vault: {e}"))?;
+
+        match &self.subcommand {
+            VaultMemberSubcommand::List => {
+                let members = vw
+                    .list_members()
+                    .map_err(|e| format!("Failed to list members: {e}"))?;
+                cprintln!("<green>Vault</green>: <blue>{vault_key}</blue>");
+                if members.is_empty() {
+                    println!("<personal vault, no members>");
+                }
+                let local_key = local_member_key().ok().map(|k| k.to_string());
+                for member in members {
+                    let kind = match member.member_key() {
+                        Ok(MemberKey::SecureEnclave(_)) => "secure enclave",
+                        Ok(MemberKey::Age(_)) => "age",
+                        Err(_) => "unknown",
+                    };
+                    if local_key.as_ref() == Some(&member.public_key) {
+                        cprintln!(
+                            "  {} <dim>{kind}</dim> <green>(you)</green>",
+                            member.public_key
+                        );
+                    } else {
+                        cprintln!("  {} <dim>{kind}</dim>", member.public_key);
+                    }
+                }
+                return Ok(());
+            },
+            VaultMemberSubcommand::Add {
+                public_key,
+                members_file,
+            } => {
+                vw.add_member(public_key, members_file.clone())
+                    .map_err(|e| format!("Failed to add member: {e}"))?;
+            },
+            VaultMemberSubcommand::Remove { public_key } => {
+                vw.remove_member(public_key)
+                    .map_err(|e| format!("Failed to remove member: {e}"))?;
+            },
+            VaultMemberSubcommand::Key => unreachable!(),
+        }
+
+        vw.save()
+            .map_err(|e| format!("Failed to save vault: {e}"))?;
+        cprintln!("Updated members of vault <blue>{vault_key}</blue>");
+        Ok(())
+    }
+}
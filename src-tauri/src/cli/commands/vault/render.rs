@@ -0,0 +1,142 @@
+use std::fs::OpenOptions;
+use std::io::{IsTerminal, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum, ValueHint};
+use color_print::ceprintln;
+use secrecy::ExposeSecret;
+
+use crate::cli::commands::vault::utils::select_vault;
+use crate::secrets::vaults::VaultsManager;
+use crate::secrets::vaults::render::{RenderFormat, RenderedItems, render};
+
+#[derive(Parser, Debug)]
+pub struct VaultRenderCommand {
+    /// Vault key of vault to render (will prompt if not given)
+    #[arg(long)]
+    vault: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum)]
+    format: RenderFormatArg,
+
+    /// Item to render, can be repeated (default: every item)
+    #[arg(long = "item", value_name = "ITEM")]
+    items: Vec<String>,
+
+    /// Name of the Kubernetes Secret (default: the vault key)
+    #[arg(long)]
+    name: Option<String>,
+
+    /// File to write to, created with 0600 permissions (default: stdout)
+    #[arg(long, short = 'o', value_hint = ValueHint::FilePath)]
+    output: Option<PathBuf>,
+
+    /// Print to a terminal without asking for confirmation
+    #[arg(long, short = 'y')]
+    yes: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum RenderFormatArg {
+    /// ITEM_CREDENTIAL=value lines
+    Dotenv,
+    /// {"item": {"credential": "value"}}
+    Json,
+    /// item: {credential: value}
+    Yaml,
+    /// Kubernetes Secret manifest
+    K8sSecret,
+}
+
+impl VaultRenderCommand {
+    pub fn execute(&self) -> Result<(), String> {
+        let mut vm = VaultsManager::new();
+        let vault_key = match self.vault {
+            Some(ref k) => k.to_string(),
+            None => select_vault(vm.vault_labels())?,
+        };
+        let vw = vm
+            .get_vault_mut(&vault_key)
+            .ok_or_else(|| format!("Vault not found: {vault_key}"))?;
+        vw.unlock()
+            .map_err(|e| format!("Failed to unlock vault: {e}"))?;
+
+        let items = if self.items.is_empty() {
+            vw.list_items()
+                .map_err(|e| format!("Failed to list items: {e}"))?
+        } else {
+            self.items
+                .iter()
+                .map(|item_key| match vw.get_item_overview(item_key) {
+                    Ok(Some(item)) => Ok(item),
+                    Ok(None) => Err(format!("Item not found: {item_key}")),
+                    Err(e) => Err(format!("Failed to get item {item_key}: {e}")),
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        let mut values = RenderedItems::new();
+        for item in items {
+            for cred in item.credentials.values() {
+                let value = vw
+                    .get_secret(&item.key, &cred.key)
+                    .map_err(|e| format!("Failed to read {}/{}: {e}", item.key, cred.key))?
+                    .ok_or_else(|| format!("Credential not found: {}/{}", item.key, cred.key))?;
+                values
+                    .entry(item.key.clone())
+                    .or_default()
+                    .insert(cred.key.clone(), value.expose_secret().as_str().into());
+            }
+        }
+        let num_credentials = values.values().map(|creds| creds.len()).sum::<usize>();
+
+        let format = match self.format {
+            RenderFormatArg::Dotenv => RenderFormat::Dotenv,
+            RenderFormatArg::Json => RenderFormat::Json,
+            RenderFormatArg::Yaml => RenderFormat::Yaml,
+            RenderFormatArg::K8sSecret => RenderFormat::K8sSecret,
+        };
+        // kubernetes names can't contain underscores
+        let name = self
+            .name
+            .clone()
+            .unwrap_or_else(|| vault_key.replace('_', "-"));
+        let rendered = render(format, &values, &name).map_err(|e| e.to_string())?;
+
+        match &self.output {
+            Some(path) => {
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(path)
+                    .map_err(|e| format!("Failed to create {}: {e}", path.display()))?;
+                file.write_all(rendered.expose_secret().as_bytes())
+                    .map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+                ceprintln!(
+                    "Rendered {num_credentials} credentials from vault <blue>{vault_key}</blue> to <blue>{}</blue>",
+                    path.display()
+                );
+            },
+            None => {
+                let mut stdout = std::io::stdout();
+                if stdout.is_terminal() && !self.yes {
+                    let confirmed = inquire::Confirm::new(&format!(
+                        "Print {num_credentials} credentials from vault {vault_key} in plaintext?"
+                    ))
+                    .with_default(false)
+                    .prompt()
+                    .unwrap_or(false);
+                    if !confirmed {
+                        return Err("Not confirmed, nothing was printed".to_string());
+                    }
+                }
+                stdout
+                    .write_all(rendered.expose_secret().as_bytes())
+                    .map_err(|e| format!("Failed to write output: {e}"))?;
+            },
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use clap::Parser;
+use color_print::cprintln;
+
+use crate::cli::commands::vault::utils::select_vault;
+use crate::secrets::vaults::VaultsManager;
+
+#[derive(Parser, Debug)]
+pub struct VaultRotateKeyCommand {
+    /// Vault key of vault to rotate (will prompt if not given)
+    #[arg(long)]
+    vault: Option<String>,
+}
+
+impl VaultRotateKeyCommand {
+    pub fn execute(&self) -> Result<(), String> {
+        let mut vm = VaultsManager::new();
+        let vault_key = match self.vault {
+            Some(ref k) => k.to_string(),
+            None => select_vault(vm.vault_labels())?,
+        };
+
+        let vw = vm
+            .get_vault_mut(&vault_key)
+            .ok_or_else(|| format!("Vault not found: {vault_key}"))?;
+
+        vw.unlock_for_update()
+            .map_err(|e| format!("Failed to unlock vault: {e}"))?;
+        vw.rotate_file_key()
+            .map_err(|e| format!("Failed to rotate file key: {e}"))?;
+        vw.save()
+            .map_err(|e| format!("Failed to save vault: {e}"))?;
+
+        cprintln!("Rotated file key of vault <blue>{vault_key}</blue>");
+        cprintln!(
+            "<dim>Exports made before the rotation can still be decrypted with their passphrase or recipient.</dim>"
+        );
+        Ok(())
+    }
+}
@@ -1,9 +1,12 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::{IsTerminal, Read};
 
-use inquire::Password;
+use inquire::{Password, Select};
 use secrecy::SecretString;
 
+use crate::secrets::vaults::vault_export::parse_age_identity;
+
 pub fn prompt_passphrase(prompt: &str) -> Result<SecretString, String> {
     if std::io::stdin().is_terminal() {
         Password::new(prompt)
@@ -22,21 +25,21 @@ pub fn prompt_passphrase(prompt: &str) -> Result<SecretString, String> {
 pub fn read_age_identity_file(path: &str) -> Result<age::x25519::Identity, String> {
     let contents = fs::read_to_string(path)
         .map_err(|e| format!("Failed to read identity file '{path}': {e}"))?;
+    parse_age_identity(&contents).map_err(|e| format!("Identity file '{path}' {e}"))
+}
 
-    let key_line = contents
-        .lines()
-        .filter(|l| l.starts_with("AGE-SECRET-KEY-1"))
-        .collect::<Vec<_>>();
-
-    if key_line.len() != 1 {
-        return Err(format!(
-            "Identity file '{path}' should contain exactly one secret key found {}",
-            key_line.len()
-        ));
+pub fn select_vault(vault_labels: BTreeMap<String, String>) -> Result<String, String> {
+    if vault_labels.is_empty() {
+        return Err("No vaults found".to_string());
     }
-    let identity_str = key_line.first().unwrap().trim();
+    let labels = vault_labels.keys().cloned().collect::<Vec<_>>();
+    let selected = Select::new("Select a vault:", labels)
+        .prompt()
+        .map_err(|e| format!("Vault selection cancelled: {e}"))?
+        .to_string();
 
-    identity_str
-        .parse::<age::x25519::Identity>()
-        .map_err(|e| format!("Invalid age identity in '{path}': {e}"))
+    vault_labels
+        .get(&selected)
+        .cloned()
+        .ok_or_else(|| "Failed to resolve selected vault".to_string())
 }
@@ -5,6 +5,7 @@ use std::io;
 
 use clap::{CommandFactory, Parser, Subcommand};
 use clap_complete::{Shell, generate};
+use color_print::{ceprintln, cprintln};
 use fork::daemon;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_subscriber::layer::SubscriberExt;
@@ -13,13 +14,17 @@ use tracing_subscriber::{EnvFilter, fmt, reload};
 
 use crate::cli::commands::age::AgeCommand;
 use crate::cli::commands::exec::ExecCommand;
+use crate::cli::commands::file::FileCommand;
+use crate::cli::commands::generate::GenerateCommand;
 use crate::cli::commands::inject::InjectCommand;
-use crate::cli::commands::item::{ItemCommand, ItemReference};
+use crate::cli::commands::item::ItemCommand;
 use crate::cli::commands::keychain::KeychainCommand;
 use crate::cli::commands::ssh_agent::SshAgentCommand;
 use crate::cli::commands::vault::VaultCommand;
 use crate::core::build_sha;
 use crate::core::dirs::{log_data_dir, vaults_dir};
+use crate::secrets::vaults::ALLOW_VAULT_ROLLBACK_ENV;
+use crate::secrets::vaults::reference::ItemReference;
 
 #[derive(Parser, Debug)]
 pub struct AxoPassCli {
@@ -40,12 +45,29 @@ pub enum AxoPassCommand {
         item_reference: ItemReference,
     },
 
+    /// Generate a one-time password from a credential holding an otpauth://
+    /// URI or base32 seed
+    Totp {
+        item_reference: ItemReference,
+
+        /// Show the seconds until the code changes (on stderr)
+        #[arg(long)]
+        remaining: bool,
+    },
+
+    /// Generate a random password or passphrase, optionally storing it in a
+    /// vault
+    Generate(GenerateCommand),
+
     /// Run a command with secrets interpolated into the environment
     Exec(ExecCommand),
 
     /// Inject secrets into a file
     Inject(InjectCommand),
 
+    /// Encrypt the values of YAML, JSON, TOML and .env files in place
+    File(FileCommand),
+
     /// Commands for managing items stored in keychain
     Keychain(KeychainCommand),
 
@@ -131,6 +153,17 @@ impl AxoPassCommand {
     }
 
     async fn execute_async(&self) {
+        // the vault layer only logs unlocking a rolled back vault
+        if std::env::var_os(ALLOW_VAULT_ROLLBACK_ENV).is_some()
+            && !matches!(
+                self,
+                AxoPassCommand::SshAgent(_) | AxoPassCommand::Shellenv { .. }
+            )
+        {
+            ceprintln!(
+                "<yellow>Warning:</yellow> {ALLOW_VAULT_ROLLBACK_ENV} is set, rolled back vaults can be unlocked"
+            );
+        }
         match self {
             AxoPassCommand::Keychain(keychain) => keychain.execute().await,
             AxoPassCommand::Vault(vault) => vault.execute().await,
@@ -138,8 +171,19 @@ impl AxoPassCommand {
             AxoPassCommand::Read { item_reference } => {
                 ItemCommand::cmd_read(item_reference, None).unwrap();
             },
+            AxoPassCommand::Totp {
+                item_reference,
+                remaining,
+            } => {
+                if let Err(e) = ItemCommand::cmd_totp(item_reference, None, *remaining) {
+                    cprintln!("<red>Error:</red> {e}");
+                    std::process::exit(1);
+                }
+            },
+            AxoPassCommand::Generate(generate) => generate.execute().await,
             AxoPassCommand::Exec(exec) => exec.execute().await,
             AxoPassCommand::Inject(inject) => inject.execute().await,
+            AxoPassCommand::File(file) => file.execute().await,
             AxoPassCommand::Age(age) => age.execute().await,
             AxoPassCommand::Info => {
                 println!("ap {}", env!("CARGO_PKG_VERSION"));
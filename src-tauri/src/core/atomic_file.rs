@@ -0,0 +1,151 @@
+use std::fs::{self, File, OpenOptions};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// Write `contents` to a temporary file next to `path` and rename it into
+/// place, so readers (and a crash mid-write) never see a truncated file. The
+/// permissions of an existing file are preserved.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    write_atomic_with_mode(path, contents, 0o666)
+}
+
+/// Like `write_atomic`, but a newly created file gets `mode` (before the
+/// umask) instead of the default permissions.
+pub fn write_atomic_with_mode(path: &Path, contents: &[u8], mode: u32) -> io::Result<()> {
+    let tmp_path = sibling_path(path, &format!("{}.tmp", std::process::id()));
+    let result = (|| {
+        let mut tmp_file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(&tmp_path)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            tmp_file.set_permissions(metadata.permissions())?;
+        }
+        tmp_file.write_all(contents)?;
+        tmp_file.sync_all()?;
+        fs::rename(&tmp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result?;
+
+    // persist the rename itself; not all platforms support syncing directories
+    if let Some(dir) = path.parent()
+        && let Ok(dir) = File::open(dir)
+    {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Digest of a file's contents, used to detect whether another process
+/// changed the file since it was read. Returns None if the file does not exist.
+pub fn file_digest(path: &Path) -> io::Result<Option<u64>> {
+    match fs::read(path) {
+        Ok(contents) => Ok(Some(content_digest(&contents))),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+pub fn content_digest(contents: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Exclusive advisory lock on a file, held until dropped. The lock is taken
+/// on a `.lock` file next to the target, since the target itself is replaced
+/// on every write_atomic.
+pub struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    /// Block until the lock for `path` is acquired.
+    pub fn acquire(path: &Path) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(sibling_path(path, "lock"))?;
+        file.lock()?;
+        Ok(Self { file })
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+fn sibling_path(path: &Path, extension: &str) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".");
+    file_name.push(extension);
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn test_write_atomic_replaces_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.json");
+
+        write_atomic(&path, b"first").unwrap();
+        let digest = file_digest(&path).unwrap();
+        write_atomic(&path, b"second").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_ne!(file_digest(&path).unwrap(), digest);
+        assert_eq!(file_digest(&dir.path().join("missing")).unwrap(), None);
+
+        // only the target and nothing left over from the temp file
+        let entries = fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(entries, 1);
+    }
+
+    #[test]
+    fn test_write_atomic_with_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("output.env");
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        write_atomic_with_mode(&path, b"first", 0o600).unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        // an existing file keeps its permissions
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        write_atomic_with_mode(&path, b"second", 0o600).unwrap();
+        assert_eq!(mode(&path), 0o640);
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+    }
+
+    #[test]
+    fn test_file_lock_is_exclusive() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault.json");
+
+        let lock = FileLock::acquire(&path).unwrap();
+        let other = File::options()
+            .write(true)
+            .open(dir.path().join("vault.json.lock"))
+            .unwrap();
+        assert!(other.try_lock().is_err());
+        drop(lock);
+        assert!(other.try_lock().is_ok());
+    }
+}
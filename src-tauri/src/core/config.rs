@@ -1,6 +1,6 @@
 use std::collections::BTreeMap;
 use std::collections::btree_map::Entry;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::{LazyLock, Mutex};
 use std::{fs, io};
 
@@ -8,6 +8,7 @@ use anyhow::Context;
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 
+use crate::core::atomic_file::{FileLock, content_digest, file_digest, write_atomic};
 use crate::core::dirs::app_data_dir;
 use crate::core::updates::{UpdateCheckRecord, UpdateCheckResult};
 
@@ -24,6 +25,18 @@ pub struct AppConfig {
     pub updates: Option<UpdateCheckRecord>,
     #[serde(default)]
     pub external_vaults: BTreeMap<String, ExternalVaultConfig>,
+    // highest revision of each vault (by id) seen on this device, to detect
+    // vault files replaced with older copies, see VaultWrapper::unlock
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vault_revisions: BTreeMap<uuid::Uuid, u64>,
+    // passphrase protected software vault key to use instead of the Secure
+    // Enclave, see get_vault_encryption_key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vault_key_file: Option<PathBuf>,
+
+    // digest of the config file as last read or written, see update
+    #[serde(skip)]
+    file_digest: Option<u64>,
 }
 
 impl Default for AppConfig {
@@ -33,6 +46,9 @@ impl Default for AppConfig {
             update_check_disabled: None,
             updates: None,
             external_vaults: BTreeMap::new(),
+            vault_revisions: BTreeMap::new(),
+            vault_key_file: None,
+            file_digest: None,
         }
     }
 }
@@ -45,27 +61,42 @@ impl AppConfig {
     fn load_or_create() -> Result<Self, anyhow::Error> {
         let path = Self::config_path();
         if path.exists() {
-            fs::read_to_string(&path)
-                .context("reading config file")
-                .and_then(|data| toml::from_str(&data).context("parsing config file"))
+            Self::load(&path)
         } else {
             log::debug!("Creating new config file at {}", path.display());
-            let config = Self::default();
-            if let Err(e) = config.save() {
+            let mut config = Self::default();
+            if let Err(e) = config.update(|_| {}) {
                 log::warn!("Failed to save initial config: {e}");
             }
             Ok(config)
         }
     }
 
-    pub fn save(&self) -> Result<(), io::Error> {
+    fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let data = fs::read_to_string(path).context("reading config file")?;
+        let mut config: Self = toml::from_str(&data).context("parsing config file")?;
+        config.file_digest = Some(content_digest(data.as_bytes()));
+        Ok(config)
+    }
+
+    /// Apply `f` to the config and save it. The config file is locked while
+    /// saving, and if it was changed by another process (e.g. the app and the
+    /// CLI) since it was loaded, it is reloaded before `f` is reapplied, so
+    /// that changes are not lost.
+    pub fn update<F: FnOnce(&mut Self)>(&mut self, f: F) -> Result<(), io::Error> {
         let path = Self::config_path();
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent)?;
+        let _file_lock = FileLock::acquire(&path)?;
+        if file_digest(&path)? != self.file_digest && path.exists() {
+            log::debug!("Config file changed on disk, reloading");
+            *self = Self::load(&path).map_err(io::Error::other)?;
         }
+        f(self);
+
         let contents = toml::to_string_pretty(self)
             .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-        fs::write(path, contents)
+        write_atomic(&path, contents.as_bytes())?;
+        self.file_digest = Some(content_digest(contents.as_bytes()));
+        Ok(())
     }
 
     pub fn record_update_check(&mut self, result: UpdateCheckResult) {
@@ -77,11 +108,26 @@ impl AppConfig {
 
     pub fn add_external_vault(&mut self, vault_key: &str, path: PathBuf) -> Result<(), io::Error> {
         let vault_key = vault_key.to_string();
-        if let Entry::Vacant(e) = self.external_vaults.entry(vault_key) {
-            e.insert(ExternalVaultConfig { path });
-            self.save()?;
-        }
-        Ok(())
+        self.update(|config| {
+            if let Entry::Vacant(e) = config.external_vaults.entry(vault_key) {
+                e.insert(ExternalVaultConfig { path });
+            }
+        })
+    }
+
+    pub fn last_seen_vault_revision(&self, vault_id: uuid::Uuid) -> u64 {
+        self.vault_revisions.get(&vault_id).copied().unwrap_or(0)
+    }
+
+    pub fn record_vault_revision(
+        &mut self,
+        vault_id: uuid::Uuid,
+        revision: u64,
+    ) -> Result<(), io::Error> {
+        self.update(|config| {
+            let seen = config.vault_revisions.entry(vault_id).or_insert(0);
+            *seen = (*seen).max(revision);
+        })
     }
 }
 
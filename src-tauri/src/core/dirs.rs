@@ -1,8 +1,15 @@
 use std::path::PathBuf;
+use std::sync::OnceLock;
+
+// set on first use; tests set it first to keep their vaults and config out of
+// the user's, see test_vaults::use_test_data_dir
+pub(crate) static APP_DATA_DIR: OnceLock<PathBuf> = OnceLock::new();
 
 pub fn app_data_dir() -> PathBuf {
-    // ~/Library/Application Support/Axo Pass
-    dirs::data_dir().unwrap().join("Axo Pass")
+    APP_DATA_DIR
+        // ~/Library/Application Support/Axo Pass
+        .get_or_init(|| dirs::data_dir().unwrap().join("Axo Pass"))
+        .clone()
 }
 
 pub fn log_data_dir() -> PathBuf {
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+// `NAME=` or `export NAME=` at the start of a line, up to the start of the
+// value
+static ASSIGNMENT_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[ \t]*(?:export[ \t]+)?(?P<name>[A-Za-z_][A-Za-z0-9_.]*)[ \t]*=[ \t]*").unwrap()
+});
+
+/// Replace the values of variables in a dotenv file, e.g. with `values`
+/// {"API_KEY": "axo://vault/item/api_key"}, `API_KEY="secret" # prod` becomes
+/// `API_KEY=axo://vault/item/api_key # prod`. Quoted values (including
+/// multi-line ones) are replaced as a whole. Comments, blank lines, ordering
+/// and all other variables are kept as they are.
+pub fn replace_env_values(contents: &str, values: &HashMap<String, String>) -> String {
+    let mut output = String::with_capacity(contents.len());
+    let mut rest = contents;
+    while !rest.is_empty() {
+        let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        let Some(captures) = ASSIGNMENT_REGEX.captures(&rest[..line_end]) else {
+            output.push_str(&rest[..line_end]);
+            rest = &rest[line_end..];
+            continue;
+        };
+        let value_start = captures.get(0).unwrap().end();
+        let value_end = value_start + value_len(&rest[value_start..]);
+        match values.get(&captures["name"]) {
+            Some(value) => {
+                output.push_str(&rest[..value_start]);
+                output.push_str(value);
+            },
+            None => output.push_str(&rest[..value_end]),
+        }
+        // the rest of the line (a trailing comment and the newline) is copied
+        // as is
+        rest = &rest[value_end..];
+    }
+    output
+}
+
+/// Remove every assignment of a variable from a dotenv file, including a
+/// trailing comment on the same line.
+pub fn remove_env_var(contents: &str, name: &str) -> String {
+    let mut output = String::with_capacity(contents.len());
+    let mut rest = contents;
+    while !rest.is_empty() {
+        let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        let Some(captures) = ASSIGNMENT_REGEX.captures(&rest[..line_end]) else {
+            output.push_str(&rest[..line_end]);
+            rest = &rest[line_end..];
+            continue;
+        };
+        let value_start = captures.get(0).unwrap().end();
+        let value_end = value_start + value_len(&rest[value_start..]);
+        if &captures["name"] == name {
+            let line_end = rest[value_end..]
+                .find('\n')
+                .map_or(rest.len(), |i| value_end + i + 1);
+            rest = &rest[line_end..];
+        } else {
+            output.push_str(&rest[..value_end]);
+            rest = &rest[value_end..];
+        }
+    }
+    output
+}
+
+/// Format a value for a dotenv file: as is if no quoting is needed, otherwise
+/// double-quoted with `\`, `"`, `$` and newlines escaped, so it is read back
+/// unchanged (and without expanding $VARIABLES).
+pub fn quote_env_value(value: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_./:@%+=,~^[]".contains(c);
+    if !value.is_empty() && value.chars().all(is_plain) {
+        return value.to_string();
+    }
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '\\' | '"' | '$' => {
+                quoted.push('\\');
+                quoted.push(c);
+            },
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// length of the value at the start of s: up to the closing quote for quoted
+// values, otherwise up to a comment or the end of the line
+fn value_len(s: &str) -> usize {
+    match s.chars().next() {
+        Some(quote @ ('"' | '\'')) => {
+            let mut escaped = false;
+            for (i, c) in s.char_indices().skip(1) {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' && quote == '"' {
+                    escaped = true;
+                } else if c == quote {
+                    return i + 1;
+                }
+            }
+            s.len()
+        },
+        _ => {
+            let line = &s[..s.find('\n').unwrap_or(s.len())];
+            let comment_start = line
+                .find(" #")
+                .or_else(|| line.find("\t#"))
+                .unwrap_or(line.len());
+            line[..comment_start].trim_end().len()
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_env_values() {
+        let contents = concat!(
+            "# database\n",
+            "DATABASE_URL=postgres://user:pw@localhost/db\n",
+            "export API_KEY = \"abc \\\" def\" # prod key\n",
+            "PRIVATE_KEY='-----BEGIN KEY-----\n",
+            "abc\n",
+            "-----END KEY-----'\n",
+            "\n",
+            "DEBUG=true\r\n",
+            "TOKEN=xyz",
+        );
+        let values = HashMap::from([
+            (
+                "DATABASE_URL".to_string(),
+                "axo://v/app/database_url".to_string(),
+            ),
+            ("API_KEY".to_string(), "axo://v/app/api_key".to_string()),
+            (
+                "PRIVATE_KEY".to_string(),
+                "axo://v/app/private_key".to_string(),
+            ),
+            ("TOKEN".to_string(), "axo://v/app/token".to_string()),
+        ]);
+        assert_eq!(
+            replace_env_values(contents, &values),
+            concat!(
+                "# database\n",
+                "DATABASE_URL=axo://v/app/database_url\n",
+                "export API_KEY = axo://v/app/api_key # prod key\n",
+                "PRIVATE_KEY=axo://v/app/private_key\n",
+                "\n",
+                "DEBUG=true\r\n",
+                "TOKEN=axo://v/app/token",
+            )
+        );
+
+        // multi-line values of other variables are left alone, even when a line
+        // inside them looks like an assignment
+        let contents = "CERT=\"line one\nTOKEN=not-a-variable\"\nTOKEN=xyz\n";
+        assert_eq!(
+            replace_env_values(contents, &values),
+            "CERT=\"line one\nTOKEN=not-a-variable\"\nTOKEN=axo://v/app/token\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_env_var() {
+        let contents = "A=1\nTOKEN=\"multi\nline\" # comment\nB=2\nexport TOKEN=again";
+        assert_eq!(remove_env_var(contents, "TOKEN"), "A=1\nB=2\n");
+        assert_eq!(remove_env_var(contents, "C"), contents);
+    }
+
+    #[test]
+    fn test_quote_env_value() {
+        assert_eq!(
+            quote_env_value("postgres://u@host:5432/db"),
+            "postgres://u@host:5432/db"
+        );
+        assert_eq!(quote_env_value(""), "\"\"");
+        let value = "p$w \"d\\\nx";
+        let quoted = quote_env_value(value);
+        assert_eq!(quoted, "\"p\\$w \\\"d\\\\\\nx\"");
+        let vars = dotenvy::from_read_iter(format!("A={quoted}").as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(vars, [("A".to_string(), value.to_string())]);
+    }
+}
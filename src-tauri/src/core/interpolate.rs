@@ -1,34 +1,240 @@
-use regex::Regex;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
+use time::{Duration, OffsetDateTime};
+
+use crate::core::template::{Template, TemplateContext};
 use crate::secrets::vaults::VaultsManager;
+use crate::secrets::vaults::reference::{ItemReference, replace_references};
+
+/// Credentials expiring within this window are reported when interpolated.
+const EXPIRY_WARNING_WINDOW: Duration = Duration::days(7);
+
+/// A reference that couldn't be resolved, see Interpolator::failures.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReferenceFailure {
+    pub reference: String,
+    pub reason: String,
+}
+
+/// Replaces axo:// references in inputs (or renders templates, see Template)
+/// with values from the vaults. Each reference is resolved once, so e.g. a
+/// ?totp reference in an if exists and its output gives the same code.
+///
+/// References that can't be resolved are replaced with NOT_FOUND or ERROR,
+/// and collected in failures. Strict callers check failures before using
+/// the output.
+pub struct Interpolator<'a> {
+    vaults: &'a mut VaultsManager,
+    resolved: HashMap<String, Result<Option<String>, String>>,
+    failures: BTreeMap<String, String>,
+    // vaults that couldn't be unlocked, see unlock_vaults
+    locked: BTreeMap<String, String>,
+    warned: HashSet<String>,
+}
+
+impl<'a> Interpolator<'a> {
+    pub fn new(vaults: &'a mut VaultsManager) -> Self {
+        Self {
+            vaults,
+            resolved: HashMap::new(),
+            failures: BTreeMap::new(),
+            locked: BTreeMap::new(),
+            warned: HashSet::new(),
+        }
+    }
+
+    /// Unlock the vaults of references (e.g. from find_references) before
+    /// interpolating them, each once and in parallel across vaults. References
+    /// to vaults that can't be unlocked fail without trying again.
+    pub fn unlock_vaults<'r>(&mut self, references: impl IntoIterator<Item = &'r str>) {
+        let references = references
+            .into_iter()
+            .filter_map(|item_url| item_url.parse::<ItemReference>().ok())
+            .collect::<Vec<_>>();
+        match self.vaults.unlock_vaults_for(&references) {
+            Ok(errors) => {
+                for (vault_key, e) in errors {
+                    self.locked.insert(vault_key, e.to_string());
+                }
+            },
+            // without the vault key, none of the locked vaults can be unlocked
+            Err(e) => {
+                log::error!("Error unlocking vaults: {e:?}");
+                for reference in &references {
+                    let vault_key = self.vault_key(reference).to_string();
+                    if !self
+                        .vaults
+                        .get_vault(&vault_key)
+                        .is_some_and(|vault| vault.is_unlocked())
+                    {
+                        self.locked.insert(vault_key, e.to_string());
+                    }
+                }
+            },
+        }
+    }
+
+    pub fn interpolate(&mut self, input: &str) -> String {
+        replace_references(input, |item_url| self.value(item_url))
+    }
 
-pub fn interpolate_secrets(input: &str, vaults: &mut VaultsManager) -> String {
-    let axo_url_re =
-        Regex::new(r"\baxo://(?P<vault>[a-zA-Z0-9-_]+)/(?P<item>[a-zA-Z0-9-_]+)/(?P<credential>[a-zA-Z0-9-_]+\b)").unwrap();
-    let result = axo_url_re.replace_all(input, |caps: &regex::Captures| {
-        let item_url = &caps[0];
+    /// Render a template, see Template.
+    pub fn render_template(&mut self, input: &str) -> Result<String, String> {
+        let template = Template::parse(input)?;
+        let references = template.references();
+        self.unlock_vaults(references.iter().map(String::as_str));
+        Ok(template.render(self))
+    }
+
+    /// References that couldn't be resolved so far, sorted by reference.
+    pub fn failures(&self) -> Vec<ReferenceFailure> {
+        self.failures
+            .iter()
+            .map(|(reference, reason)| ReferenceFailure {
+                reference: reference.clone(),
+                reason: reason.clone(),
+            })
+            .collect()
+    }
+
+    fn resolve(&mut self, item_url: &str) -> Result<Option<String>, String> {
+        if let Some(resolved) = self.resolved.get(item_url) {
+            return resolved.clone();
+        }
         log::debug!("Found reference {item_url}");
-        match vaults.get_secret_by_url(item_url) {
-            Ok(Some(secret)) => secret,
+        if let Some(reason) = self.unlock_error(item_url) {
+            return Err(reason);
+        }
+        let resolved = match self.vaults.get_secret_by_url(item_url) {
+            Ok(Some(secret)) => {
+                if self.warned.insert(item_url.to_string()) {
+                    warn_if_expiring(item_url, self.vaults);
+                }
+                Ok(Some(secret))
+            },
             Ok(None) => {
                 log::warn!("Secret not found for reference: {}", item_url);
-                "NOT_FOUND".to_string()
+                Ok(None)
             },
             Err(e) => {
                 log::error!("Error fetching secret for reference {}: {:?}", item_url, e);
+                Err(e.to_string())
+            },
+        };
+        self.resolved.insert(item_url.to_string(), resolved.clone());
+        resolved
+    }
+
+    // the error unlocking the referenced vault, if unlock_vaults failed to
+    fn unlock_error(&self, item_url: &str) -> Option<String> {
+        let reference = item_url.parse::<ItemReference>().ok()?;
+        self.locked.get(self.vault_key(&reference)).cloned()
+    }
+
+    fn vault_key<'r>(&'r self, reference: &'r ItemReference) -> &'r str {
+        reference
+            .vault
+            .as_deref()
+            .unwrap_or(self.vaults.default_vault())
+    }
+
+    fn fail(&mut self, item_url: &str, reason: String) {
+        self.failures.entry(item_url.to_string()).or_insert(reason);
+    }
+}
+
+impl TemplateContext for Interpolator<'_> {
+    fn value(&mut self, item_url: &str) -> String {
+        match self.resolve(item_url) {
+            Ok(Some(secret)) => secret,
+            Ok(None) => {
+                self.fail(item_url, "not found".to_string());
+                "NOT_FOUND".to_string()
+            },
+            Err(e) => {
+                self.fail(item_url, e);
                 "ERROR".to_string()
             },
         }
-    });
-    result.to_string()
+    }
+
+    // a reference that doesn't exist isn't a failure here, but one that
+    // can't be resolved (e.g. the vault doesn't unlock) is
+    fn exists(&mut self, item_url: &str) -> bool {
+        match self.resolve(item_url) {
+            Ok(secret) => secret.is_some(),
+            Err(e) => {
+                self.fail(item_url, e);
+                false
+            },
+        }
+    }
+
+    fn credential_keys(&mut self, item_url: &str) -> Vec<String> {
+        if let Some(reason) = self.unlock_error(item_url) {
+            self.fail(item_url, reason);
+            return Vec::new();
+        }
+        let keys = item_url
+            .parse::<ItemReference>()
+            .and_then(|reference| self.vaults.get_credential_keys(&reference));
+        keys.unwrap_or_else(|e| {
+            log::error!("Error listing credentials of {item_url}: {e:?}");
+            self.fail(item_url, e.to_string());
+            Vec::new()
+        })
+    }
+}
+
+/// A report of failures for error messages, one reference per line.
+pub fn failure_report(failures: &[ReferenceFailure]) -> String {
+    let mut report = match failures.len() {
+        1 => "1 reference could not be resolved:".to_string(),
+        n => format!("{n} references could not be resolved:"),
+    };
+    for failure in failures {
+        report.push_str(&format!("\n  {}: {}", failure.reference, failure.reason));
+    }
+    report
+}
+
+fn warn_if_expiring(item_url: &str, vaults: &VaultsManager) {
+    let Some(expires_at) = vaults.get_expiry_by_url(item_url) else {
+        return;
+    };
+    let now = OffsetDateTime::now_utc();
+    if expires_at <= now {
+        eprintln!("warning: {item_url} expired on {}", expires_at.date());
+    } else if expires_at - now <= EXPIRY_WARNING_WINDOW {
+        eprintln!("warning: {item_url} expires on {}", expires_at.date());
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::secrets::vaults::reference::find_references;
+    use crate::secrets::vaults::test_vaults::{TestVaults, use_test_data_dir};
+
+    fn test_vaults() -> TestVaults {
+        TestVaults::new(&[
+            ("app", "database", "username", "admin"),
+            ("app", "database", "password", "hunter2"),
+            (
+                "app",
+                "database",
+                "config",
+                r#"{"primary": {"host": "db.internal", "port": 5432}}"#,
+            ),
+            ("app", "database", "hosts", "db-1\ndb-2"),
+            ("ops", "deploy", "token", "tok_123"),
+            ("ops", "deploy", "user", "deployer"),
+        ])
+    }
 
     #[test]
     fn test_interpolate_secrets() {
+        use_test_data_dir();
         let cases: &[(&str, &str)] = &[
             ("hello world", "hello world"),
             ("", ""),
@@ -41,11 +247,24 @@ mod tests {
             ("axo://vault/item", "axo://vault/item"),
             // replace url with ERROR (vault doesn't exist)
             ("axo://nonexistent-vault/my-item/my-cred", "ERROR"),
+            // without a vault, the default vault is used (which doesn't exist either)
+            ("axo:///my-item/my-cred", "ERROR"),
             // only url is replaced
             (
                 "prefix axo://no-vault/item/cred suffix",
                 "prefix ERROR suffix",
             ),
+            // query modifiers are part of the reference
+            ("axo://nonexistent-vault/my-item/my-cred?totp", "ERROR"),
+            ("axo://nonexistent-vault/my-item/kubeconfig?file", "ERROR"),
+            (
+                "axo://nonexistent-vault/my-item/config?json=.a.b&encoding=base64",
+                "ERROR",
+            ),
+            // unknown modifiers are an error rather than left in the output
+            ("axo://nonexistent-vault/my-item/my-cred?other", "ERROR"),
+            // a trailing ? isn't part of the reference
+            ("axo://nonexistent-vault/my-item/my-cred?", "ERROR?"),
             // multiple references are all replaced
             ("a=axo://v1/i1/c1 b=axo://v2/i2/c2", "a=ERROR b=ERROR"),
             // "xaxo" has no \b before `axo` — not matched
@@ -54,14 +273,125 @@ mod tests {
             ("axo://vault/item/cred.", "ERROR."),
         ];
 
-        // todo: have a way to create a test vault
         let mut vaults = VaultsManager::default();
         for (input, expected) in cases {
             assert_eq!(
-                interpolate_secrets(input, &mut vaults),
+                Interpolator::new(&mut vaults).interpolate(input),
                 *expected,
                 "input: {input:?}",
             );
         }
     }
+
+    #[test]
+    fn test_failures() {
+        use_test_data_dir();
+        let mut vaults = VaultsManager::default();
+        let mut interpolator = Interpolator::new(&mut vaults);
+        interpolator.unlock_vaults(find_references("a=axo://v2/i/c b=axo://v1/i/c"));
+        interpolator.interpolate("a=axo://v2/i/c b=axo://v1/i/c");
+        interpolator.interpolate("c=axo://v1/i/c d=axo://v1/i/c?bad");
+        // if exists doesn't fail for missing references, only for ones that
+        // can't be resolved
+        let output = interpolator
+            .render_template(r#"{{ if exists "v3/i/c" }}yes{{ else }}no{{ end }}"#)
+            .unwrap();
+        assert_eq!(output, "no");
+
+        let failures = interpolator.failures();
+        let references = failures
+            .iter()
+            .map(|failure| failure.reference.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            references,
+            [
+                "axo://v1/i/c",
+                "axo://v1/i/c?bad",
+                "axo://v2/i/c",
+                "axo://v3/i/c"
+            ]
+        );
+        assert_eq!(failures[0].reason, "Vault v1 not found");
+        assert!(
+            failures[1]
+                .reason
+                .starts_with("Invalid vault item reference")
+        );
+        assert!(failure_report(&failures).starts_with(
+            "4 references could not be resolved:\n  axo://v1/i/c: Vault v1 not found\n"
+        ));
+    }
+
+    #[test]
+    fn test_interpolate_values() {
+        let test_vaults = test_vaults();
+        let mut vaults = test_vaults.unlocked_manager();
+        vaults.set_default_vault("ops".to_string());
+        let mut interpolator = Interpolator::new(&mut vaults);
+        let cases = [
+            ("axo://app/database/password", "hunter2"),
+            (
+                "USER=axo://app/database/username PASS=axo://app/database/password",
+                "USER=admin PASS=hunter2",
+            ),
+            (
+                "axo://app/database/config?json=.primary.host",
+                "db.internal",
+            ),
+            ("axo://app/database/config?json=.primary.port", "5432"),
+            ("axo://app/database/hosts?line=2", "db-2"),
+            ("axo://app/database/hosts?line=3&default=none", "none"),
+            ("axo://app/database/missing?default=fallback", "fallback"),
+            (
+                "axo://app/database/password?encoding=base64",
+                "aHVudGVyMg==",
+            ),
+            // without a vault, the default vault is used
+            ("axo:///deploy/token", "tok_123"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                interpolator.interpolate(input),
+                expected,
+                "input: {input:?}"
+            );
+        }
+        assert_eq!(interpolator.failures(), []);
+    }
+
+    #[test]
+    fn test_render_template() {
+        let test_vaults = test_vaults();
+        let mut vaults = test_vaults.unlocked_manager();
+        let mut interpolator = Interpolator::new(&mut vaults);
+        let template = r#"{{ range "ops/deploy" }}{{ .key }}={{ .value }}
+{{ end }}{{ if exists "app/database/password" }}password{{ end }}
+{{ if exists "app/database/missing" }}missing{{ else }}no missing{{ end }}
+"#;
+        assert_eq!(
+            interpolator.render_template(template).unwrap(),
+            "token=tok_123\nuser=deployer\npassword\nno missing\n"
+        );
+        assert_eq!(interpolator.failures(), []);
+    }
+
+    #[test]
+    fn test_strict_and_lenient() {
+        let test_vaults = test_vaults();
+        let mut vaults = test_vaults.unlocked_manager();
+        let mut interpolator = Interpolator::new(&mut vaults);
+        // lenient callers use the output with placeholders for failures...
+        let output = interpolator
+            .interpolate("PASS=axo://app/database/password\nTOKEN=axo://app/database/token\n");
+        assert_eq!(output, "PASS=hunter2\nTOKEN=NOT_FOUND\n");
+        // ...which strict callers refuse
+        assert_eq!(
+            interpolator.failures(),
+            [ReferenceFailure {
+                reference: "axo://app/database/token".to_string(),
+                reason: "not found".to_string(),
+            }]
+        );
+    }
 }
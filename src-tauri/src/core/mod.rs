@@ -1,10 +1,14 @@
+pub mod atomic_file;
 pub mod auth;
 pub mod build_sha;
 pub mod config;
 pub mod dirs;
+pub mod env_file;
 pub mod find_bin_folder;
 pub mod interpolate;
 mod objc_helpers;
+pub mod password_generator;
 pub mod provenance;
 pub mod read_input;
+pub mod template;
 pub mod updates;
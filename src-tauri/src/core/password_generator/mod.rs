@@ -0,0 +1,205 @@
+use std::sync::LazyLock;
+
+use aes_gcm::aead::OsRng;
+use aes_gcm::aead::rand_core::RngCore;
+use secrecy::SecretString;
+use zeroize::Zeroize;
+
+// BIP39 English wordlist: 2048 words (11 bits each) with unique 4-letter
+// prefixes, also used by age for generated passphrases
+static WORDLIST: LazyLock<Vec<&'static str>> =
+    LazyLock::new(|| include_str!("./bip39-english.txt").lines().collect());
+
+/// Character classes for generated passwords. A password contains at least one
+/// character of every class it is generated from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CharacterClass {
+    Lower,
+    Upper,
+    Digits,
+    Symbols,
+}
+
+impl CharacterClass {
+    pub fn chars(&self) -> &'static str {
+        match self {
+            CharacterClass::Lower => "abcdefghijklmnopqrstuvwxyz",
+            CharacterClass::Upper => "ABCDEFGHIJKLMNOPQRSTUVWXYZ",
+            CharacterClass::Digits => "0123456789",
+            // no quotes, backslashes, $ or spaces, which need escaping in
+            // shells and config files
+            CharacterClass::Symbols => "!#%&*+-.:=?@^_~",
+        }
+    }
+}
+
+pub struct Generated {
+    pub value: SecretString,
+    /// estimated entropy in bits, assuming the generator is known
+    pub entropy: f64,
+}
+
+/// Generate a password of length characters from the given classes, with at
+/// least one character of each class.
+pub fn generate_password(length: usize, classes: &[CharacterClass]) -> Result<Generated, String> {
+    let mut classes = classes.to_vec();
+    classes.sort();
+    classes.dedup();
+    if classes.is_empty() {
+        return Err("At least one character class is required".to_string());
+    }
+    if length < classes.len() {
+        return Err(format!(
+            "Passwords with {} character classes need at least {} characters",
+            classes.len(),
+            classes.len()
+        ));
+    }
+
+    let alphabet = classes
+        .iter()
+        .flat_map(|class| class.chars().chars())
+        .collect::<Vec<_>>();
+    // passwords missing a class are rejected rather than patched, so that
+    // every valid password is equally likely
+    let value = loop {
+        let mut password = (0..length)
+            .map(|_| alphabet[random_index(alphabet.len())])
+            .collect::<String>();
+        if classes
+            .iter()
+            .all(|class| password.chars().any(|c| class.chars().contains(c)))
+        {
+            break password;
+        }
+        password.zeroize();
+    };
+    Ok(Generated {
+        value: value.into(),
+        entropy: password_entropy(length, &classes),
+    })
+}
+
+/// Generate a passphrase of words random words from the wordlist.
+pub fn generate_passphrase(words: usize, separator: &str) -> Result<Generated, String> {
+    if words == 0 {
+        return Err("At least one word is required".to_string());
+    }
+    let value = (0..words)
+        .map(|_| WORDLIST[random_index(WORDLIST.len())])
+        .collect::<Vec<_>>()
+        .join(separator);
+    Ok(Generated {
+        value: value.into(),
+        entropy: words as f64 * (WORDLIST.len() as f64).log2(),
+    })
+}
+
+// uniformly random index below n from the OS CSPRNG. Values from the last,
+// partial multiple of n are rejected, since they would favour small indexes.
+fn random_index(n: usize) -> usize {
+    let n = u32::try_from(n).expect("alphabet fits in u32");
+    let zone = u32::MAX - u32::MAX % n;
+    loop {
+        let value = OsRng.next_u32();
+        if value < zone {
+            return (value % n) as usize;
+        }
+    }
+}
+
+// log2 of the number of passwords with at least one character of every class,
+// counted by inclusion-exclusion over the classes left out
+fn password_entropy(length: usize, classes: &[CharacterClass]) -> f64 {
+    let total = classes
+        .iter()
+        .map(|class| class.chars().len())
+        .sum::<usize>() as f64;
+    let mut fraction = 0.0;
+    for left_out in 0..(1u32 << classes.len()) {
+        let remaining = classes
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| left_out & (1 << i) == 0)
+            .map(|(_, class)| class.chars().len())
+            .sum::<usize>() as f64;
+        let term = (remaining / total).powi(length as i32);
+        if left_out.count_ones() % 2 == 0 {
+            fraction += term;
+        } else {
+            fraction -= term;
+        }
+    }
+    length as f64 * total.log2() + fraction.log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::*;
+
+    const ALL_CLASSES: [CharacterClass; 4] = [
+        CharacterClass::Lower,
+        CharacterClass::Upper,
+        CharacterClass::Digits,
+        CharacterClass::Symbols,
+    ];
+
+    #[test]
+    fn test_generate_password() {
+        for length in [4, 5, 24] {
+            let generated = generate_password(length, &ALL_CLASSES).unwrap();
+            let password = generated.value.expose_secret();
+            assert_eq!(password.chars().count(), length);
+            for class in ALL_CLASSES {
+                assert!(password.chars().any(|c| class.chars().contains(c)));
+            }
+        }
+
+        let generated = generate_password(32, &[CharacterClass::Digits]).unwrap();
+        assert!(
+            generated
+                .value
+                .expose_secret()
+                .chars()
+                .all(|c| c.is_ascii_digit())
+        );
+        assert!((generated.entropy - 32.0 * 10f64.log2()).abs() < 1e-9);
+
+        // requiring every class costs a little entropy, less for longer passwords
+        let naive = |length: usize| length as f64 * 77f64.log2();
+        let short = generate_password(8, &ALL_CLASSES).unwrap().entropy;
+        let long = generate_password(64, &ALL_CLASSES).unwrap().entropy;
+        assert!(short < naive(8) && short > naive(8) - 2.0);
+        assert!(long < naive(64) && long > naive(64) - 0.1);
+
+        assert!(generate_password(3, &ALL_CLASSES).is_err());
+        assert!(generate_password(8, &[]).is_err());
+    }
+
+    #[test]
+    fn test_generate_passphrase() {
+        assert_eq!(WORDLIST.len(), 2048);
+        let generated = generate_passphrase(6, "-").unwrap();
+        let words = generated
+            .value
+            .expose_secret()
+            .split('-')
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        assert_eq!(words.len(), 6);
+        assert!(words.iter().all(|word| WORDLIST.contains(&word.as_str())));
+        assert_eq!(generated.entropy, 66.0);
+        assert!(generate_passphrase(0, "-").is_err());
+    }
+
+    #[test]
+    fn test_random_index() {
+        let mut counts = [0; 3];
+        for _ in 0..3000 {
+            counts[random_index(3)] += 1;
+        }
+        assert!(counts.iter().all(|&count| count > 800));
+    }
+}
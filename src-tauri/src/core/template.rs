@@ -0,0 +1,585 @@
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+
+use crate::secrets::vaults::reference::{find_references, replace_references};
+
+// Templates for `ap inject --template`. Text outside of actions is copied,
+// with bare axo:// references replaced as in plain mode. Actions:
+//
+//   {{ axo "vault/item/cred" | base64 }}   value of a reference, with filters
+//   {{ "axo://vault/item/cred" }}          a string literal, never replaced
+//   {{ if exists "vault/item/cred" }} ... {{ else }} ... {{ end }}
+//   {{ if not exists "vault/item/cred" }} ... {{ end }}
+//   {{ range "vault/item" }}{{ .key }}={{ .value }}{{ end }}
+//
+// References can leave out axo://. {{- and -}} trim the whitespace before or
+// after an action, as in Go templates.
+
+/// Resolves the references of a template, see Template::render.
+pub trait TemplateContext {
+    /// Value of an axo:// reference, or a placeholder if it can't be resolved.
+    fn value(&mut self, reference: &str) -> String;
+
+    /// Whether an axo:// reference resolves to a value.
+    fn exists(&mut self, reference: &str) -> bool;
+
+    /// Credential keys of an item reference (axo://vault/item), in order.
+    fn credential_keys(&mut self, item_reference: &str) -> Vec<String>;
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Output(Operand, Vec<Filter>),
+    If {
+        negate: bool,
+        reference: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Range {
+        item_reference: String,
+        body: Vec<Node>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+enum Operand {
+    Reference(String),
+    Literal(String),
+    // key and value of the credential in the enclosing range
+    Key,
+    Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Filter {
+    Base64,
+    JsonEscape,
+    YamlQuote,
+    Trim,
+    Upper,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Pipe,
+}
+
+enum Segment {
+    Text(String),
+    Action { tokens: Vec<Token>, line: usize },
+}
+
+// ends a block: {{ else }} or {{ end }}
+#[derive(PartialEq)]
+enum Terminator {
+    Else,
+    End,
+}
+
+// nodes of a block, and the terminator and its line (None at the end of the
+// input)
+type Block = (Vec<Node>, Option<(Terminator, usize)>);
+
+impl Template {
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            segments: split_actions(input)?.into_iter(),
+            range_depth: 0,
+        };
+        match parser.parse_block()? {
+            (nodes, None) => Ok(Template { nodes }),
+            (_, Some((_, line))) => Err(format!("line {line}: unexpected else or end")),
+        }
+    }
+
+    /// The references used by the template (item references for ranges),
+    /// e.g. to unlock their vaults before rendering.
+    pub fn references(&self) -> Vec<String> {
+        let mut references = Vec::new();
+        collect_references(&self.nodes, &mut references);
+        references
+    }
+
+    pub fn render(&self, ctx: &mut impl TemplateContext) -> String {
+        let mut output = String::new();
+        render_nodes(&self.nodes, ctx, None, &mut output);
+        output
+    }
+}
+
+// splits input into text and the tokens of {{ actions }}
+fn split_actions(input: &str) -> Result<Vec<Segment>, String> {
+    let mut segments = Vec::new();
+    let mut rest = input;
+    let mut trim_next = false;
+    while let Some(start) = rest.find("{{") {
+        let mut text = &rest[..start];
+        if trim_next {
+            text = text.trim_start();
+        }
+        let line = input[..input.len() - rest.len() + start]
+            .matches('\n')
+            .count()
+            + 1;
+        let end = find_action_end(&rest[start + 2..])
+            .ok_or_else(|| format!("line {line}: unclosed {{{{"))?;
+        let mut action = &rest[start + 2..start + 2 + end];
+        if let Some(trimmed) = action.strip_prefix('-') {
+            text = text.trim_end();
+            action = trimmed;
+        }
+        trim_next =
+            action.ends_with('-') && action[..action.len() - 1].ends_with(char::is_whitespace);
+        if trim_next {
+            action = &action[..action.len() - 1];
+        }
+        segments.push(Segment::Text(text.to_string()));
+        let tokens = tokenize(action).map_err(|e| format!("line {line}: {e}"))?;
+        segments.push(Segment::Action { tokens, line });
+        rest = &rest[start + 2 + end + 2..];
+    }
+    let text = if trim_next { rest.trim_start() } else { rest };
+    segments.push(Segment::Text(text.to_string()));
+    Ok(segments)
+}
+
+// offset of the }} closing an action, skipping string literals
+fn find_action_end(action: &str) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in action.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '}' if !in_string && action[i..].starts_with("}}") => return Some(i),
+            _ => {},
+        }
+    }
+    None
+}
+
+fn tokenize(action: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = action.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            _ if c.is_whitespace() => {},
+            '|' => tokens.push(Token::Pipe),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(c @ ('"' | '\\')) => value.push(c),
+                            Some(c) => return Err(format!("unknown escape \\{c}")),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some(c) => value.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            },
+            _ => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '|' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    segments: std::vec::IntoIter<Segment>,
+    range_depth: usize,
+}
+
+impl Parser {
+    // nodes up to the next else or end (or the end of the input)
+    fn parse_block(&mut self) -> Result<Block, String> {
+        let mut nodes = Vec::new();
+        while let Some(segment) = self.segments.next() {
+            let (tokens, line) = match segment {
+                Segment::Text(text) if text.is_empty() => continue,
+                Segment::Text(text) => {
+                    nodes.push(Node::Text(text));
+                    continue;
+                },
+                Segment::Action { tokens, line } => (tokens, line),
+            };
+            let words = tokens
+                .iter()
+                .map(|token| match token {
+                    Token::Word(word) => Some(word.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let node = match (words.as_slice(), tokens.as_slice()) {
+                ([Some("else")], _) => return Ok((nodes, Some((Terminator::Else, line)))),
+                ([Some("end")], _) => return Ok((nodes, Some((Terminator::End, line)))),
+                ([Some("if"), Some("exists"), None], [.., Token::Str(reference)]) => {
+                    self.parse_if(false, reference, line)?
+                },
+                ([Some("if"), Some("not"), Some("exists"), None], [.., Token::Str(reference)]) => {
+                    self.parse_if(true, reference, line)?
+                },
+                ([Some("range"), None], [_, Token::Str(item_reference)]) => {
+                    self.parse_range(item_reference, line)?
+                },
+                ([Some("if" | "range" | "else" | "end"), ..], [Token::Word(keyword), ..]) => {
+                    return Err(format!("line {line}: invalid {keyword}"));
+                },
+                _ => self
+                    .parse_output(&tokens)
+                    .map_err(|e| format!("line {line}: {e}"))?,
+            };
+            nodes.push(node);
+        }
+        Ok((nodes, None))
+    }
+
+    fn parse_if(&mut self, negate: bool, reference: &str, line: usize) -> Result<Node, String> {
+        let (then, terminator) = self.parse_block()?;
+        let otherwise = match terminator {
+            Some((Terminator::End, _)) => Vec::new(),
+            Some((Terminator::Else, _)) => match self.parse_block()? {
+                (otherwise, Some((Terminator::End, _))) => otherwise,
+                (_, Some((Terminator::Else, line))) => {
+                    return Err(format!("line {line}: unexpected else"));
+                },
+                (_, None) => return Err(format!("line {line}: if without end")),
+            },
+            None => return Err(format!("line {line}: if without end")),
+        };
+        Ok(Node::If {
+            negate,
+            reference: reference_url(reference),
+            then,
+            otherwise,
+        })
+    }
+
+    fn parse_range(&mut self, item_reference: &str, line: usize) -> Result<Node, String> {
+        self.range_depth += 1;
+        let (body, terminator) = self.parse_block()?;
+        self.range_depth -= 1;
+        match terminator {
+            Some((Terminator::End, _)) => Ok(Node::Range {
+                item_reference: reference_url(item_reference.trim_end_matches('/')),
+                body,
+            }),
+            Some((Terminator::Else, line)) => Err(format!("line {line}: unexpected else")),
+            None => Err(format!("line {line}: range without end")),
+        }
+    }
+
+    // operand, followed by filters: axo "ref" | base64 | ...
+    fn parse_output(&self, tokens: &[Token]) -> Result<Node, String> {
+        let (operand, rest) = match tokens {
+            [Token::Word(axo), Token::Str(reference), rest @ ..] if axo == "axo" => {
+                (Operand::Reference(reference_url(reference)), rest)
+            },
+            [Token::Str(literal), rest @ ..] => (Operand::Literal(literal.clone()), rest),
+            [Token::Word(field), rest @ ..] if field == ".key" || field == ".value" => {
+                if self.range_depth == 0 {
+                    return Err(format!("{field} outside of range"));
+                }
+                match field.as_str() {
+                    ".key" => (Operand::Key, rest),
+                    _ => (Operand::Value, rest),
+                }
+            },
+            [] => return Err("empty action".to_string()),
+            _ => return Err("expected axo \"reference\", a string, .key or .value".to_string()),
+        };
+        let mut filters = Vec::new();
+        let mut rest = rest;
+        while let [Token::Pipe, Token::Word(filter), tail @ ..] = rest {
+            filters.push(match filter.as_str() {
+                "base64" => Filter::Base64,
+                "json-escape" => Filter::JsonEscape,
+                "yaml-quote" => Filter::YamlQuote,
+                "trim" => Filter::Trim,
+                "upper" => Filter::Upper,
+                _ => return Err(format!("unknown filter {filter}")),
+            });
+            rest = tail;
+        }
+        if !rest.is_empty() {
+            return Err("expected | filter".to_string());
+        }
+        Ok(Node::Output(operand, filters))
+    }
+}
+
+// references in templates can leave out axo://
+fn reference_url(reference: &str) -> String {
+    if reference.starts_with("axo://") {
+        reference.to_string()
+    } else {
+        format!("axo://{reference}")
+    }
+}
+
+fn collect_references(nodes: &[Node], references: &mut Vec<String>) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => references.extend(find_references(text).map(str::to_string)),
+            Node::Output(Operand::Reference(reference), _) => references.push(reference.clone()),
+            Node::Output(..) => {},
+            Node::If {
+                reference,
+                then,
+                otherwise,
+                ..
+            } => {
+                references.push(reference.clone());
+                collect_references(then, references);
+                collect_references(otherwise, references);
+            },
+            Node::Range {
+                item_reference,
+                body,
+            } => {
+                references.push(item_reference.clone());
+                collect_references(body, references);
+            },
+        }
+    }
+}
+
+// scope is the item reference and credential key of the enclosing range
+fn render_nodes(
+    nodes: &[Node],
+    ctx: &mut impl TemplateContext,
+    scope: Option<(&str, &str)>,
+    output: &mut String,
+) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => {
+                output.push_str(&replace_references(text, |reference| ctx.value(reference)))
+            },
+            Node::Output(operand, filters) => {
+                let mut value = match (operand, scope) {
+                    (Operand::Reference(reference), _) => ctx.value(reference),
+                    (Operand::Literal(literal), _) => literal.clone(),
+                    (Operand::Key, Some((_, key))) => key.to_string(),
+                    (Operand::Value, Some((item_reference, key))) => {
+                        ctx.value(&format!("{item_reference}/{key}"))
+                    },
+                    // rejected by the parser
+                    (Operand::Key | Operand::Value, None) => String::new(),
+                };
+                for filter in filters {
+                    value = filter.apply(value);
+                }
+                output.push_str(&value);
+            },
+            Node::If {
+                negate,
+                reference,
+                then,
+                otherwise,
+            } => {
+                let branch = if ctx.exists(reference) != *negate {
+                    then
+                } else {
+                    otherwise
+                };
+                render_nodes(branch, ctx, scope, output);
+            },
+            Node::Range {
+                item_reference,
+                body,
+            } => {
+                for key in ctx.credential_keys(item_reference) {
+                    render_nodes(body, ctx, Some((item_reference, &key)), output);
+                }
+            },
+        }
+    }
+}
+
+impl Filter {
+    fn apply(self, value: String) -> String {
+        match self {
+            Filter::Base64 => BASE64_STANDARD.encode(value),
+            // contents of a JSON string, without the quotes
+            Filter::JsonEscape => {
+                let quoted = serde_json::Value::String(value).to_string();
+                quoted[1..quoted.len() - 1].to_string()
+            },
+            // JSON strings are valid double-quoted YAML scalars
+            Filter::YamlQuote => serde_json::Value::String(value).to_string(),
+            Filter::Trim => value.trim().to_string(),
+            Filter::Upper => value.to_uppercase(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    struct TestContext(BTreeMap<&'static str, &'static str>);
+
+    impl TemplateContext for TestContext {
+        fn value(&mut self, reference: &str) -> String {
+            self.0.get(reference).unwrap_or(&"NOT_FOUND").to_string()
+        }
+
+        fn exists(&mut self, reference: &str) -> bool {
+            self.0.contains_key(reference)
+        }
+
+        fn credential_keys(&mut self, item_reference: &str) -> Vec<String> {
+            let prefix = format!("{item_reference}/");
+            self.0
+                .keys()
+                .filter_map(|reference| reference.strip_prefix(&prefix))
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    fn render(input: &str) -> String {
+        let mut ctx = TestContext(BTreeMap::from([
+            ("axo://v/db/user", "app"),
+            ("axo://v/db/password", " p\"w\n "),
+            ("axo:///default/token", "t0ken"),
+        ]));
+        Template::parse(input)
+            .unwrap_or_else(|e| panic!("Failed to parse {input:?}: {e}"))
+            .render(&mut ctx)
+    }
+
+    #[test]
+    fn test_render() {
+        let cases = [
+            ("", ""),
+            ("plain text", "plain text"),
+            // bare references are replaced as in plain mode
+            ("user=axo://v/db/user", "user=app"),
+            (r#"{{ axo "v/db/user" }}"#, "app"),
+            (r#"{{axo "axo://v/db/user"|upper}}"#, "APP"),
+            (r#"{{ axo "/default/token" }}"#, "t0ken"),
+            (r#"{{ axo "v/db/missing" }}"#, "NOT_FOUND"),
+            // string literals are the escape for literal references and braces
+            (r#"{{ "axo://v/db/user" }}"#, "axo://v/db/user"),
+            (r#"{{ "{{ }}" }}"#, "{{ }}"),
+            (r#"{{ "a\"b\\c" }}"#, r#"a"b\c"#),
+            // filters
+            (r#"{{ axo "v/db/user" | base64 }}"#, "YXBw"),
+            (r#"{{ axo "v/db/password" | trim }}"#, "p\"w"),
+            (r#"{{ axo "v/db/password" | json-escape }}"#, r#" p\"w\n "#),
+            (r#"{{ axo "v/db/password" | yaml-quote }}"#, r#"" p\"w\n ""#),
+            (
+                r#"{{ axo "v/db/password" | trim | upper | base64 }}"#,
+                "UCJX",
+            ),
+            // conditionals
+            (r#"{{ if exists "v/db/user" }}yes{{ end }}"#, "yes"),
+            (r#"{{ if exists "v/db/nope" }}yes{{ end }}"#, ""),
+            (
+                r#"{{ if exists "v/db/nope" }}yes{{ else }}no{{ end }}"#,
+                "no",
+            ),
+            (
+                r#"{{ if not exists "v/db/nope" }}yes{{ else }}no{{ end }}"#,
+                "yes",
+            ),
+            (
+                r#"{{ if exists "v/db/user" }}{{ if exists "v/x/y" }}a{{ else }}b{{ end }}{{ end }}"#,
+                "b",
+            ),
+            // loops and whitespace trimming
+            (
+                "{{ range \"v/db\" -}}\n{{ .key | upper }}={{ .value | trim }}\n{{- end }}",
+                "PASSWORD=p\"wUSER=app",
+            ),
+            (
+                "{{ range \"v/db/\" }}{{ .key }};{{ end }}",
+                "password;user;",
+            ),
+            ("{{ range \"v/none\" }}{{ .key }}{{ end }}", ""),
+            ("a  {{- \"b\" -}}  \n c", "abc"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(render(input), expected, "input: {input:?}");
+        }
+    }
+
+    #[test]
+    fn test_references() {
+        let template = Template::parse(
+            r#"axo://v/a/b {{ axo "v/c/d" | trim }}{{ if exists "/e/f" }}{{ range "v/g" }}{{ .value }} axo://w/h/i{{ end }}{{ end }}{{ "axo://x/y/z" }}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            template.references(),
+            [
+                "axo://v/a/b",
+                "axo://v/c/d",
+                "axo:///e/f",
+                "axo://v/g",
+                "axo://w/h/i"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (input, error) in [
+            ("{{ axo \"v/i/c\"", "line 1: unclosed {{"),
+            ("a\n{{ \"abc }}", "line 2: unclosed {{"),
+            ("{{ \"a\\x\" }}", "line 1: unknown escape \\x"),
+            ("{{ }}", "line 1: empty action"),
+            (
+                "{{ axo }}",
+                "line 1: expected axo \"reference\", a string, .key or .value",
+            ),
+            (
+                "{{ axo \"v/i/c\" | lower }}",
+                "line 1: unknown filter lower",
+            ),
+            ("{{ axo \"v/i/c\" base64 }}", "line 1: expected | filter"),
+            ("{{ .key }}", "line 1: .key outside of range"),
+            ("{{ if exists \"v/i/c\" }}", "line 1: if without end"),
+            ("{{ if \"v/i/c\" }}{{ end }}", "line 1: invalid if"),
+            (
+                "{{ range \"v/i\" }}{{ else }}{{ end }}",
+                "line 1: unexpected else",
+            ),
+            ("\n\n{{ end }}", "line 3: unexpected else or end"),
+        ] {
+            assert_eq!(
+                Template::parse(input).map(|_| ()),
+                Err(error.to_string()),
+                "input: {input:?}"
+            );
+        }
+    }
+}
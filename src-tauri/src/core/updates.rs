@@ -55,26 +55,29 @@ pub async fn check_for_updates(app_handle: tauri::AppHandle, force: bool) {
                 let version = update.version.clone();
                 println!("Update available: {version}");
                 let mut config = APP_CONFIG.lock().unwrap();
-                config.record_update_check(UpdateCheckResult::UpdateAvailable { version });
-                if let Err(e) = config.save() {
+                if let Err(e) = config.update(|config| {
+                    config.record_update_check(UpdateCheckResult::UpdateAvailable { version })
+                }) {
                     eprintln!("Failed to save config after update check: {e}");
                 }
             },
             Ok(None) => {
                 eprintln!("No updates available");
                 let mut config = APP_CONFIG.lock().unwrap();
-                config.record_update_check(UpdateCheckResult::UpToDate {});
-                if let Err(e) = config.save() {
+                if let Err(e) = config
+                    .update(|config| config.record_update_check(UpdateCheckResult::UpToDate {}))
+                {
                     eprintln!("Failed to save config after update check: {e}");
                 }
             },
             Err(e) => {
                 eprintln!("Failed to check for updates: {e}");
                 let mut config = APP_CONFIG.lock().unwrap();
-                config.record_update_check(UpdateCheckResult::Error {
-                    error: e.to_string(),
-                });
-                if let Err(e) = config.save() {
+                if let Err(e) = config.update(|config| {
+                    config.record_update_check(UpdateCheckResult::Error {
+                        error: e.to_string(),
+                    })
+                }) {
                     eprintln!("Failed to save config after update check: {e}");
                 }
             },
@@ -82,10 +85,11 @@ pub async fn check_for_updates(app_handle: tauri::AppHandle, force: bool) {
         Err(e) => {
             eprintln!("Failed to get updater: {e}");
             let mut config = APP_CONFIG.lock().unwrap();
-            config.record_update_check(UpdateCheckResult::Error {
-                error: e.to_string(),
-            });
-            if let Err(e) = config.save() {
+            if let Err(e) = config.update(|config| {
+                config.record_update_check(UpdateCheckResult::Error {
+                    error: e.to_string(),
+                })
+            }) {
                 eprintln!("Failed to save config after update check: {e}");
             }
         },
@@ -16,9 +16,9 @@ use objc2_core_foundation::{
 };
 use objc2_security::{
     SecItemDelete, SecKey, kSecAttrAccessControl, kSecAttrApplicationLabel, kSecAttrApplicationTag,
-    kSecAttrIsPermanent, kSecAttrKeyType, kSecAttrKeyTypeECSECPrimeRandom, kSecAttrLabel,
-    kSecAttrTokenID, kSecAttrTokenIDSecureEnclave, kSecClass, kSecClassKey, kSecMatchItemList,
-    kSecPrivateKeyAttrs, kSecPublicKeyAttrs, kSecUseDataProtectionKeychain,
+    kSecAttrIsPermanent, kSecAttrKeyClass, kSecAttrKeyType, kSecAttrKeyTypeECSECPrimeRandom,
+    kSecAttrLabel, kSecAttrTokenID, kSecAttrTokenIDSecureEnclave, kSecClass, kSecClassKey,
+    kSecMatchItemList, kSecPrivateKeyAttrs, kSecPublicKeyAttrs, kSecUseDataProtectionKeychain,
 };
 pub use query::ManagedKeyQuery;
 pub use shared::KeyClass;
@@ -200,6 +200,44 @@ impl ManagedKey {
         }
     }
 
+    /// Encrypt data to a P-256 public key that is not in the local keychain
+    /// (e.g. another user's vault key), using the same algorithm as encrypt so
+    /// that the holder of the matching Secure Enclave key can decrypt it.
+    pub fn encrypt_for_public_key(public_key: &KeyData, plaintext: &[u8]) -> Option<String> {
+        let sec1_bytes = public_key.ecdsa()?.as_sec1_bytes();
+        unsafe {
+            let attrs = CFMutableDictionary::<CFString, CFType>::empty();
+            attrs.add(kSecAttrKeyType, kSecAttrKeyTypeECSECPrimeRandom);
+            attrs.add(kSecAttrKeyClass, KeyClass::Public.as_objc());
+
+            let mut cf_error_ptr: *mut CFError = ptr::null_mut();
+            let sec_key = SecKey::with_data(
+                &CFData::from_bytes(sec1_bytes),
+                attrs.as_opaque(),
+                &mut cf_error_ptr,
+            );
+            if !cf_error_ptr.is_null() {
+                let cf_error = cf_error_ptr.as_ref().unwrap();
+                log::debug!("Error importing public key: {:?}", cf_error);
+                return None;
+            }
+
+            let res = sec_key?
+                .encrypted_data(alg(), &CFData::from_bytes(plaintext), &mut cf_error_ptr)
+                .map(|enc| b64.encode(enc.as_bytes_unchecked()));
+            if !cf_error_ptr.is_null() {
+                let cf_error = cf_error_ptr.as_ref().unwrap();
+                log::debug!(
+                    "Error encrypting data (size={}) with public key: {:?}",
+                    plaintext.len(),
+                    cf_error
+                );
+                return None;
+            }
+            res
+        }
+    }
+
     pub fn decrypt(&self, b64_ciphertext: &[u8]) -> Option<Vec<u8>> {
         unsafe {
             let ciphertext = b64.decode(b64_ciphertext).ok()?;
@@ -0,0 +1,77 @@
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+/// Decrypted attachments written to a private temporary directory, so that
+/// `axo://vault/item/attachment?file` can be passed to a command as a file
+/// path. The directory and its files are removed when this is dropped.
+pub struct AttachmentFiles {
+    dir: PathBuf,
+}
+
+impl AttachmentFiles {
+    pub fn create() -> io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("axo-pass-{}", Uuid::new_v4()));
+        DirBuilder::new().mode(0o700).create(&dir)?;
+        Ok(Self { dir })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        fs::read_dir(&self.dir).map_or(true, |mut entries| entries.next().is_none())
+    }
+
+    /// Write an attachment and return its path. The file keeps the
+    /// attachment's file name (for tools that look at the extension), in its
+    /// own subdirectory so names can't collide.
+    pub fn write(&self, file_name: &str, data: &[u8]) -> io::Result<PathBuf> {
+        let file_name = Path::new(file_name)
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_else(|| "attachment".into());
+        let dir = self.dir.join(Uuid::new_v4().to_string());
+        DirBuilder::new().mode(0o700).create(&dir)?;
+        let path = dir.join(file_name);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+        file.write_all(data)?;
+        Ok(path)
+    }
+}
+
+impl Drop for AttachmentFiles {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            log::warn!("Failed to remove {}: {e}", self.dir.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn test_attachment_files_are_private_and_removed() {
+        let files = AttachmentFiles::create().unwrap();
+        assert!(files.is_empty());
+        let path = files.write("../config.yaml", b"apiVersion: v1").unwrap();
+        assert!(!files.is_empty());
+        assert_eq!(path.file_name().unwrap(), "config.yaml");
+        assert!(path.starts_with(&files.dir));
+        assert_eq!(fs::read(&path).unwrap(), b"apiVersion: v1");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let dir = files.dir.clone();
+        drop(files);
+        assert!(!dir.exists());
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::vault_wrapper::VaultWrapper;
+
+/// Item and credential keys of a vault with a digest of each credential value,
+/// so that two vaults can be compared without keeping their secrets around.
+#[derive(Default)]
+pub struct VaultSnapshot {
+    items: BTreeMap<Uuid, ItemSnapshot>,
+}
+
+#[derive(Default)]
+struct ItemSnapshot {
+    key: String,
+    credentials: BTreeMap<Uuid, CredentialSnapshot>,
+}
+
+struct CredentialSnapshot {
+    key: String,
+    digest: Option<[u8; 32]>,
+}
+
+/// A difference between two vaults, from the first vault to the second.
+#[derive(Debug, PartialEq)]
+pub enum VaultChange {
+    ItemAdded {
+        id: Uuid,
+        key: String,
+    },
+    ItemRemoved {
+        id: Uuid,
+        key: String,
+    },
+    ItemRenamed {
+        id: Uuid,
+        old_key: String,
+        new_key: String,
+    },
+    CredentialAdded {
+        item_key: String,
+        id: Uuid,
+        key: String,
+    },
+    CredentialRemoved {
+        item_key: String,
+        id: Uuid,
+        key: String,
+    },
+    CredentialRenamed {
+        item_key: String,
+        id: Uuid,
+        old_key: String,
+        new_key: String,
+    },
+    /// the values differ, only their digests were compared
+    CredentialChanged {
+        item_key: String,
+        id: Uuid,
+        key: String,
+    },
+}
+
+impl VaultSnapshot {
+    /// Snapshot an unlocked vault. Each credential value is decrypted, hashed
+    /// and dropped.
+    pub fn from_vault(vw: &VaultWrapper) -> Result<Self, Error> {
+        let mut snapshot = Self::default();
+        for item in vw.list_items()? {
+            let item_snapshot = snapshot.add_item(item.id, &item.key);
+            for credential in item.credentials.values() {
+                let digest = vw
+                    .get_secret(&item.key, &credential.key)?
+                    .map(|value| Sha256::digest(value.expose_secret().as_bytes()).into());
+                item_snapshot.credentials.insert(
+                    credential.id,
+                    CredentialSnapshot {
+                        key: credential.key.clone(),
+                        digest,
+                    },
+                );
+            }
+        }
+        Ok(snapshot)
+    }
+
+    fn add_item(&mut self, id: Uuid, key: &str) -> &mut ItemSnapshot {
+        let item = self.items.entry(id).or_default();
+        item.key = key.to_string();
+        item
+    }
+}
+
+/// Compare two vaults. Items and credentials are matched by UUID, so a changed
+/// key is a rename, and then by key, so that items created separately in each
+/// vault (e.g. by two teammates) are compared rather than reported as removed
+/// and added.
+pub fn diff_vaults(a: &VaultSnapshot, b: &VaultSnapshot) -> Vec<VaultChange> {
+    let mut changes = Vec::new();
+    for (a_id, b_id) in match_by_id_then_key(&a.items, &b.items, |item| &item.key) {
+        match (a_id, b_id) {
+            (Some(id), None) => changes.push(VaultChange::ItemRemoved {
+                id,
+                key: a.items[&id].key.clone(),
+            }),
+            (None, Some(id)) => changes.push(VaultChange::ItemAdded {
+                id,
+                key: b.items[&id].key.clone(),
+            }),
+            (Some(a_id), Some(b_id)) => {
+                let (a_item, b_item) = (&a.items[&a_id], &b.items[&b_id]);
+                if a_item.key != b_item.key {
+                    changes.push(VaultChange::ItemRenamed {
+                        id: b_id,
+                        old_key: a_item.key.clone(),
+                        new_key: b_item.key.clone(),
+                    });
+                }
+                diff_credentials(a_item, b_item, &mut changes);
+            },
+            (None, None) => {},
+        }
+    }
+    changes
+}
+
+fn diff_credentials(a: &ItemSnapshot, b: &ItemSnapshot, changes: &mut Vec<VaultChange>) {
+    let item_key = &b.key;
+    let matched = match_by_id_then_key(&a.credentials, &b.credentials, |cred| &cred.key);
+    for (a_id, b_id) in matched {
+        match (a_id, b_id) {
+            (Some(id), None) => changes.push(VaultChange::CredentialRemoved {
+                item_key: item_key.clone(),
+                id,
+                key: a.credentials[&id].key.clone(),
+            }),
+            (None, Some(id)) => changes.push(VaultChange::CredentialAdded {
+                item_key: item_key.clone(),
+                id,
+                key: b.credentials[&id].key.clone(),
+            }),
+            (Some(a_id), Some(b_id)) => {
+                let (a_cred, b_cred) = (&a.credentials[&a_id], &b.credentials[&b_id]);
+                if a_cred.key != b_cred.key {
+                    changes.push(VaultChange::CredentialRenamed {
+                        item_key: item_key.clone(),
+                        id: b_id,
+                        old_key: a_cred.key.clone(),
+                        new_key: b_cred.key.clone(),
+                    });
+                }
+                if a_cred.digest != b_cred.digest {
+                    changes.push(VaultChange::CredentialChanged {
+                        item_key: item_key.clone(),
+                        id: b_id,
+                        key: b_cred.key.clone(),
+                    });
+                }
+            },
+            (None, None) => {},
+        }
+    }
+}
+
+// pairs of ids from a and b, matched by id and then by key, with None for the
+// unmatched side. Sorted by key (the key in b for renames).
+fn match_by_id_then_key<T>(
+    a: &BTreeMap<Uuid, T>,
+    b: &BTreeMap<Uuid, T>,
+    key: impl Fn(&T) -> &String,
+) -> Vec<(Option<Uuid>, Option<Uuid>)> {
+    let mut matched = Vec::new();
+    let mut a_unmatched = BTreeMap::new();
+    for (id, a_value) in a {
+        if let Some(b_value) = b.get(id) {
+            matched.push((key(b_value), Some(*id), Some(*id)));
+        } else {
+            a_unmatched.insert(key(a_value), *id);
+        }
+    }
+    let mut a_matched_by_key = BTreeSet::new();
+    for (id, value) in b.iter().filter(|(id, _)| !a.contains_key(id)) {
+        let a_id = a_unmatched.get(key(value)).copied();
+        if let Some(a_id) = a_id {
+            a_matched_by_key.insert(a_id);
+        }
+        matched.push((key(value), a_id, Some(*id)));
+    }
+    for (key, id) in a_unmatched {
+        if !a_matched_by_key.contains(&id) {
+            matched.push((key, Some(id), None));
+        }
+    }
+    matched.sort_by_key(|(key, ..)| *key);
+    matched
+        .into_iter()
+        .map(|(_, a_id, b_id)| (a_id, b_id))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (id, key, credentials as (id, key, value))
+    type TestItem<'a> = (Uuid, &'a str, &'a [(Uuid, &'a str, &'a str)]);
+
+    fn snapshot(items: &[TestItem]) -> VaultSnapshot {
+        let mut snapshot = VaultSnapshot::default();
+        for (item_id, item_key, credentials) in items {
+            let item = snapshot.add_item(*item_id, item_key);
+            for (cred_id, cred_key, value) in *credentials {
+                item.credentials.insert(
+                    *cred_id,
+                    CredentialSnapshot {
+                        key: cred_key.to_string(),
+                        digest: Some(Sha256::digest(value.as_bytes()).into()),
+                    },
+                );
+            }
+        }
+        snapshot
+    }
+
+    #[test]
+    fn test_diff_vaults() {
+        let ids = (0..8).map(|_| Uuid::new_v4()).collect::<Vec<_>>();
+        let a = snapshot(&[
+            (
+                ids[0],
+                "github",
+                &[(ids[1], "token", "t1"), (ids[2], "user", "me")],
+            ),
+            (ids[3], "old-db", &[(ids[4], "password", "pw")]),
+            (ids[5], "removed", &[]),
+        ]);
+        let b = snapshot(&[
+            (
+                ids[0],
+                "github",
+                &[(ids[1], "api-token", "t2"), (ids[6], "user", "me")],
+            ),
+            (ids[3], "db", &[(ids[4], "password", "pw")]),
+            (ids[7], "added", &[]),
+        ]);
+        assert_eq!(
+            diff_vaults(&a, &b),
+            [
+                VaultChange::ItemAdded {
+                    id: ids[7],
+                    key: "added".to_string(),
+                },
+                VaultChange::ItemRenamed {
+                    id: ids[3],
+                    old_key: "old-db".to_string(),
+                    new_key: "db".to_string(),
+                },
+                VaultChange::CredentialRenamed {
+                    item_key: "github".to_string(),
+                    id: ids[1],
+                    old_key: "token".to_string(),
+                    new_key: "api-token".to_string(),
+                },
+                VaultChange::CredentialChanged {
+                    item_key: "github".to_string(),
+                    id: ids[1],
+                    key: "api-token".to_string(),
+                },
+                VaultChange::ItemRemoved {
+                    id: ids[5],
+                    key: "removed".to_string(),
+                },
+            ]
+        );
+        assert!(diff_vaults(&a, &a).is_empty());
+    }
+}
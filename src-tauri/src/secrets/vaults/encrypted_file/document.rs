@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as b64;
+use serde_yaml_ng::Value as YamlValue;
+
+use crate::core::env_file::{quote_env_value, remove_env_var, replace_env_values};
+use crate::secrets::vaults::encrypted_file::FileMetadata;
+use crate::secrets::vaults::errors::Error;
+
+// top-level key (or variable, in dotenv files) holding the FileMetadata
+const METADATA_KEY: &str = "axo_pass";
+const DOTENV_METADATA_VAR: &str = "AXO_PASS_METADATA";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    Yaml,
+    Json,
+    Toml,
+    Dotenv,
+}
+
+impl FileFormat {
+    /// Guess the format from a file name: .yaml/.yml, .json, .toml, and .env
+    /// or .env.* (e.g. .env.production) for dotenv files.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let file_name = path.file_name()?.to_str()?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("yaml" | "yml") => Some(FileFormat::Yaml),
+            Some("json") => Some(FileFormat::Json),
+            Some("toml") => Some(FileFormat::Toml),
+            Some("env") => Some(FileFormat::Dotenv),
+            _ if file_name == ".env" || file_name.starts_with(".env.") => Some(FileFormat::Dotenv),
+            _ => None,
+        }
+    }
+}
+
+/// Type of a leaf value, kept with its encrypted value so that numbers and
+/// booleans are restored as such.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LeafType {
+    Str,
+    Int,
+    Float,
+    Bool,
+    Datetime,
+}
+
+impl LeafType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LeafType::Str => "str",
+            LeafType::Int => "int",
+            LeafType::Float => "float",
+            LeafType::Bool => "bool",
+            LeafType::Datetime => "datetime",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "str" => Some(LeafType::Str),
+            "int" => Some(LeafType::Int),
+            "float" => Some(LeafType::Float),
+            "bool" => Some(LeafType::Bool),
+            "datetime" => Some(LeafType::Datetime),
+            _ => None,
+        }
+    }
+}
+
+/// A scalar value of a document as a string, e.g. 42 is {Int, "42"}.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leaf {
+    pub leaf_type: LeafType,
+    pub value: String,
+}
+
+impl Leaf {
+    pub fn new(leaf_type: LeafType, value: impl Into<String>) -> Self {
+        Self {
+            leaf_type,
+            value: value.into(),
+        }
+    }
+}
+
+type LeafFn<'a> = dyn FnMut(&[String], Leaf) -> Result<Leaf, Error> + 'a;
+
+/// A parsed YAML, JSON, TOML or dotenv file. Keys keep their order, and
+/// dotenv files keep their comments and formatting.
+pub enum Document {
+    Yaml(YamlValue),
+    Json(YamlValue),
+    Toml(toml::Table),
+    Dotenv {
+        contents: String,
+        values: Vec<(String, String)>,
+        // new values by variable name, formatted for the file
+        replaced: HashMap<String, String>,
+        metadata: Option<String>,
+    },
+}
+
+impl Document {
+    pub fn parse(contents: &str, format: FileFormat) -> Result<Self, Error> {
+        let invalid = |e: &dyn std::fmt::Display| Error::EncryptedFileError(e.to_string());
+        let document = match format {
+            FileFormat::Yaml => {
+                Document::Yaml(serde_yaml_ng::from_str(contents).map_err(|e| invalid(&e))?)
+            },
+            FileFormat::Json => {
+                Document::Json(serde_json::from_str(contents).map_err(|e| invalid(&e))?)
+            },
+            FileFormat::Toml => Document::Toml(toml::from_str(contents).map_err(|e| invalid(&e))?),
+            FileFormat::Dotenv => {
+                let values = dotenvy::from_read_iter(contents.as_bytes())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| invalid(&e))?;
+                Document::Dotenv {
+                    contents: contents.to_string(),
+                    values,
+                    replaced: HashMap::new(),
+                    metadata: None,
+                }
+            },
+        };
+        if let Document::Yaml(value) | Document::Json(value) = &document
+            && !value.is_mapping()
+        {
+            return Err(invalid(&"expected a mapping at the top level"));
+        }
+        Ok(document)
+    }
+
+    /// Remove and return the metadata of an encrypted file, or None if the
+    /// file is not encrypted.
+    pub fn take_metadata(&mut self) -> Result<Option<FileMetadata>, Error> {
+        let invalid = |e: &dyn std::fmt::Display| {
+            Error::EncryptedFileError(format!("invalid {METADATA_KEY} metadata: {e}"))
+        };
+        match self {
+            Document::Yaml(value) | Document::Json(value) => {
+                let Some(metadata) = value
+                    .as_mapping_mut()
+                    .and_then(|mapping| mapping.remove(METADATA_KEY))
+                else {
+                    return Ok(None);
+                };
+                serde_yaml_ng::from_value(metadata)
+                    .map(Some)
+                    .map_err(|e| invalid(&e))
+            },
+            Document::Toml(table) => {
+                let Some(metadata) = table.remove(METADATA_KEY) else {
+                    return Ok(None);
+                };
+                metadata.try_into().map(Some).map_err(|e| invalid(&e))
+            },
+            Document::Dotenv {
+                contents, values, ..
+            } => {
+                let Some(index) = values
+                    .iter()
+                    .position(|(name, _)| name == DOTENV_METADATA_VAR)
+                else {
+                    return Ok(None);
+                };
+                let (_, metadata) = values.remove(index);
+                *contents = remove_env_var(contents, DOTENV_METADATA_VAR);
+                let json = b64.decode(metadata.trim()).map_err(|e| invalid(&e))?;
+                serde_json::from_slice(&json)
+                    .map(Some)
+                    .map_err(|e| invalid(&e))
+            },
+        }
+    }
+
+    pub fn set_metadata(&mut self, metadata: &FileMetadata) -> Result<(), Error> {
+        let invalid = |e: &dyn std::fmt::Display| Error::EncryptedFileError(e.to_string());
+        match self {
+            Document::Yaml(value) | Document::Json(value) => {
+                let metadata = serde_yaml_ng::to_value(metadata).map_err(|e| invalid(&e))?;
+                if let Some(mapping) = value.as_mapping_mut() {
+                    mapping.insert(METADATA_KEY.into(), metadata);
+                }
+            },
+            Document::Toml(table) => {
+                let metadata = toml::Value::try_from(metadata).map_err(|e| invalid(&e))?;
+                table.insert(METADATA_KEY.to_string(), metadata);
+            },
+            Document::Dotenv {
+                metadata: dotenv_metadata,
+                ..
+            } => {
+                let json = serde_json::to_vec(metadata).map_err(|e| invalid(&e))?;
+                *dotenv_metadata = Some(b64.encode(json));
+            },
+        }
+        Ok(())
+    }
+
+    /// Replace every scalar value with f(path, value), in document order. The
+    /// path holds the keys (and array indices) leading to the value. Nulls are
+    /// left as they are.
+    pub fn map_leaves(&mut self, f: &mut LeafFn) -> Result<(), Error> {
+        let mut path = Vec::new();
+        match self {
+            Document::Yaml(value) | Document::Json(value) => map_yaml_leaves(value, &mut path, f),
+            Document::Toml(table) => {
+                for (key, value) in table.iter_mut() {
+                    path.push(key.to_string());
+                    map_toml_leaves(value, &mut path, f)?;
+                    path.pop();
+                }
+                Ok(())
+            },
+            Document::Dotenv {
+                values, replaced, ..
+            } => {
+                for (name, value) in values.iter_mut() {
+                    let leaf = f(
+                        std::slice::from_ref(name),
+                        Leaf::new(LeafType::Str, value.as_str()),
+                    )?;
+                    if leaf.value != *value {
+                        replaced.insert(name.clone(), quote_env_value(&leaf.value));
+                        *value = leaf.value;
+                    }
+                }
+                Ok(())
+            },
+        }
+    }
+
+    pub fn to_string(&self) -> Result<String, Error> {
+        let invalid = |e: &dyn std::fmt::Display| Error::EncryptedFileError(e.to_string());
+        match self {
+            Document::Yaml(value) => serde_yaml_ng::to_string(value).map_err(|e| invalid(&e)),
+            Document::Json(value) => {
+                let mut json = serde_json::to_string_pretty(value).map_err(|e| invalid(&e))?;
+                json.push('\n');
+                Ok(json)
+            },
+            Document::Toml(table) => toml::to_string_pretty(table).map_err(|e| invalid(&e)),
+            Document::Dotenv {
+                contents,
+                replaced,
+                metadata,
+                ..
+            } => {
+                let mut dotenv = replace_env_values(contents, replaced);
+                if let Some(metadata) = metadata {
+                    if !dotenv.is_empty() && !dotenv.ends_with('\n') {
+                        dotenv.push('\n');
+                    }
+                    dotenv.push_str(&format!("{DOTENV_METADATA_VAR}={metadata}\n"));
+                }
+                Ok(dotenv)
+            },
+        }
+    }
+}
+
+fn map_yaml_leaves(
+    value: &mut YamlValue,
+    path: &mut Vec<String>,
+    f: &mut LeafFn,
+) -> Result<(), Error> {
+    let leaf = match value {
+        YamlValue::Mapping(mapping) => {
+            for (key, value) in mapping.iter_mut() {
+                let key = match key {
+                    YamlValue::String(key) => key.clone(),
+                    key => serde_yaml_ng::to_string(key)
+                        .map_err(|e| Error::EncryptedFileError(e.to_string()))?
+                        .trim_end()
+                        .to_string(),
+                };
+                path.push(key);
+                map_yaml_leaves(value, path, f)?;
+                path.pop();
+            }
+            return Ok(());
+        },
+        YamlValue::Sequence(sequence) => {
+            for (index, value) in sequence.iter_mut().enumerate() {
+                path.push(index.to_string());
+                map_yaml_leaves(value, path, f)?;
+                path.pop();
+            }
+            return Ok(());
+        },
+        YamlValue::Tagged(tagged) => return map_yaml_leaves(&mut tagged.value, path, f),
+        YamlValue::Null => return Ok(()),
+        YamlValue::String(s) => Leaf::new(LeafType::Str, s.as_str()),
+        YamlValue::Number(n) if n.is_f64() => Leaf::new(LeafType::Float, n.to_string()),
+        YamlValue::Number(n) => Leaf::new(LeafType::Int, n.to_string()),
+        YamlValue::Bool(b) => Leaf::new(LeafType::Bool, b.to_string()),
+    };
+    let leaf = f(path, leaf)?;
+    *value = match leaf.leaf_type {
+        LeafType::Str | LeafType::Datetime => YamlValue::String(leaf.value),
+        LeafType::Int => match leaf.value.parse::<i64>() {
+            Ok(n) => n.into(),
+            Err(_) => parse_leaf::<u64>(&leaf)?.into(),
+        },
+        LeafType::Float => parse_leaf::<f64>(&leaf)?.into(),
+        LeafType::Bool => parse_leaf::<bool>(&leaf)?.into(),
+    };
+    Ok(())
+}
+
+fn map_toml_leaves(
+    value: &mut toml::Value,
+    path: &mut Vec<String>,
+    f: &mut LeafFn,
+) -> Result<(), Error> {
+    let leaf = match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                path.push(key.to_string());
+                map_toml_leaves(value, path, f)?;
+                path.pop();
+            }
+            return Ok(());
+        },
+        toml::Value::Array(array) => {
+            for (index, value) in array.iter_mut().enumerate() {
+                path.push(index.to_string());
+                map_toml_leaves(value, path, f)?;
+                path.pop();
+            }
+            return Ok(());
+        },
+        toml::Value::String(s) => Leaf::new(LeafType::Str, s.as_str()),
+        toml::Value::Integer(n) => Leaf::new(LeafType::Int, n.to_string()),
+        toml::Value::Float(n) => Leaf::new(LeafType::Float, n.to_string()),
+        toml::Value::Boolean(b) => Leaf::new(LeafType::Bool, b.to_string()),
+        toml::Value::Datetime(datetime) => Leaf::new(LeafType::Datetime, datetime.to_string()),
+    };
+    let leaf = f(path, leaf)?;
+    *value = match leaf.leaf_type {
+        LeafType::Str => toml::Value::String(leaf.value),
+        LeafType::Int => toml::Value::Integer(parse_leaf(&leaf)?),
+        LeafType::Float => toml::Value::Float(parse_leaf(&leaf)?),
+        LeafType::Bool => toml::Value::Boolean(parse_leaf(&leaf)?),
+        LeafType::Datetime => toml::Value::Datetime(parse_leaf(&leaf)?),
+    };
+    Ok(())
+}
+
+fn parse_leaf<T: std::str::FromStr>(leaf: &Leaf) -> Result<T, Error> {
+    // the value itself is not included, as it may be a decrypted secret
+    leaf.value.parse().map_err(|_| {
+        Error::EncryptedFileError(format!("invalid {} value", leaf.leaf_type.as_str()))
+    })
+}
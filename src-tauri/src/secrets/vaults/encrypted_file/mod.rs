@@ -0,0 +1,233 @@
+mod document;
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+use aes_gcm::aead::OsRng;
+use aes_gcm::{Aes256Gcm, KeyInit};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD as b64;
+use regex::Regex;
+use secrecy::{ExposeSecret, SecretBox, SecretString};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub use crate::secrets::vaults::encrypted_file::document::FileFormat;
+use crate::secrets::vaults::encrypted_file::document::{Document, Leaf, LeafType};
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::vault::encrypted_blob::EncryptedBlob;
+use crate::secrets::vaults::vault::vault_member::{MemberKey, VaultMember};
+use crate::secrets::vaults::vault_wrapper::{age_identity_from_env, get_vault_encryption_key};
+
+// Format versions of encrypted files:
+// 1. initial version
+pub const ENCRYPTED_FILE_FORMAT_VERSION: u32 = 1;
+
+// an encrypted leaf value: nonce || ciphertext of the JSON-encoded value, with
+// the type of the original value
+static ENCRYPTED_VALUE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^ENC\[AES256_GCM,data:(?P<data>[A-Za-z0-9+/]+),type:(?P<type>[a-z]+)\]$").unwrap()
+});
+
+/// Stored in the encrypted file next to the values (see Document for where).
+/// The data key is wrapped for each member, like a vault's file key, and mac
+/// is a digest of every path and value, encrypted with the data key, so that
+/// values can't be removed, added or swapped without being noticed.
+#[derive(Serialize, Deserialize)]
+pub struct FileMetadata {
+    version: u32,
+    members: Vec<VaultMember>,
+    mac: String,
+}
+
+/// A decrypted file, holding its data key and members so that it can be
+/// encrypted again after editing.
+pub struct DecryptedFile {
+    pub plaintext: SecretString,
+    data_key: SecretBox<Vec<u8>>,
+    members: Vec<VaultMember>,
+    // encrypted values by path, reused for values that didn't change so that
+    // edits only change the lines that were edited
+    encrypted_values: HashMap<Vec<String>, (Leaf, String)>,
+}
+
+/// Encrypt every value of a YAML, JSON, TOML or dotenv file, keeping keys,
+/// structure and (for dotenv files) comments readable. Each value is encrypted
+/// with AES-GCM under a new data key, with its path in the document as
+/// associated data, and the data key is wrapped for each of member_keys.
+pub fn encrypt_file(
+    contents: &str,
+    format: FileFormat,
+    member_keys: &[MemberKey],
+) -> Result<String, Error> {
+    if member_keys.is_empty() {
+        return Err(Error::EncryptedFileError("no recipients".to_string()));
+    }
+    let data_key = Aes256Gcm::generate_key(OsRng);
+    let members = member_keys
+        .iter()
+        .map(|member_key| VaultMember::wrap(member_key, &data_key))
+        .collect::<Result<Vec<_>, _>>()?;
+    let file = DecryptedFile {
+        plaintext: contents.into(),
+        data_key: SecretBox::new(Box::new(data_key.to_vec())),
+        members,
+        encrypted_values: HashMap::new(),
+    };
+    file.encrypt(contents, format)
+}
+
+/// Decrypt a file encrypted with encrypt_file, unwrapping the data key with
+/// the age identity from AXO_AGE_IDENTITY or the local vault key.
+pub fn decrypt_file(contents: &str, format: FileFormat) -> Result<DecryptedFile, Error> {
+    decrypt_file_with(contents, format, unwrap_data_key)
+}
+
+fn decrypt_file_with(
+    contents: &str,
+    format: FileFormat,
+    unwrap: impl FnOnce(&[VaultMember]) -> Result<Vec<u8>, Error>,
+) -> Result<DecryptedFile, Error> {
+    let mut document = Document::parse(contents, format)?;
+    let metadata = document
+        .take_metadata()?
+        .ok_or_else(|| Error::EncryptedFileError("file is not encrypted".to_string()))?;
+    if metadata.version > ENCRYPTED_FILE_FORMAT_VERSION {
+        return Err(Error::VaultVersionTooNew(
+            metadata.version,
+            ENCRYPTED_FILE_FORMAT_VERSION,
+        ));
+    }
+    let data_key = SecretBox::new(Box::new(unwrap(&metadata.members)?));
+    let cipher = Aes256Gcm::new_from_slice(data_key.expose_secret())
+        .map_err(|_| Error::VaultFileKeyDecryptionError)?;
+
+    let mut mac = Sha256::new();
+    let mut encrypted_values = HashMap::new();
+    document.map_leaves(&mut |path, leaf| {
+        let Some((encrypted, leaf_type)) = parse_encrypted_value(&leaf.value) else {
+            // values added in plaintext are caught by the mac
+            update_mac(&mut mac, path, &leaf);
+            return Ok(leaf);
+        };
+        let value = encrypted.decrypt(&cipher, leaf_aad(path, leaf_type))?;
+        let decrypted = Leaf::new(leaf_type, value.expose_secret().as_str());
+        update_mac(&mut mac, path, &decrypted);
+        encrypted_values.insert(path.to_vec(), (decrypted.clone(), leaf.value));
+        Ok(decrypted)
+    })?;
+
+    let (expected_mac, _) = parse_encrypted_value(&metadata.mac)
+        .ok_or_else(|| Error::EncryptedFileError("invalid mac".to_string()))?;
+    let expected_mac = expected_mac.decrypt(&cipher, mac_aad())?;
+    if *expected_mac.expose_secret() != b64.encode(mac.finalize()) {
+        return Err(Error::EncryptedFileError(
+            "values were added, removed or moved since the file was encrypted".to_string(),
+        ));
+    }
+
+    Ok(DecryptedFile {
+        plaintext: document.to_string()?.into(),
+        data_key,
+        members: metadata.members,
+        encrypted_values,
+    })
+}
+
+impl DecryptedFile {
+    /// Encrypt contents (e.g. the edited plaintext) with this file's data key
+    /// and members. Values that didn't change keep their ciphertext.
+    pub fn encrypt(&self, contents: &str, format: FileFormat) -> Result<String, Error> {
+        let mut document = Document::parse(contents, format)?;
+        if document.take_metadata()?.is_some() {
+            return Err(Error::EncryptedFileError(
+                "file is already encrypted".to_string(),
+            ));
+        }
+        let cipher = Aes256Gcm::new_from_slice(self.data_key.expose_secret())
+            .map_err(|_| Error::VaultFileKeyEncryptionError)?;
+
+        let mut mac = Sha256::new();
+        document.map_leaves(&mut |path, leaf| {
+            update_mac(&mut mac, path, &leaf);
+            let encrypted = match self.encrypted_values.get(path) {
+                Some((previous, encrypted)) if *previous == leaf => encrypted.clone(),
+                _ => encrypt_value(&cipher, path, &leaf)?,
+            };
+            Ok(Leaf::new(LeafType::Str, encrypted))
+        })?;
+
+        let mac = b64.encode(mac.finalize());
+        let metadata = FileMetadata {
+            version: ENCRYPTED_FILE_FORMAT_VERSION,
+            members: self.members.clone(),
+            mac: format_encrypted_value(
+                EncryptedBlob::encrypt(&mac, &cipher, mac_aad())?,
+                LeafType::Str,
+            ),
+        };
+        document.set_metadata(&metadata)?;
+        document.to_string()
+    }
+}
+
+fn unwrap_data_key(members: &[VaultMember]) -> Result<Vec<u8>, Error> {
+    if let Some(identity) = age_identity_from_env()?
+        && let Some(data_key) = members
+            .iter()
+            .find_map(|member| member.unwrap_with_age_identity(&identity))
+    {
+        return Ok(data_key);
+    }
+    let user_encryption_key = get_vault_encryption_key()?;
+    members
+        .iter()
+        .find_map(|member| member.unwrap_with_vault_key(user_encryption_key.as_ref()))
+        .ok_or(Error::VaultFileKeyDecryptionError)
+}
+
+fn encrypt_value(cipher: &Aes256Gcm, path: &[String], leaf: &Leaf) -> Result<String, Error> {
+    let encrypted = EncryptedBlob::encrypt(&leaf.value, cipher, leaf_aad(path, leaf.leaf_type))?;
+    Ok(format_encrypted_value(encrypted, leaf.leaf_type))
+}
+
+fn format_encrypted_value(encrypted: EncryptedBlob<String>, leaf_type: LeafType) -> String {
+    format!(
+        "ENC[AES256_GCM,data:{},type:{}]",
+        b64.encode(encrypted.to_bytes()),
+        leaf_type.as_str()
+    )
+}
+
+fn parse_encrypted_value(value: &str) -> Option<(EncryptedBlob<String>, LeafType)> {
+    let captures = ENCRYPTED_VALUE_REGEX.captures(value)?;
+    let data = b64.decode(&captures["data"]).ok()?;
+    let leaf_type = LeafType::parse(&captures["type"])?;
+    Some((EncryptedBlob::from_bytes(&data).ok()?, leaf_type))
+}
+
+// the path and type of a value, so that encrypted values can't be moved to
+// another key or read as another type
+fn leaf_aad(path: &[String], leaf_type: LeafType) -> Vec<String> {
+    let mut aad = path.to_vec();
+    aad.push(leaf_type.as_str().to_string());
+    aad
+}
+
+fn mac_aad() -> Vec<String> {
+    vec!["mac".to_string()]
+}
+
+fn update_mac(mac: &mut Sha256, path: &[String], leaf: &Leaf) {
+    for key in path {
+        mac.update(key.as_bytes());
+        mac.update([0]);
+    }
+    mac.update(leaf.leaf_type.as_str());
+    mac.update([0]);
+    mac.update(leaf.value.as_bytes());
+    mac.update([0xff]);
+}
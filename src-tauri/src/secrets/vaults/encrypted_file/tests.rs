@@ -0,0 +1,176 @@
+use secrecy::ExposeSecret;
+
+use crate::secrets::vaults::encrypted_file::{
+    DecryptedFile, FileFormat, decrypt_file_with, encrypt_file,
+};
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::vault::vault_member::MemberKey;
+
+const YAML: &str = "\
+database:
+  host: db.internal
+  password: hunter2
+  port: 5432
+  replicas:
+  - password: replica-pass
+    weight: 0.5
+debug: false
+empty: null
+";
+
+const JSON: &str = r#"{
+  "api_key": "sk_live_123",
+  "limits": {
+    "rate": 100,
+    "burst": 1.5
+  },
+  "enabled": true
+}
+"#;
+
+const TOML: &str = r#"title = "app"
+token = "tok_secret"
+expires = 2030-01-02T03:04:05Z
+
+[server]
+port = 8080
+secret = "server-secret"
+"#;
+
+const DOTENV: &str = "\
+# production settings
+DATABASE_URL=postgres://user:pw@localhost/db
+export API_KEY=\"abc \\\"def\\\"\" # rotated monthly
+
+EMPTY=
+";
+
+fn identity() -> age::x25519::Identity {
+    age::x25519::Identity::generate()
+}
+
+fn encrypt(contents: &str, format: FileFormat, identity: &age::x25519::Identity) -> String {
+    encrypt_file(contents, format, &[MemberKey::Age(identity.to_public())]).unwrap()
+}
+
+fn decrypt(
+    contents: &str,
+    format: FileFormat,
+    identity: &age::x25519::Identity,
+) -> Result<DecryptedFile, Error> {
+    decrypt_file_with(contents, format, |members| {
+        members
+            .iter()
+            .find_map(|member| member.unwrap_with_age_identity(identity))
+            .ok_or(Error::VaultFileKeyDecryptionError)
+    })
+}
+
+#[test]
+fn test_yaml_round_trip() {
+    let identity = identity();
+    let encrypted = encrypt(YAML, FileFormat::Yaml, &identity);
+    assert!(!encrypted.contains("hunter2"));
+    assert!(!encrypted.contains("replica-pass"));
+    assert!(encrypted.contains("password: ENC[AES256_GCM,data:"));
+    assert!(encrypted.contains(",type:int]"));
+    assert!(encrypted.contains("empty: null"));
+    assert!(encrypted.contains("axo_pass:"));
+
+    let decrypted = decrypt(&encrypted, FileFormat::Yaml, &identity).unwrap();
+    assert_eq!(decrypted.plaintext.expose_secret(), YAML);
+
+    let other_identity = age::x25519::Identity::generate();
+    assert!(decrypt(&encrypted, FileFormat::Yaml, &other_identity).is_err());
+    assert!(decrypt(YAML, FileFormat::Yaml, &identity).is_err());
+}
+
+#[test]
+fn test_json_and_toml_round_trip() {
+    let identity = identity();
+    let encrypted = encrypt(JSON, FileFormat::Json, &identity);
+    assert!(!encrypted.contains("sk_live_123"));
+    let decrypted = decrypt(&encrypted, FileFormat::Json, &identity).unwrap();
+    assert_eq!(decrypted.plaintext.expose_secret(), JSON);
+
+    let encrypted = encrypt(TOML, FileFormat::Toml, &identity);
+    assert!(!encrypted.contains("tok_secret"));
+    assert!(encrypted.contains("[server]"));
+    let decrypted = decrypt(&encrypted, FileFormat::Toml, &identity).unwrap();
+    let expected: toml::Table = toml::from_str(TOML).unwrap();
+    let actual: toml::Table = toml::from_str(decrypted.plaintext.expose_secret()).unwrap();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_dotenv_round_trip() {
+    let identity = identity();
+    let encrypted = encrypt(DOTENV, FileFormat::Dotenv, &identity);
+    assert!(encrypted.starts_with("# production settings\nDATABASE_URL=ENC[AES256_GCM,data:"));
+    assert!(encrypted.contains("# rotated monthly\n"));
+    assert!(!encrypted.contains("postgres://"));
+    assert!(encrypted.contains("\nAXO_PASS_METADATA="));
+
+    let decrypted = decrypt(&encrypted, FileFormat::Dotenv, &identity).unwrap();
+    assert_eq!(
+        decrypted.plaintext.expose_secret(),
+        DOTENV.replace("EMPTY=\n", "EMPTY=\"\"\n")
+    );
+}
+
+#[test]
+fn test_tampering_is_detected() {
+    let identity = identity();
+    let encrypted = encrypt(YAML, FileFormat::Yaml, &identity);
+    let encrypted_value = |key: &str| {
+        encrypted
+            .lines()
+            .find_map(|line| line.trim().strip_prefix(key))
+            .unwrap()
+            .to_string()
+    };
+
+    // values are bound to their path
+    let host = encrypted_value("host: ");
+    let password = encrypted_value("password: ");
+    let swapped = encrypted
+        .replacen(&host, "SWAP", 1)
+        .replacen(&password, &host, 1)
+        .replacen("SWAP", &password, 1);
+    assert!(decrypt(&swapped, FileFormat::Yaml, &identity).is_err());
+
+    // and to their type
+    let port = encrypted_value("port: ");
+    let retyped = encrypted.replace(&port, &port.replace("type:int", "type:str"));
+    assert!(decrypt(&retyped, FileFormat::Yaml, &identity).is_err());
+
+    // removing or adding values changes the mac
+    let removed = encrypted.replace(&format!("  host: {host}\n"), "");
+    assert!(decrypt(&removed, FileFormat::Yaml, &identity).is_err());
+    let added = encrypted.replace("debug: ", "extra: plaintext\ndebug: ");
+    assert!(decrypt(&added, FileFormat::Yaml, &identity).is_err());
+}
+
+#[test]
+fn test_reencrypt_keeps_unchanged_values() {
+    let identity = identity();
+    let encrypted = encrypt(YAML, FileFormat::Yaml, &identity);
+    let decrypted = decrypt(&encrypted, FileFormat::Yaml, &identity).unwrap();
+
+    let edited = decrypted
+        .plaintext
+        .expose_secret()
+        .replace("hunter2", "hunter3");
+    let reencrypted = decrypted.encrypt(&edited, FileFormat::Yaml).unwrap();
+    let changed = encrypted
+        .lines()
+        .zip(reencrypted.lines())
+        .filter(|(before, after)| before != after)
+        .map(|(before, _)| before.trim().split(':').next().unwrap().to_string())
+        .collect::<Vec<_>>();
+    // the edited value and the mac
+    assert_eq!(changed, ["password", "mac"]);
+
+    let decrypted = decrypt(&reencrypted, FileFormat::Yaml, &identity).unwrap();
+    assert_eq!(decrypted.plaintext.expose_secret(), edited);
+}
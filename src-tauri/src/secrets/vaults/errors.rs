@@ -1,6 +1,7 @@
 use thiserror::Error;
 
 use crate::secrets::keychain::errors::KeychainError;
+use crate::secrets::vaults::vault::credential_kind::CredentialKind;
 
 #[derive(Error, Debug)]
 #[allow(clippy::enum_variant_names)]
@@ -41,6 +42,33 @@ pub enum Error {
     #[error("Failed to save vault: {0}")]
     VaultWriteError(#[source] std::io::Error),
 
+    #[error("Failed to lock vault file: {0}")]
+    VaultLockError(#[source] std::io::Error),
+
+    #[error(
+        "Vault file {0} was changed by another process since it was unlocked, please try again"
+    )]
+    VaultModifiedOnDisk(String),
+
+    #[error(
+        "Vault was written by a newer version of Axo Pass (format version {0}, this version \
+         supports up to {1}), please update Axo Pass"
+    )]
+    VaultVersionTooNew(u32, u32),
+
+    #[error("Failed to migrate vault: {0}")]
+    VaultMigrationError(String),
+
+    #[error("Vault file does not match its manifest, it may have been tampered with: {0}")]
+    VaultManifestMismatch(String),
+
+    #[error(
+        "Vault is at revision {revision}, but revision {seen} was already seen on this device; \
+         the vault file may have been replaced with an older copy \
+         (set AXO_ALLOW_VAULT_ROLLBACK=1 to unlock it anyway)"
+    )]
+    VaultRolledBack { revision: u64, seen: u64 },
+
     #[error("Failed to update vault key: {0}")]
     VaultKeyUpdateFailed(#[source] std::io::Error),
 
@@ -50,12 +78,27 @@ pub enum Error {
     #[error("Invalid vault item reference: {0}")]
     InvalidVaultItemReference(String),
 
+    #[error("Could not apply reference modifier: {0}")]
+    ReferenceModifierError(String),
+
     #[error("Failed to create new encryption: {0}")]
     KeyCreationFailed(KeychainError),
 
     #[error("Could not retrieve key from keychain: {0}")]
     KeyRetrievalFailed(KeychainError),
 
+    #[error("Failed to read vault key file: {0}")]
+    VaultKeyFileReadError(#[source] std::io::Error),
+
+    #[error("Failed to write vault key file: {0}")]
+    VaultKeyFileWriteError(#[source] std::io::Error),
+
+    #[error("Invalid vault key file: {0}")]
+    InvalidVaultKeyFile(String),
+
+    #[error("Invalid age identity in AXO_AGE_IDENTITY or AXO_AGE_IDENTITY_FILE: {0}")]
+    InvalidAgeIdentity(String),
+
     #[error("Could not retrieve secret {0} from vault: {1}")]
     SecretRetrievalFailed(String, #[source] anyhow::Error),
 
@@ -68,6 +111,33 @@ pub enum Error {
     #[error("Invalid credential key, only a-zA-Z0-9-_ allowed: {0}")]
     InvalidCredentialKey(String),
 
+    #[error("Item already exists: {0}")]
+    DuplicateItemKey(String),
+
+    #[error("Credential already exists: {0}")]
+    DuplicateCredentialKey(String),
+
+    #[error("Attachment not found: {0}")]
+    InvalidAttachmentKey(String),
+
+    #[error("Failed to read attachment file: {0}")]
+    AttachmentReadError(#[source] std::io::Error),
+
+    #[error("Failed to write attachment file: {0}")]
+    AttachmentWriteError(#[source] std::io::Error),
+
+    #[error("Invalid tag, only a-zA-Z0-9-_ allowed: {0}")]
+    InvalidTag(String),
+
+    #[error("Credential version {0} not found in history")]
+    InvalidCredentialVersion(u32),
+
+    #[error("Invalid {0} value: {1}")]
+    InvalidCredentialValue(CredentialKind, String),
+
+    #[error("Invalid one-time password: {0}")]
+    InvalidOtp(String),
+
     #[error("Credential secret cannot be empty")]
     InvalidEmptyCredentialValue,
 
@@ -79,6 +149,18 @@ pub enum Error {
 
     #[error("Failed to import vault: {0}")]
     VaultImportError(String),
+
+    #[error("Failed to render vault: {0}")]
+    VaultRenderError(String),
+
+    #[error("Invalid encrypted file: {0}")]
+    EncryptedFileError(String),
+
+    #[error("Invalid vault member: {0}")]
+    InvalidVaultMember(String),
+
+    #[error("Vault member not found: {0}")]
+    VaultMemberNotFound(String),
 }
 
 impl From<Error> for String {
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::importers::{ImportedEntry, label_from_camel_case};
+use crate::secrets::vaults::vault::credential_kind::CredentialKind;
+
+// custom field types
+const FIELD_TYPE_HIDDEN: u8 = 1;
+const FIELD_TYPE_LINKED: u8 = 3;
+
+// card and identity fields that are concealed in Bitwarden
+const CONCEALED_FIELDS: [&str; 5] = ["number", "code", "ssn", "passportNumber", "licenseNumber"];
+
+#[derive(Deserialize)]
+struct BitwardenExport {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    folders: Vec<BitwardenFolder>,
+    #[serde(default)]
+    items: Vec<BitwardenItem>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenFolder {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenItem {
+    name: Option<String>,
+    folder_id: Option<String>,
+    notes: Option<String>,
+    fields: Option<Vec<BitwardenField>>,
+    login: Option<BitwardenLogin>,
+    card: Option<Map<String, Value>>,
+    identity: Option<Map<String, Value>>,
+    ssh_key: Option<BitwardenSshKey>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenField {
+    name: Option<String>,
+    value: Option<String>,
+    #[serde(rename = "type", default)]
+    field_type: u8,
+}
+
+#[derive(Deserialize)]
+struct BitwardenLogin {
+    username: Option<String>,
+    password: Option<String>,
+    totp: Option<String>,
+    uris: Option<Vec<BitwardenUri>>,
+}
+
+#[derive(Deserialize)]
+struct BitwardenUri {
+    uri: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BitwardenSshKey {
+    private_key: Option<String>,
+}
+
+/// Parse an unencrypted Bitwarden JSON export. Folders become tags.
+pub fn parse(data: &[u8]) -> Result<Vec<ImportedEntry>, Error> {
+    let export: BitwardenExport = serde_json::from_slice(data)
+        .map_err(|e| Error::VaultImportError(format!("Invalid Bitwarden export: {e}")))?;
+    if export.encrypted {
+        return Err(Error::VaultImportError(
+            "Encrypted Bitwarden exports are not supported, export as unencrypted JSON".to_string(),
+        ));
+    }
+    let folders = export
+        .folders
+        .iter()
+        .map(|folder| (folder.id.as_str(), folder.name.as_str()))
+        .collect::<HashMap<_, _>>();
+
+    let entries = export
+        .items
+        .into_iter()
+        .map(|item| {
+            let mut entry = ImportedEntry::new(item.name.as_deref());
+            if let Some(folder) = item.folder_id.as_deref().and_then(|id| folders.get(id)) {
+                entry.tags.push(folder.to_string());
+            }
+            if let Some(login) = item.login {
+                let field = |value: Option<String>| value.unwrap_or_default();
+                entry.add_field("username", CredentialKind::Username, &field(login.username));
+                entry.add_field("password", CredentialKind::Password, &field(login.password));
+                entry.add_field("totp", CredentialKind::TotpSeed, &field(login.totp));
+                for uri in login.uris.into_iter().flatten().filter_map(|u| u.uri) {
+                    entry.add_field("url", CredentialKind::Url, &uri);
+                }
+            }
+            for (name, value) in item.card.iter().chain(item.identity.iter()).flatten() {
+                let Some(value) = value.as_str() else {
+                    continue;
+                };
+                let kind = if CONCEALED_FIELDS.contains(&name.as_str()) {
+                    CredentialKind::Password
+                } else {
+                    CredentialKind::Note
+                };
+                entry.add_field(&label_from_camel_case(name), kind, value);
+            }
+            if let Some(private_key) = item.ssh_key.and_then(|key| key.private_key) {
+                entry.add_field("private key", CredentialKind::SshKey, &private_key);
+            }
+            for field in item.fields.into_iter().flatten() {
+                let kind = match field.field_type {
+                    FIELD_TYPE_LINKED => continue,
+                    FIELD_TYPE_HIDDEN => CredentialKind::Password,
+                    _ => CredentialKind::Note,
+                };
+                let name = field.name.unwrap_or_default();
+                entry.add_field(&name, kind, &field.value.unwrap_or_default());
+            }
+            entry.add_field(
+                "notes",
+                CredentialKind::Note,
+                &item.notes.unwrap_or_default(),
+            );
+            entry
+        })
+        .collect();
+    Ok(entries)
+}
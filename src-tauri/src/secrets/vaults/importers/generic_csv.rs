@@ -0,0 +1,70 @@
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::importers::{ImportedEntry, kind_from_label};
+
+// columns used as the item title when no title column is given
+const TITLE_COLUMNS: [&str; 3] = ["title", "name", "item"];
+
+/// Which columns of a CSV file to import. Each row becomes an item titled by
+/// title_column (by default the first of title, name or item). columns maps
+/// column headers to credential keys; if it is empty, every other column is
+/// imported, keyed by its header. Kinds are guessed from the credential keys,
+/// e.g. username, url and notes.
+#[derive(Default)]
+pub struct CsvMapping {
+    pub title_column: Option<String>,
+    pub columns: Vec<(String, String)>,
+}
+
+pub fn parse(data: &[u8], mapping: &CsvMapping) -> Result<Vec<ImportedEntry>, Error> {
+    let invalid = |e: &dyn std::fmt::Display| Error::VaultImportError(format!("Invalid CSV: {e}"));
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::Headers)
+        .from_reader(data);
+    let headers = reader
+        .headers()
+        .map_err(|e| invalid(&e))?
+        .iter()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let column_index = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.eq_ignore_ascii_case(name))
+            .ok_or_else(|| invalid(&format!("no column named {name}")))
+    };
+
+    let title_index = match &mapping.title_column {
+        Some(title_column) => column_index(title_column)?,
+        None => TITLE_COLUMNS
+            .iter()
+            .find_map(|name| column_index(name).ok())
+            .ok_or_else(|| invalid(&"no title column, set one with --title-column"))?,
+    };
+    let columns = if mapping.columns.is_empty() {
+        headers
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != title_index)
+            .map(|(index, header)| (index, header.clone()))
+            .collect::<Vec<_>>()
+    } else {
+        mapping
+            .columns
+            .iter()
+            .map(|(column, label)| Ok((column_index(column)?, label.clone())))
+            .collect::<Result<Vec<_>, Error>>()?
+    };
+
+    let mut entries = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| invalid(&e))?;
+        let mut entry = ImportedEntry::new(record.get(title_index));
+        for (index, label) in &columns {
+            let value = record.get(*index).unwrap_or_default();
+            entry.add_field(label, kind_from_label(label), value);
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
@@ -0,0 +1,100 @@
+use roxmltree::{Document, Node};
+
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::importers::ImportedEntry;
+use crate::secrets::vaults::vault::credential_kind::CredentialKind;
+
+/// Parse a KeePass 2 XML export. Entries in the recycle bin and previous
+/// versions of entries (History) are skipped. The names of the groups an entry
+/// is in, apart from the root group, and its tags become tags.
+pub fn parse(data: &[u8]) -> Result<Vec<ImportedEntry>, Error> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        Error::VaultImportError(format!("Invalid KeePass XML export: {e}"))
+    };
+    let xml = std::str::from_utf8(data).map_err(|e| invalid(&e))?;
+    let doc = Document::parse(xml).map_err(|e| invalid(&e))?;
+
+    let root = doc.root_element();
+    if !root.has_tag_name("KeePassFile") {
+        return Err(invalid(&"missing KeePassFile element"));
+    }
+    let recycle_bin = child(root, "Meta")
+        .and_then(|meta| child_text(meta, "RecycleBinUUID"))
+        .filter(|uuid| !uuid.is_empty());
+    let root_group = child(root, "Root")
+        .and_then(|r| child(r, "Group"))
+        .ok_or_else(|| invalid(&"missing Root group"))?;
+
+    let mut entries = Vec::new();
+    add_group_entries(&mut entries, root_group, &[], recycle_bin);
+    Ok(entries)
+}
+
+fn add_group_entries(
+    entries: &mut Vec<ImportedEntry>,
+    group: Node,
+    group_names: &[String],
+    recycle_bin: Option<&str>,
+) {
+    for node in group.children().filter(Node::is_element) {
+        match node.tag_name().name() {
+            "Entry" => entries.push(parse_entry(node, group_names)),
+            "Group" => {
+                if recycle_bin.is_some() && child_text(node, "UUID") == recycle_bin {
+                    continue;
+                }
+                let mut names = group_names.to_vec();
+                names.extend(child_text(node, "Name").map(str::to_string));
+                add_group_entries(entries, node, &names, recycle_bin);
+            },
+            _ => {},
+        }
+    }
+}
+
+fn parse_entry(node: Node, group_names: &[String]) -> ImportedEntry {
+    let strings = node
+        .children()
+        .filter(|n| n.has_tag_name("String"))
+        .filter_map(|string| {
+            let key = child_text(string, "Key")?;
+            let value = child(string, "Value")?;
+            let protected = value.attribute("ProtectInMemory") == Some("True");
+            Some((key, value.text().unwrap_or_default(), protected))
+        })
+        .collect::<Vec<_>>();
+
+    let title = strings
+        .iter()
+        .find(|(key, _, _)| *key == "Title")
+        .map(|(_, value, _)| *value);
+    let mut entry = ImportedEntry::new(title);
+    entry.tags.extend(group_names.iter().cloned());
+    if let Some(tags) = child_text(node, "Tags") {
+        entry
+            .tags
+            .extend(tags.split([';', ',']).map(|tag| tag.trim().to_string()));
+    }
+    for (key, value, protected) in strings {
+        let (label, kind) = match key {
+            "Title" => continue,
+            "UserName" => ("username", CredentialKind::Username),
+            "Password" => ("password", CredentialKind::Password),
+            "URL" => ("url", CredentialKind::Url),
+            "Notes" => ("notes", CredentialKind::Note),
+            "otp" | "TimeOtp-Secret-Base32" => ("totp", CredentialKind::TotpSeed),
+            _ if protected => (key, CredentialKind::Password),
+            _ => (key, CredentialKind::Note),
+        };
+        entry.add_field(label, kind, value);
+    }
+    entry
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).map(|n| n.text().unwrap_or_default())
+}
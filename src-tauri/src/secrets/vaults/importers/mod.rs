@@ -0,0 +1,241 @@
+mod bitwarden;
+mod generic_csv;
+mod keepass;
+mod one_password;
+
+#[cfg(test)]
+mod tests;
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use secrecy::{ExposeSecret, SecretString};
+use uuid::Uuid;
+
+use crate::secrets::vaults::errors::Error;
+pub use crate::secrets::vaults::importers::generic_csv::CsvMapping;
+use crate::secrets::vaults::vault::credential_kind::CredentialKind;
+use crate::secrets::vaults::vault::{VaultItemCredentialOverview, VaultItemOverview};
+use crate::secrets::vaults::vault_wrapper::normalized_key;
+
+/// Formats `ap vault import-from` can read. All of them are unencrypted
+/// exports.
+pub enum ImportFormat {
+    /// Bitwarden JSON export (unencrypted)
+    Bitwarden,
+    /// 1Password .1pux export (a zip file holding export.data)
+    OnePassword,
+    /// KeePass 2 XML export
+    KeePass,
+    /// CSV file with a header row, see CsvMapping
+    Csv(CsvMapping),
+}
+
+/// An entry read from another password manager's export, before keys are
+/// assigned. Fields are kept in the order they appear in the export.
+pub struct ImportedEntry {
+    pub title: String,
+    pub tags: Vec<String>,
+    pub fields: Vec<ImportedField>,
+}
+
+pub struct ImportedField {
+    pub label: String,
+    pub kind: CredentialKind,
+    pub value: SecretString,
+}
+
+impl ImportedEntry {
+    fn new(title: Option<&str>) -> Self {
+        Self {
+            title: title
+                .map(str::trim)
+                .filter(|title| !title.is_empty())
+                .unwrap_or("Untitled")
+                .to_string(),
+            tags: Vec::new(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// Add a field, skipping empty values.
+    fn add_field(&mut self, label: &str, kind: CredentialKind, value: &str) {
+        if value.trim().is_empty() {
+            return;
+        }
+        self.fields.push(ImportedField {
+            label: label.trim().to_string(),
+            kind,
+            value: value.to_string().into(),
+        });
+    }
+}
+
+/// An entry converted to a new item, with one credential per field. values
+/// holds each credential's value by credential ID.
+pub struct ImportedItem {
+    pub item: VaultItemOverview,
+    pub values: BTreeMap<Uuid, SecretString>,
+}
+
+/// The items to import, and the entries that are left out, see plan_import.
+pub struct ImportPlan {
+    pub items: Vec<ImportedItem>,
+    /// titles of entries without any values
+    pub empty: Vec<String>,
+    /// entries and fields that couldn't be converted
+    pub rejected: Vec<RejectedImport>,
+}
+
+/// An entry, or a field of an entry if field is set, that couldn't be
+/// converted, with the reason.
+pub struct RejectedImport {
+    pub title: String,
+    pub field: Option<String>,
+    pub reason: String,
+}
+
+/// Read the entries of an export file.
+pub fn read_entries(format: &ImportFormat, path: &Path) -> Result<Vec<ImportedEntry>, Error> {
+    let data = fs::read(path).map_err(Error::VaultReadError)?;
+    match format {
+        ImportFormat::Bitwarden => bitwarden::parse(&data),
+        ImportFormat::OnePassword => one_password::parse(&data),
+        ImportFormat::KeePass => keepass::parse(&data),
+        ImportFormat::Csv(mapping) => generic_csv::parse(&data, mapping),
+    }
+}
+
+/// Convert entries into items. Item keys are derived from titles and
+/// credential keys from field labels, made unique by appending a number (e.g.
+/// github-2) both within the import and against existing_item_keys, so that
+/// importing never overwrites existing items. Values that are not valid for
+/// the kind of their field are imported as passwords or notes instead.
+/// Entries without fields, and entries or fields that can't be converted, are
+/// left out and listed in the plan.
+pub fn plan_import(
+    entries: Vec<ImportedEntry>,
+    existing_item_keys: impl IntoIterator<Item = String>,
+) -> ImportPlan {
+    let mut item_keys = existing_item_keys.into_iter().collect::<HashSet<_>>();
+    let mut plan = ImportPlan {
+        items: Vec::new(),
+        empty: Vec::new(),
+        rejected: Vec::new(),
+    };
+    for entry in entries {
+        if entry.fields.is_empty() {
+            log::debug!("Skipping entry without fields: {}", entry.title);
+            plan.empty.push(entry.title);
+            continue;
+        }
+        let item_key = unique_key(derived_key(&entry.title, "item"), &mut item_keys);
+        let mut item = match VaultItemOverview::try_new(&entry.title, &item_key) {
+            Ok(item) => item,
+            Err(e) => {
+                log::warn!("Not importing entry {}: {e}", entry.title);
+                plan.rejected.push(RejectedImport {
+                    title: entry.title,
+                    field: None,
+                    reason: e.to_string(),
+                });
+                continue;
+            },
+        };
+        let mut tags = entry
+            .tags
+            .iter()
+            .filter(|tag| !tag.trim().is_empty())
+            .map(|tag| derived_key(tag, "tag"))
+            .collect::<Vec<_>>();
+        tags.sort();
+        tags.dedup();
+        item.tags = tags;
+
+        let mut values = BTreeMap::new();
+        let mut cred_keys = HashSet::new();
+        for field in entry.fields {
+            let cred_key = unique_key(derived_key(&field.label, "field"), &mut cred_keys);
+            let mut cred = match VaultItemCredentialOverview::try_new(&field.label, &cred_key) {
+                Ok(cred) => cred,
+                Err(e) => {
+                    log::warn!(
+                        "Not importing field {} of {}: {e}",
+                        field.label,
+                        entry.title
+                    );
+                    plan.rejected.push(RejectedImport {
+                        title: entry.title.clone(),
+                        field: Some(field.label),
+                        reason: e.to_string(),
+                    });
+                    continue;
+                },
+            };
+            cred.kind = match field.kind.validate(field.value.expose_secret()) {
+                Ok(()) => field.kind,
+                Err(_) if field.kind.is_concealed() => CredentialKind::Password,
+                Err(_) => CredentialKind::Note,
+            };
+            values.insert(cred.id, field.value);
+            item.credentials.insert(cred.id, cred);
+        }
+        plan.items.push(ImportedItem { item, values });
+    }
+    plan
+}
+
+/// Derive a valid key from a title or label: characters other than letters,
+/// digits, - and _ are replaced, and labels that don't start with a letter or
+/// are too short are prefixed with fallback (e.g. "2FA" becomes field-2fa).
+pub(crate) fn derived_key(label: &str, fallback: &str) -> String {
+    let sanitized = label
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => ' ',
+        })
+        .collect::<String>();
+    let sanitized = sanitized.trim_matches(|c: char| c.is_whitespace() || c == '-' || c == '_');
+    normalized_key(sanitized)
+        .or_else(|| normalized_key(&format!("{fallback} {sanitized}")))
+        .unwrap_or_else(|| fallback.to_string())
+}
+
+pub(crate) fn unique_key(key: String, taken: &mut HashSet<String>) -> String {
+    let mut unique = key.clone();
+    let mut n = 2;
+    while taken.contains(&unique) {
+        unique = format!("{key}-{n}");
+        n += 1;
+    }
+    taken.insert(unique.clone());
+    unique
+}
+
+/// Guess the kind of a field from its label, for formats without field types.
+fn kind_from_label(label: &str) -> CredentialKind {
+    match label.trim().to_lowercase().as_str() {
+        "username" | "user" | "user name" | "login" | "login name" | "email" | "e-mail" => {
+            CredentialKind::Username
+        },
+        "url" | "uri" | "website" | "web site" | "login uri" => CredentialKind::Url,
+        "notes" | "note" | "comments" => CredentialKind::Note,
+        "totp" | "otp" | "one-time password" | "login totp" => CredentialKind::TotpSeed,
+        _ => CredentialKind::Password,
+    }
+}
+
+/// Turn a camelCase field name into words, e.g. cardholderName becomes
+/// "cardholder name".
+fn label_from_camel_case(name: &str) -> String {
+    let mut label = String::new();
+    for c in name.chars() {
+        if c.is_uppercase() && !label.is_empty() {
+            label.push(' ');
+        }
+        label.extend(c.to_lowercase());
+    }
+    label
+}
@@ -0,0 +1,190 @@
+use std::io::{Cursor, Read};
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::importers::ImportedEntry;
+use crate::secrets::vaults::vault::credential_kind::CredentialKind;
+
+const EXPORT_DATA_FILE: &str = "export.data";
+
+#[derive(Deserialize)]
+struct OnePuxExport {
+    accounts: Vec<OnePuxAccount>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxAccount {
+    #[serde(default)]
+    vaults: Vec<OnePuxVault>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxVault {
+    attrs: OnePuxVaultAttrs,
+    #[serde(default)]
+    items: Vec<OnePuxItem>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxVaultAttrs {
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxItem {
+    state: Option<String>,
+    overview: OnePuxOverview,
+    details: OnePuxDetails,
+}
+
+#[derive(Deserialize)]
+struct OnePuxOverview {
+    title: Option<String>,
+    url: Option<String>,
+    #[serde(default)]
+    urls: Vec<OnePuxUrl>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxUrl {
+    label: Option<String>,
+    url: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnePuxDetails {
+    #[serde(default)]
+    login_fields: Vec<OnePuxLoginField>,
+    notes_plain: Option<String>,
+    #[serde(default)]
+    sections: Vec<OnePuxSection>,
+    password: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OnePuxLoginField {
+    #[serde(default)]
+    value: String,
+    name: Option<String>,
+    designation: Option<String>,
+    field_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxSection {
+    #[serde(default)]
+    fields: Vec<OnePuxSectionField>,
+}
+
+#[derive(Deserialize)]
+struct OnePuxSectionField {
+    title: Option<String>,
+    id: Option<String>,
+    #[serde(default)]
+    value: Map<String, Value>,
+}
+
+/// Parse a 1Password .1pux export, a zip file with the items of every
+/// account and vault in export.data. Vault names and archived state become
+/// tags.
+pub fn parse(data: &[u8]) -> Result<Vec<ImportedEntry>, Error> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        Error::VaultImportError(format!("Invalid 1Password export: {e}"))
+    };
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|e| invalid(&e))?;
+    let mut export_data = Vec::new();
+    archive
+        .by_name(EXPORT_DATA_FILE)
+        .map_err(|e| invalid(&e))?
+        .read_to_end(&mut export_data)
+        .map_err(|e| invalid(&e))?;
+    let export: OnePuxExport = serde_json::from_slice(&export_data).map_err(|e| invalid(&e))?;
+
+    let mut entries = Vec::new();
+    for vault in export
+        .accounts
+        .into_iter()
+        .flat_map(|account| account.vaults)
+    {
+        for item in vault.items {
+            let mut entry = ImportedEntry::new(item.overview.title.as_deref());
+            entry.tags.extend(vault.attrs.name.clone());
+            entry.tags.extend(item.overview.tags);
+            if item.state.as_deref() == Some("archived") {
+                entry.tags.push("archived".to_string());
+            }
+            add_item_fields(&mut entry, item.details);
+            if item.overview.urls.is_empty() {
+                let url = item.overview.url.unwrap_or_default();
+                entry.add_field("website", CredentialKind::Url, &url);
+            }
+            for url in item.overview.urls {
+                let label = url.label.filter(|l| !l.is_empty());
+                entry.add_field(
+                    label.as_deref().unwrap_or("website"),
+                    CredentialKind::Url,
+                    &url.url,
+                );
+            }
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+fn add_item_fields(entry: &mut ImportedEntry, details: OnePuxDetails) {
+    for field in details.login_fields {
+        let label = field.designation.or(field.name).unwrap_or_default();
+        let kind = match (label.as_str(), field.field_type.as_deref()) {
+            ("username", _) => CredentialKind::Username,
+            (_, Some("P")) | ("password", _) => CredentialKind::Password,
+            _ => CredentialKind::Note,
+        };
+        entry.add_field(&label, kind, &field.value);
+    }
+    let password = details.password.unwrap_or_default();
+    entry.add_field("password", CredentialKind::Password, &password);
+    for field in details
+        .sections
+        .into_iter()
+        .flat_map(|section| section.fields)
+    {
+        let label = field
+            .title
+            .filter(|title| !title.is_empty())
+            .or(field.id)
+            .unwrap_or_default();
+        // the value is an object with a single key naming its type
+        let Some((value_type, value)) = field.value.into_iter().next() else {
+            continue;
+        };
+        let (kind, value) = match (value_type.as_str(), value) {
+            ("concealed" | "creditCardNumber", Value::String(s)) => (CredentialKind::Password, s),
+            ("totp", Value::String(s)) => (CredentialKind::TotpSeed, s),
+            ("url", Value::String(s)) => (CredentialKind::Url, s),
+            (_, Value::String(s)) => (CredentialKind::Note, s),
+            (_, Value::Number(n)) => (CredentialKind::Note, n.to_string()),
+            ("email", Value::Object(email)) => match email.get("email_address") {
+                Some(Value::String(s)) => (CredentialKind::Username, s.clone()),
+                _ => continue,
+            },
+            ("sshKey", Value::Object(key)) => match key.get("privateKey") {
+                Some(Value::String(s)) => (CredentialKind::SshKey, s.clone()),
+                _ => continue,
+            },
+            (value_type, _) => {
+                log::debug!("Skipping 1Password field {label} of type {value_type}");
+                continue;
+            },
+        };
+        entry.add_field(&label, kind, &value);
+    }
+    let notes = details.notes_plain.unwrap_or_default();
+    entry.add_field("notes", CredentialKind::Note, &notes);
+}
@@ -0,0 +1,183 @@
+use secrecy::ExposeSecret;
+
+use crate::secrets::vaults::importers::{
+    CsvMapping, ImportedEntry, ImportedItem, bitwarden, generic_csv, keepass, one_password,
+    plan_import,
+};
+use crate::secrets::vaults::vault::credential_kind::CredentialKind;
+
+// (label, kind, value) of each field
+fn fields(entry: &ImportedEntry) -> Vec<(&str, CredentialKind, &str)> {
+    entry
+        .fields
+        .iter()
+        .map(|f| (f.label.as_str(), f.kind, f.value.expose_secret()))
+        .collect()
+}
+
+// (key, kind, value) of each credential, sorted by key
+fn credentials(item: &ImportedItem) -> Vec<(&str, CredentialKind, &str)> {
+    let mut credentials = item
+        .item
+        .credentials
+        .values()
+        .map(|c| (c.key.as_str(), c.kind, item.values[&c.id].expose_secret()))
+        .collect::<Vec<_>>();
+    credentials.sort_by_key(|(key, _, _)| *key);
+    credentials
+}
+
+#[test]
+fn test_bitwarden() {
+    let entries = bitwarden::parse(include_bytes!("../fixtures/import/bitwarden.json")).unwrap();
+    assert_eq!(entries.len(), 4);
+    assert_eq!(entries[0].title, "GitHub");
+    assert_eq!(entries[0].tags, ["Work"]);
+    assert_eq!(
+        fields(&entries[0]),
+        [
+            ("username", CredentialKind::Username, "octocat"),
+            ("password", CredentialKind::Password, "ghp_password"),
+            ("totp", CredentialKind::TotpSeed, "JBSWY3DPEHPK3PXP"),
+            ("url", CredentialKind::Url, "https://github.com"),
+            ("Recovery code", CredentialKind::Password, "abcd-efgh"),
+            ("notes", CredentialKind::Note, "personal account"),
+        ]
+    );
+    assert!(fields(&entries[2]).contains(&("cardholder name", CredentialKind::Note, "Alice")));
+    assert!(fields(&entries[2]).contains(&("code", CredentialKind::Password, "123")));
+
+    let plan = plan_import(entries, ["visa".to_string()]);
+    // the empty note is skipped
+    assert_eq!(plan.empty, ["Empty note"]);
+    assert!(plan.rejected.is_empty());
+    let items = plan.items;
+    let keys = items
+        .iter()
+        .map(|i| i.item.key.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["github", "github-2", "visa-2"]);
+    assert_eq!(items[0].item.tags, ["work"]);
+    assert_eq!(
+        credentials(&items[0]),
+        [
+            ("notes", CredentialKind::Note, "personal account"),
+            ("password", CredentialKind::Password, "ghp_password"),
+            ("recovery-code", CredentialKind::Password, "abcd-efgh"),
+            ("totp", CredentialKind::TotpSeed, "JBSWY3DPEHPK3PXP"),
+            ("url", CredentialKind::Url, "https://github.com"),
+            ("username", CredentialKind::Username, "octocat"),
+        ]
+    );
+
+    let encrypted = br#"{"encrypted": true, "encKeyValidation_DO_NOT_EDIT": "..."}"#;
+    assert!(bitwarden::parse(encrypted).is_err());
+}
+
+#[test]
+fn test_one_password() {
+    let entries =
+        one_password::parse(include_bytes!("../fixtures/import/one_password.1pux")).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].title, "AWS");
+    assert_eq!(entries[0].tags, ["Private", "cloud"]);
+    assert_eq!(
+        fields(&entries[0]),
+        [
+            ("username", CredentialKind::Username, "admin"),
+            ("password", CredentialKind::Password, "s3cret"),
+            ("access key id", CredentialKind::Note, "AKIAEXAMPLE"),
+            (
+                "secret access key",
+                CredentialKind::Password,
+                "wJalrEXAMPLE"
+            ),
+            (
+                "one-time password",
+                CredentialKind::TotpSeed,
+                "otpauth://totp/AWS?secret=JBSWY3DPEHPK3PXP"
+            ),
+            ("notes", CredentialKind::Note, "root account"),
+            ("website", CredentialKind::Url, "https://aws.amazon.com"),
+        ]
+    );
+    assert_eq!(entries[1].tags, ["Private", "archived"]);
+    assert_eq!(
+        fields(&entries[1]),
+        [("password", CredentialKind::Password, "old-pass")]
+    );
+
+    assert!(one_password::parse(b"not a zip file").is_err());
+}
+
+#[test]
+fn test_keepass() {
+    let entries = keepass::parse(include_bytes!("../fixtures/import/keepass.xml")).unwrap();
+    // the recycle bin is skipped
+    let titles = entries.iter().map(|e| e.title.as_str()).collect::<Vec<_>>();
+    assert_eq!(titles, ["Build Server", "VPN"]);
+    assert_eq!(
+        fields(&entries[0]),
+        [
+            ("username", CredentialKind::Username, "root"),
+            ("password", CredentialKind::Password, "hunter2"),
+            ("url", CredentialKind::Url, "https://ci.example.com"),
+            ("notes", CredentialKind::Note, "rack 4"),
+            ("API Key", CredentialKind::Password, "key-123"),
+            ("Region", CredentialKind::Note, "eu-west-1"),
+        ]
+    );
+    assert_eq!(entries[1].tags, ["Work", "infra", "vpn"]);
+
+    let items = plan_import(entries, []).items;
+    // not a valid URL, so imported as a note
+    assert!(credentials(&items[1]).contains(&("url", CredentialKind::Note, "vpn.example.com")));
+}
+
+#[test]
+fn test_generic_csv() {
+    let data = include_bytes!("../fixtures/import/generic.csv");
+    let entries = generic_csv::parse(data, &CsvMapping::default()).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+        fields(&entries[0]),
+        [
+            ("Username", CredentialKind::Username, "alice"),
+            ("Password", CredentialKind::Password, "s3cret"),
+            ("URL", CredentialKind::Url, "https://example.com"),
+            ("Notes", CredentialKind::Note, "multi\nline"),
+            ("2FA", CredentialKind::Password, "JBSWY3DPEHPK3PXP"),
+        ]
+    );
+
+    let items = plan_import(entries, []).items;
+    assert_eq!(items[1].item.key, "example-2");
+    assert!(credentials(&items[0]).contains(&(
+        "field-2fa",
+        CredentialKind::Password,
+        "JBSWY3DPEHPK3PXP"
+    )));
+
+    let mapping = CsvMapping {
+        title_column: Some("username".to_string()),
+        columns: vec![
+            ("Password".to_string(), "login password".to_string()),
+            ("2FA".to_string(), "totp".to_string()),
+        ],
+    };
+    let entries = generic_csv::parse(data, &mapping).unwrap();
+    assert_eq!(entries[0].title, "alice");
+    assert_eq!(
+        fields(&entries[0]),
+        [
+            ("login password", CredentialKind::Password, "s3cret"),
+            ("totp", CredentialKind::TotpSeed, "JBSWY3DPEHPK3PXP"),
+        ]
+    );
+
+    let mapping = CsvMapping {
+        title_column: Some("missing".to_string()),
+        ..Default::default()
+    };
+    assert!(generic_csv::parse(data, &mapping).is_err());
+}
@@ -0,0 +1,226 @@
+use serde_json::{Map, Value};
+
+use crate::secrets::vaults::errors::Error;
+
+// Format versions of vault files (EncryptedVault) and vault exports
+// (ExportedVault). Files are upgraded to the current version when they are
+// loaded, by applying each migration in turn to the raw JSON, and always saved
+// in the current version.
+//
+// Vault files:
+// 1. no `version` field. Fields added over time (`history`, `aliases`,
+//    `attachments`, `manifest`) are optional.
+// 2. adds `version`. The manifest (see VaultManifest) is required, so it can't
+//    be stripped from a file without being noticed.
+//
+// Vault exports:
+// 1. no `version` field
+// 2. adds `version`. `aliases` was added later and is optional, so older
+//    versions import exports without their aliases.
+pub const VAULT_FORMAT_VERSION: u32 = 2;
+pub const EXPORT_FORMAT_VERSION: u32 = 2;
+
+type Migration = fn(&mut Map<String, Value>) -> Result<(), Error>;
+
+// MIGRATIONS[n] upgrades version n + 1 to version n + 2
+const VAULT_MIGRATIONS: [Migration; VAULT_FORMAT_VERSION as usize - 1] = [vault_v1_to_v2];
+const EXPORT_MIGRATIONS: [Migration; EXPORT_FORMAT_VERSION as usize - 1] = [export_v1_to_v2];
+
+/// Upgrade a vault file to VAULT_FORMAT_VERSION, returning the version it was
+/// written in.
+pub fn migrate_vault(value: &mut Value) -> Result<u32, Error> {
+    let version = migrate(value, &VAULT_MIGRATIONS)?;
+    if version >= 2 && value.get("manifest").is_none_or(Value::is_null) {
+        return Err(Error::VaultManifestMismatch(
+            "manifest is missing".to_string(),
+        ));
+    }
+    Ok(version)
+}
+
+/// Upgrade a vault export to EXPORT_FORMAT_VERSION, returning the version it
+/// was written in.
+pub fn migrate_export(value: &mut Value) -> Result<u32, Error> {
+    migrate(value, &EXPORT_MIGRATIONS)
+}
+
+fn migrate(value: &mut Value, migrations: &[Migration]) -> Result<u32, Error> {
+    let current_version = migrations.len() as u32 + 1;
+    let Value::Object(fields) = value else {
+        return Err(Error::VaultMigrationError(
+            "expected a JSON object".to_string(),
+        ));
+    };
+    let version = match fields.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v >= 1)
+            .ok_or_else(|| Error::VaultMigrationError(format!("invalid version {version}")))?,
+    };
+    if version > current_version {
+        return Err(Error::VaultVersionTooNew(version, current_version));
+    }
+
+    for (from_version, migration) in (version..).zip(&migrations[version as usize - 1..]) {
+        log::debug!(
+            "Migrating from version {from_version} to {}",
+            from_version + 1
+        );
+        migration(fields)?;
+        fields.insert("version".to_string(), (from_version + 1).into());
+    }
+    Ok(version)
+}
+
+// only adds the version field; the manifest is checked by migrate_vault, since
+// version 1 files may not have one
+fn vault_v1_to_v2(_vault: &mut Map<String, Value>) -> Result<(), Error> {
+    Ok(())
+}
+
+fn export_v1_to_v2(_export: &mut Map<String, Value>) -> Result<(), Error> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::*;
+    use crate::secrets::vaults::vault::Vault;
+    use crate::secrets::vaults::vault::encrypted_vault::{EncryptedVault, VaultFileKey};
+    use crate::secrets::vaults::vault::vault_cipher::VaultCipher;
+    use crate::secrets::vaults::vault_export::{ExportedVault, ImportIdentity};
+
+    // age identity the fixtures are shared with or exported for
+    const FIXTURE_IDENTITY: &str =
+        "AGE-SECRET-KEY-1C77AF5EQZ2DFKYJ2LRWL6LK8H2MFSUWYRUGY59SX52DDX8WKT39S45LTRA";
+
+    fn fixture_identity() -> age::x25519::Identity {
+        FIXTURE_IDENTITY.parse().unwrap()
+    }
+
+    // checks the contents every fixture vault was created with
+    fn assert_fixture_contents(vault: &Vault) {
+        let secret = vault
+            .get_item_credential_secret("github", "token")
+            .unwrap()
+            .unwrap();
+        assert_eq!(secret.expose_secret(), "ghp_v2");
+        let previous = vault
+            .get_item_credential_history_secret("github", "token", 1)
+            .unwrap();
+        assert_eq!(previous.expose_secret(), "ghp_v1");
+        let data = vault
+            .get_item_attachment_data("github", "deploy-key")
+            .unwrap();
+        assert_eq!(data.expose_secret(), b"ssh-ed25519 AAAA fixture");
+    }
+
+    #[test]
+    fn test_vault_fixtures() {
+        let fixtures = [
+            (1, include_str!("./fixtures/vault_v1.json")),
+            (2, include_str!("./fixtures/vault_v2.json")),
+        ];
+        for (version, data) in fixtures {
+            let mut value: Value = serde_json::from_str(data).unwrap();
+            assert_eq!(migrate_vault(&mut value).unwrap(), version);
+            assert_eq!(value["version"], VAULT_FORMAT_VERSION);
+
+            let enc_vault = EncryptedVault::from_json(data).unwrap();
+            assert_eq!(enc_vault.version, VAULT_FORMAT_VERSION);
+            let cipher = enc_vault
+                .decrypt_file_key_with_age_identity(&fixture_identity())
+                .unwrap();
+            let vault = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+            assert_fixture_contents(&vault);
+            assert_eq!(vault.revision(), 3);
+            assert_eq!(vault.get_item_alias("octocat").unwrap().target, "github");
+
+            // saved in the current version
+            let saved = serde_json::to_value(vault.into_encrypted().unwrap()).unwrap();
+            assert_eq!(saved["version"], VAULT_FORMAT_VERSION);
+        }
+    }
+
+    #[test]
+    fn test_personal_vault_v1_fixture() {
+        // the original format: a Secure Enclave wrapped file key and items
+        // without history, so only check that it still parses
+        let data = include_str!("./fixtures/vault_v1_personal.json");
+        let enc_vault = EncryptedVault::from_json(data).unwrap();
+        assert_eq!(enc_vault.version, VAULT_FORMAT_VERSION);
+        assert!(matches!(enc_vault.file_key, VaultFileKey::Personal(_)));
+        assert_eq!(enc_vault.items.len(), 1);
+        assert!(enc_vault.manifest.is_none());
+    }
+
+    #[test]
+    fn test_export_fixtures() {
+        let fixtures = [
+            (1, include_str!("./fixtures/export_v1.json")),
+            (2, include_str!("./fixtures/export_v2.json")),
+        ];
+        for (version, data) in fixtures {
+            let mut value: Value = serde_json::from_str(data).unwrap();
+            assert_eq!(migrate_export(&mut value).unwrap(), version);
+
+            let exported = ExportedVault::from_json(data).unwrap();
+            assert_eq!(exported.version, EXPORT_FORMAT_VERSION);
+            let raw_key = ImportIdentity::Identity(fixture_identity())
+                .unwrap_file_key(&exported.age_file_key)
+                .unwrap();
+            let enc_vault = EncryptedVault {
+                version: VAULT_FORMAT_VERSION,
+                id: exported.id,
+                name: exported.name,
+                file_key: VaultFileKey::Personal(Vec::new()),
+                items: exported.items,
+                aliases: Default::default(),
+                manifest: None,
+            };
+            let cipher = VaultCipher::new_with_bytes(&raw_key, enc_vault.id);
+            let vault = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+            assert_fixture_contents(&vault);
+        }
+    }
+
+    #[test]
+    fn test_newer_versions_are_rejected() {
+        let mut value: Value =
+            serde_json::from_str(include_str!("./fixtures/vault_v2.json")).unwrap();
+        value["version"] = (VAULT_FORMAT_VERSION + 1).into();
+        assert!(matches!(
+            EncryptedVault::from_json(&value.to_string()),
+            Err(Error::VaultVersionTooNew(3, 2))
+        ));
+
+        let mut value: Value =
+            serde_json::from_str(include_str!("./fixtures/export_v2.json")).unwrap();
+        value["version"] = (EXPORT_FORMAT_VERSION + 1).into();
+        assert!(matches!(
+            ExportedVault::from_json(&value.to_string()),
+            Err(Error::VaultVersionTooNew(3, 2))
+        ));
+
+        value["version"] = "2".into();
+        assert!(matches!(
+            ExportedVault::from_json(&value.to_string()),
+            Err(Error::VaultMigrationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_manifest_is_required_from_v2() {
+        let mut value: Value =
+            serde_json::from_str(include_str!("./fixtures/vault_v2.json")).unwrap();
+        value.as_object_mut().unwrap().remove("manifest");
+        assert!(matches!(
+            EncryptedVault::from_json(&value.to_string()),
+            Err(Error::VaultManifestMismatch(_))
+        ));
+    }
+}
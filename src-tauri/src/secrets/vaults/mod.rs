@@ -1,10 +1,30 @@
+mod attachment_files;
+pub mod diff;
+pub mod encrypted_file;
 mod errors;
+pub mod importers;
+mod migrations;
+pub mod otp;
+pub mod reference;
+pub mod render;
+pub mod search;
+#[cfg(test)]
+pub(crate) mod test_vaults;
 mod vault;
 pub mod vault_export;
+mod vault_key;
 mod vault_wrapper;
 mod vaults_manager;
 
+pub use attachment_files::AttachmentFiles;
 pub use errors::Error;
-pub use vault::{VaultItemCredentialOverview, VaultItemOverview};
-pub use vault_wrapper::{DEFAULT_VAULT, VaultWrapper};
+pub use vault::attachment::VaultItemAttachmentOverview;
+pub use vault::credential_kind::CredentialKind;
+pub use vault::vault_member::{MemberKey, VaultMember};
+pub use vault::{ItemTransfer, VaultItemAlias, VaultItemCredentialOverview, VaultItemOverview};
+pub use vault_key::{SoftwareKey, VaultKey};
+pub use vault_wrapper::{
+    ALLOW_VAULT_ROLLBACK_ENV, DEFAULT_VAULT, VaultWrapper, create_local_member_key,
+    local_member_key,
+};
 pub use vaults_manager::VaultsManager;
@@ -0,0 +1,525 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use regex::Regex;
+
+use crate::secrets::vaults::errors::Error;
+
+// Reference grammar (the item shorthand is only accepted by the CLI). Without
+// a vault, as in axo:///item/credential, the default vault is used. Without a
+// credential, it references the item (e.g. for template ranges), but
+// references embedded in text always have one, see replace_references.
+//
+//   reference = "axo://" [ vault ] "/" item [ "/" [ credential ] ] [ query ]
+//             | item [ "/" [ credential ] ] [ query ]
+//   query     = "?" modifier *( "&" modifier )
+//   modifier  = "totp" | "file"
+//             | "encoding=" ( "base64" | "hex" | "urlencode" )
+//             | "json=" 1*( "." key )
+//             | "line=" 1*DIGIT
+//             | "default=" *pchar  (percent-encoded)
+//
+// Keys are [a-zA-Z0-9_-]+. Each modifier can be given once.
+const KEY: &str = r"[a-zA-Z0-9_-]+";
+
+static REFERENCE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"^(?:axo://(?P<vault>{KEY})?/)?(?P<item>{KEY})(?:/(?P<credential>{KEY})?)?(?:\?(?P<query>[^\s]*))?$"
+    ))
+    .unwrap()
+});
+
+// References embedded in text (see replace_references). The query can't end
+// with punctuation, so that e.g. a full stop after a reference is kept.
+static REFERENCE_IN_TEXT_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"\baxo://(?:{KEY})?/{KEY}/{KEY}\b(?:\?[a-zA-Z0-9_.~%=&+:/,-]*[a-zA-Z0-9_~%=/-])?"
+    ))
+    .unwrap()
+});
+
+static JSON_PATH_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!(r"^(?:\.{KEY})+$")).unwrap());
+
+/// A parsed reference to an item or credential, e.g.
+/// `axo://vault/item/credential?json=.password`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemReference {
+    pub vault: Option<String>,
+    pub item: String,
+    pub credential: Option<String>,
+    pub query: ReferenceQuery,
+}
+
+/// Where the value of a reference comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReferenceSource {
+    /// the credential's value
+    #[default]
+    Value,
+    /// `?totp`: a one-time password generated from the credential
+    Totp,
+    /// `?file`: the path of a temporary file holding the attachment
+    File,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReferenceEncoding {
+    Base64,
+    Hex,
+    Urlencode,
+}
+
+/// Query modifiers of a reference. json, line and encoding are applied to the
+/// value in that order, see apply.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReferenceQuery {
+    pub source: ReferenceSource,
+    pub encoding: Option<ReferenceEncoding>,
+    /// field names (or array indexes) of `?json=.path.to.field`
+    pub json: Option<Vec<String>>,
+    /// 1-based line number
+    pub line: Option<usize>,
+    /// used instead of a credential, JSON field or line that doesn't exist
+    pub default: Option<String>,
+}
+
+impl FromStr for ItemReference {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let captures = REFERENCE_REGEX
+            .captures(s)
+            .ok_or_else(|| Error::InvalidVaultItemReference(s.to_string()))?;
+        let query = match captures.name("query") {
+            Some(query) => query
+                .as_str()
+                .parse()
+                .map_err(|e| Error::InvalidVaultItemReference(format!("{s}: {e}")))?,
+            None => ReferenceQuery::default(),
+        };
+        Ok(ItemReference {
+            vault: captures.name("vault").map(|m| m.as_str().to_string()),
+            item: captures["item"].to_string(),
+            credential: captures.name("credential").map(|m| m.as_str().to_string()),
+            query,
+        })
+    }
+}
+
+impl fmt::Display for ItemReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(vault) = &self.vault {
+            write!(f, "axo://{vault}/")?;
+        }
+        write!(f, "{}", self.item)?;
+        if let Some(credential) = &self.credential {
+            write!(f, "/{credential}")?;
+        }
+        let query = self.query.to_string();
+        if !query.is_empty() {
+            write!(f, "?{query}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ReferenceQuery {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut query = ReferenceQuery::default();
+        let mut seen = Vec::new();
+        for modifier in s.split('&').filter(|modifier| !modifier.is_empty()) {
+            let (name, value) = match modifier.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (modifier, None),
+            };
+            if seen.contains(&name) {
+                return Err(format!("{name} is given more than once"));
+            }
+            seen.push(name);
+            match (name, value) {
+                ("totp" | "file", Some(_)) => return Err(format!("{name} takes no value")),
+                ("totp" | "file", None) if query.source != ReferenceSource::Value => {
+                    return Err("totp and file can't be combined".to_string());
+                },
+                ("totp", None) => query.source = ReferenceSource::Totp,
+                ("file", None) => query.source = ReferenceSource::File,
+                ("encoding", Some(encoding)) => {
+                    query.encoding = Some(match encoding {
+                        "base64" => ReferenceEncoding::Base64,
+                        "hex" => ReferenceEncoding::Hex,
+                        "urlencode" => ReferenceEncoding::Urlencode,
+                        _ => return Err(format!("unknown encoding {encoding}")),
+                    });
+                },
+                ("json", Some(path)) if JSON_PATH_REGEX.is_match(path) => {
+                    query.json = Some(path[1..].split('.').map(str::to_string).collect());
+                },
+                ("json", Some(path)) => {
+                    return Err(format!("invalid JSON path {path} (expected e.g. .a.b)"));
+                },
+                ("line", Some(line)) => match line.parse::<usize>() {
+                    Ok(line) if line > 0 => query.line = Some(line),
+                    _ => return Err(format!("invalid line {line} (lines start at 1)")),
+                },
+                ("default", Some(default)) => query.default = Some(percent_decode(default)?),
+                ("encoding" | "json" | "line" | "default", None) => {
+                    return Err(format!("{name} needs a value"));
+                },
+                _ => return Err(format!("unknown modifier {name}")),
+            }
+        }
+        Ok(query)
+    }
+}
+
+impl fmt::Display for ReferenceQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut modifiers = Vec::new();
+        match self.source {
+            ReferenceSource::Value => {},
+            ReferenceSource::Totp => modifiers.push("totp".to_string()),
+            ReferenceSource::File => modifiers.push("file".to_string()),
+        }
+        if let Some(path) = &self.json {
+            modifiers.push(format!("json=.{}", path.join(".")));
+        }
+        if let Some(line) = self.line {
+            modifiers.push(format!("line={line}"));
+        }
+        if let Some(encoding) = self.encoding {
+            let encoding = match encoding {
+                ReferenceEncoding::Base64 => "base64",
+                ReferenceEncoding::Hex => "hex",
+                ReferenceEncoding::Urlencode => "urlencode",
+            };
+            modifiers.push(format!("encoding={encoding}"));
+        }
+        if let Some(default) = &self.default {
+            modifiers.push(format!("default={}", percent_encode(default)));
+        }
+        write!(f, "{}", modifiers.join("&"))
+    }
+}
+
+impl ReferenceQuery {
+    /// Apply the json, line and encoding modifiers to a resolved value. The
+    /// default (which isn't encoded) is used if the value, JSON field or line
+    /// doesn't exist.
+    pub fn apply(&self, value: Option<String>) -> Result<Option<String>, Error> {
+        let mut value = value;
+        if let (Some(path), Some(json)) = (&self.json, &value) {
+            value = json_field(json, path)?;
+        }
+        if let (Some(line), Some(lines)) = (self.line, &value) {
+            value = lines.lines().nth(line - 1).map(str::to_string);
+        }
+        let Some(value) = value else {
+            return Ok(self.default.clone());
+        };
+        Ok(Some(match self.encoding {
+            None => value,
+            Some(ReferenceEncoding::Base64) => BASE64_STANDARD.encode(value),
+            Some(ReferenceEncoding::Hex) => value.bytes().map(|b| format!("{b:02x}")).collect(),
+            Some(ReferenceEncoding::Urlencode) => percent_encode(&value),
+        }))
+    }
+}
+
+/// The `axo://[vault]/item/credential[?modifiers]` references in input, as
+/// replaced by replace_references.
+pub fn find_references(input: &str) -> impl Iterator<Item = &str> {
+    REFERENCE_IN_TEXT_REGEX
+        .find_iter(input)
+        .map(|reference| reference.as_str())
+}
+
+/// Replace every `axo://[vault]/item/credential[?modifiers]` reference in input
+/// with the result of replace.
+pub fn replace_references(input: &str, replace: impl FnMut(&str) -> String) -> String {
+    let mut replace = replace;
+    REFERENCE_IN_TEXT_REGEX
+        .replace_all(input, |caps: &regex::Captures| replace(&caps[0]))
+        .to_string()
+}
+
+// a field of a JSON value: strings are returned as they are, other values as
+// JSON
+fn json_field(json: &str, path: &[String]) -> Result<Option<String>, Error> {
+    let value: serde_json::Value = serde_json::from_str(json)
+        .map_err(|e| Error::ReferenceModifierError(format!("value is not JSON: {e}")))?;
+    let mut field = &value;
+    for key in path {
+        let next = match field {
+            serde_json::Value::Object(object) => object.get(key),
+            serde_json::Value::Array(array) => key.parse::<usize>().ok().and_then(|i| array.get(i)),
+            _ => None,
+        };
+        match next {
+            Some(next) => field = next,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(match field {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }))
+}
+
+// everything but unreserved characters (RFC 3986) is percent-encoded
+const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+fn percent_encode(value: &str) -> String {
+    utf8_percent_encode(value, UNRESERVED).to_string()
+}
+
+fn percent_decode(value: &str) -> Result<String, String> {
+    let invalid = || format!("invalid percent-encoding in {value}");
+    // percent_decode_str passes malformed escapes through unchanged
+    let malformed = value.split('%').skip(1).any(|escape| {
+        !escape
+            .as_bytes()
+            .get(..2)
+            .is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+    });
+    if malformed {
+        return Err(invalid());
+    }
+    percent_decode_str(value)
+        .decode_utf8()
+        .map(|decoded| decoded.into_owned())
+        .map_err(|_| invalid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(vault: Option<&str>, item: &str, credential: Option<&str>) -> ItemReference {
+        ItemReference {
+            vault: vault.map(str::to_string),
+            item: item.to_string(),
+            credential: credential.map(str::to_string),
+            query: ReferenceQuery::default(),
+        }
+    }
+
+    #[test]
+    fn test_item_reference_from_str() {
+        let cases = [
+            (
+                "axo://my_vault/my_item/my_credential",
+                reference(Some("my_vault"), "my_item", Some("my_credential")),
+            ),
+            (
+                "item456/cred789",
+                reference(None, "item456", Some("cred789")),
+            ),
+            ("item456", reference(None, "item456", None)),
+            (
+                "axo:///item456/cred789",
+                reference(None, "item456", Some("cred789")),
+            ),
+            ("item456/", reference(None, "item456", None)),
+            (
+                "axo://my_vault/my_item/my_credential?totp",
+                ItemReference {
+                    query: ReferenceQuery {
+                        source: ReferenceSource::Totp,
+                        ..Default::default()
+                    },
+                    ..reference(Some("my_vault"), "my_item", Some("my_credential"))
+                },
+            ),
+            (
+                "axo://v/k8s/kubeconfig?file",
+                ItemReference {
+                    query: ReferenceQuery {
+                        source: ReferenceSource::File,
+                        ..Default::default()
+                    },
+                    ..reference(Some("v"), "k8s", Some("kubeconfig"))
+                },
+            ),
+            (
+                "axo://v/db/config?json=.primary.hosts.0&line=2&encoding=base64&default=a%20b%26c",
+                ItemReference {
+                    query: ReferenceQuery {
+                        json: Some(vec![
+                            "primary".to_string(),
+                            "hosts".to_string(),
+                            "0".to_string(),
+                        ]),
+                        line: Some(2),
+                        encoding: Some(ReferenceEncoding::Base64),
+                        default: Some("a b&c".to_string()),
+                        ..Default::default()
+                    },
+                    ..reference(Some("v"), "db", Some("config"))
+                },
+            ),
+            (
+                "axo://v/i/c?default=",
+                ItemReference {
+                    query: ReferenceQuery {
+                        default: Some(String::new()),
+                        ..Default::default()
+                    },
+                    ..reference(Some("v"), "i", Some("c"))
+                },
+            ),
+        ];
+        for (reference_str, expected) in cases {
+            let item_ref = ItemReference::from_str(reference_str)
+                .unwrap_or_else(|e| panic!("Failed to parse {reference_str}: {e}"));
+            assert_eq!(
+                item_ref, expected,
+                "Failed parsing reference: {reference_str}"
+            );
+            // references are printed in the same form
+            assert_eq!(
+                ItemReference::from_str(&item_ref.to_string()).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_references() {
+        for reference_str in [
+            "",
+            "axo://",
+            "axo://vault",
+            "axo:///",
+            "axo:////item/cred",
+            "axo://vault/item/cred/extra",
+            "axo://vault/item/cred junk",
+            "axo://vault/it.em/cred",
+            "https://vault/item/cred",
+            "axo://v/i/c?other",
+            "axo://v/i/c?totp=1",
+            "axo://v/i/c?totp&file",
+            "axo://v/i/c?totp&totp",
+            "axo://v/i/c?encoding=rot13",
+            "axo://v/i/c?encoding",
+            "axo://v/i/c?json=a.b",
+            "axo://v/i/c?json=.a..b",
+            "axo://v/i/c?json=.",
+            "axo://v/i/c?line=0",
+            "axo://v/i/c?line=-1",
+            "axo://v/i/c?default=%zz",
+            "axo://v/i/c?default=%e9",
+        ] {
+            assert!(
+                ItemReference::from_str(reference_str).is_err(),
+                "{reference_str} should not parse"
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_modifiers() {
+        let apply = |query: &str, value: Option<&str>| {
+            query
+                .parse::<ReferenceQuery>()
+                .unwrap()
+                .apply(value.map(str::to_string))
+                .unwrap()
+        };
+        let json = r#"{"db": {"hosts": ["a", "b"], "port": 5432, "user": "app"}}"#;
+        assert_eq!(apply("json=.db.user", Some(json)).as_deref(), Some("app"));
+        assert_eq!(apply("json=.db.port", Some(json)).as_deref(), Some("5432"));
+        assert_eq!(apply("json=.db.hosts.1", Some(json)).as_deref(), Some("b"));
+        assert_eq!(
+            apply("json=.db.hosts", Some(json)).as_deref(),
+            Some(r#"["a","b"]"#)
+        );
+        assert_eq!(apply("json=.db.missing", Some(json)), None);
+        assert_eq!(
+            apply("json=.db.missing&default=x", Some(json)).as_deref(),
+            Some("x")
+        );
+        assert!(
+            "json=.a"
+                .parse::<ReferenceQuery>()
+                .unwrap()
+                .apply(Some("not json".to_string()))
+                .is_err()
+        );
+
+        let lines = "first\nsecond\n";
+        assert_eq!(apply("line=2", Some(lines)).as_deref(), Some("second"));
+        assert_eq!(apply("line=3", Some(lines)), None);
+
+        assert_eq!(
+            apply("encoding=base64", Some("a b")).as_deref(),
+            Some("YSBi")
+        );
+        assert_eq!(
+            apply("encoding=hex", Some("a b")).as_deref(),
+            Some("612062")
+        );
+        assert_eq!(
+            apply("encoding=urlencode", Some("a b/c?d=é")).as_deref(),
+            Some("a%20b%2Fc%3Fd%3D%C3%A9")
+        );
+        // json, then line, then encoding
+        assert_eq!(
+            apply(
+                "encoding=hex&line=1&json=.key",
+                Some(r#"{"key": "ab\ncd"}"#)
+            )
+            .as_deref(),
+            Some("6162")
+        );
+
+        assert_eq!(apply("default=x", None).as_deref(), Some("x"));
+        assert_eq!(apply("encoding=hex&default=x", None).as_deref(), Some("x"));
+        assert_eq!(apply("", None), None);
+        assert_eq!(apply("", Some("value")).as_deref(), Some("value"));
+    }
+
+    #[test]
+    fn test_replace_references() {
+        let cases = [
+            ("no references", "no references"),
+            ("axo://v/i/c", "[axo://v/i/c]"),
+            (
+                "a=axo://v/i/c b=axo://v/i/d?totp",
+                "a=[axo://v/i/c] b=[axo://v/i/d?totp]",
+            ),
+            ("axo://v/i/c?json=.a.b.", "[axo://v/i/c?json=.a.b]."),
+            ("\"axo://v/i/c?default=x\"", "\"[axo://v/i/c?default=x]\""),
+            (
+                "axo://v/i/c?line=2&encoding=hex,",
+                "[axo://v/i/c?line=2&encoding=hex],",
+            ),
+            ("axo://v/i/c?", "[axo://v/i/c]?"),
+            ("axo:///i/c?totp", "[axo:///i/c?totp]"),
+            ("axo://v/i", "axo://v/i"),
+            ("axo:///i", "axo:///i"),
+            ("xaxo://v/i/c", "xaxo://v/i/c"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(
+                replace_references(input, |reference| format!("[{reference}]")),
+                expected,
+                "input: {input:?}"
+            );
+        }
+        assert_eq!(
+            find_references("a=axo://v/i/c b=axo:///i/d?totp.").collect::<Vec<_>>(),
+            ["axo://v/i/c", "axo:///i/d?totp"]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use crate::secrets::vaults::VaultWrapper;
+
+/// A credential matching a search query, see search_vaults.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchMatch {
+    pub vault_key: String,
+    pub item_key: String,
+    pub item_title: String,
+    pub cred_key: String,
+    pub cred_title: String,
+    pub tags: Vec<String>,
+    pub score: u32,
+}
+
+impl SearchMatch {
+    pub fn url(&self) -> String {
+        format!(
+            "axo://{}/{}/{}",
+            self.vault_key, self.item_key, self.cred_key
+        )
+    }
+}
+
+/// Search the credentials of the given (unlocked) vaults. Every whitespace
+/// separated term of the query must fuzzy match the item or credential title or
+/// key, or one of the item's tags; `#tag` terms only match tags exactly.
+/// Results are sorted by score, best first. Locked vaults are skipped.
+pub fn search_vaults<'a>(
+    vaults: impl IntoIterator<Item = &'a VaultWrapper>,
+    query: &str,
+) -> Vec<SearchMatch> {
+    let terms = query.split_whitespace().collect::<Vec<_>>();
+    let mut matches = Vec::new();
+    for vw in vaults {
+        let Ok(items) = vw.list_items() else {
+            continue;
+        };
+        for item in items {
+            for cred in item.credentials.values() {
+                let fields = [
+                    item.title.as_str(),
+                    item.key.as_str(),
+                    cred.title.as_str(),
+                    cred.key.as_str(),
+                ];
+                let score = terms.iter().try_fold(0, |total, term| {
+                    let term_score = match term.strip_prefix('#') {
+                        Some(tag) => item
+                            .tags
+                            .iter()
+                            .any(|t| t.eq_ignore_ascii_case(tag))
+                            .then_some(100),
+                        None => fields
+                            .into_iter()
+                            .chain(item.tags.iter().map(String::as_str))
+                            .filter_map(|field| fuzzy_score(term, field))
+                            .max(),
+                    };
+                    term_score.map(|s| total + s)
+                });
+                if let Some(score) = score {
+                    matches.push(SearchMatch {
+                        vault_key: vw.key.clone(),
+                        item_key: item.key.clone(),
+                        item_title: item.title.clone(),
+                        cred_key: cred.key.clone(),
+                        cred_title: cred.title.clone(),
+                        tags: item.tags.clone(),
+                        score,
+                    });
+                }
+            }
+        }
+    }
+    matches.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.url().cmp(&b.url())));
+    matches
+}
+
+/// Score how well a query matches a candidate, ignoring case: exact matches
+/// score highest, then prefixes, substrings (higher at a word boundary) and
+/// finally subsequences, which lose a point per skipped character. Returns
+/// None if the query's characters don't all appear in order.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<u32> {
+    let query = query.to_lowercase();
+    let candidate = candidate.to_lowercase();
+    if query.is_empty() {
+        return Some(0);
+    }
+    if candidate == query {
+        return Some(100);
+    }
+    if candidate.starts_with(&query) {
+        return Some(80);
+    }
+    if let Some(pos) = candidate.find(&query) {
+        let at_boundary = candidate[..pos].ends_with(|c: char| !c.is_alphanumeric());
+        return Some(if at_boundary { 70 } else { 60 });
+    }
+
+    let mut candidate_chars = candidate.chars();
+    let mut gaps = 0;
+    let mut started = false;
+    for q in query.chars() {
+        loop {
+            match candidate_chars.next() {
+                Some(c) if c == q => break,
+                Some(_) if started => gaps += 1,
+                Some(_) => {},
+                None => return None,
+            }
+        }
+        started = true;
+    }
+    Some(40u32.saturating_sub(gaps).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("stripe", "Stripe"), Some(100));
+        assert_eq!(fuzzy_score("str", "stripe-staging"), Some(80));
+        assert_eq!(fuzzy_score("staging", "stripe-staging"), Some(70));
+        assert_eq!(fuzzy_score("aging", "stripe-staging"), Some(60));
+        assert_eq!(fuzzy_score("stg", "staging"), Some(39));
+        assert_eq!(fuzzy_score("xyz", "staging"), None);
+        assert_eq!(fuzzy_score("gits", "staging"), None);
+
+        // tighter subsequences rank higher
+        assert!(fuzzy_score("stk", "stripe-key") < fuzzy_score("stk", "st-key"));
+    }
+}
@@ -0,0 +1,66 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use tempfile::TempDir;
+
+use crate::core::dirs::APP_DATA_DIR;
+use crate::secrets::vaults::vault_key::SoftwareKey;
+use crate::secrets::vaults::vault_wrapper::VaultWrapper;
+use crate::secrets::vaults::vaults_manager::VaultsManager;
+
+/// Use a temporary app data directory for this test run, so tests that unlock
+/// or save vaults don't read or record vault revisions in the user's config.
+/// Must be called before anything uses the app data directory.
+pub(crate) fn use_test_data_dir() {
+    let test_dir = std::env::temp_dir().join(format!("axo-pass-test-{}", std::process::id()));
+    let dir = APP_DATA_DIR.get_or_init(|| test_dir.clone());
+    assert_eq!(
+        dir, &test_dir,
+        "app data directory was used before the test set it"
+    );
+}
+
+/// Vaults in a temporary directory for tests, encrypted with a software vault
+/// key (so unlocking them doesn't need the Secure Enclave).
+pub(crate) struct TestVaults {
+    pub dir: TempDir,
+    pub key: Arc<SoftwareKey>,
+}
+
+impl TestVaults {
+    /// Vaults with the given credentials, as (vault, item, credential, value).
+    pub fn new(credentials: &[(&str, &str, &str, &str)]) -> Self {
+        use_test_data_dir();
+        let dir = tempfile::tempdir().unwrap();
+        let key = Arc::new(SoftwareKey::generate());
+        let mut vaults = BTreeMap::new();
+        for (vault_key, item_key, cred_key, value) in credentials {
+            let vault = vaults.entry(*vault_key).or_insert_with(|| {
+                VaultWrapper::new_vault(None, dir.path(), vault_key, key.as_ref()).unwrap()
+            });
+            vault.add_item(item_key, item_key).unwrap();
+            vault
+                .add_secret(item_key, cred_key, cred_key, None, value.to_string().into())
+                .unwrap();
+        }
+        for vault in vaults.values_mut() {
+            vault.save().unwrap();
+        }
+        Self { dir, key }
+    }
+
+    /// A manager for the vaults, all locked.
+    pub fn manager(&self) -> VaultsManager {
+        VaultsManager::with_vaults_dir(self.dir.path())
+    }
+
+    /// A manager for the vaults, all unlocked with the key.
+    pub fn unlocked_manager(&self) -> VaultsManager {
+        let mut vaults = self.manager();
+        for vault_key in vaults.iter_vault_keys().collect::<Vec<_>>() {
+            let vault = vaults.get_vault_mut(&vault_key).unwrap();
+            vault.unlock_with_key(self.key.as_ref()).unwrap();
+        }
+        vaults
+    }
+}
@@ -1,8 +1,13 @@
+pub mod attachment;
+pub mod credential_kind;
 pub mod encrypted_blob;
 pub mod encrypted_vault;
 pub mod vault_cipher;
+pub mod vault_manifest;
+pub mod vault_member;
 
 use std::collections::BTreeMap;
+use std::path::PathBuf;
 
 use aes_gcm::aead::OsRng;
 use aes_gcm::{Aes256Gcm, KeyInit};
@@ -12,16 +17,27 @@ use time::OffsetDateTime;
 use uuid::Uuid;
 use zeroize::Zeroize;
 
-use crate::secrets::keychain::managed_key::ManagedKey;
 use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::migrations::{EXPORT_FORMAT_VERSION, VAULT_FORMAT_VERSION};
+use crate::secrets::vaults::vault::attachment::{AttachmentContent, VaultItemAttachmentOverview};
+use crate::secrets::vaults::vault::credential_kind::CredentialKind;
 use crate::secrets::vaults::vault::encrypted_blob::EncryptedBlob;
-use crate::secrets::vaults::vault::encrypted_vault::{EncryptedVault, VaultFileKey};
+use crate::secrets::vaults::vault::encrypted_vault::{
+    EncryptedAttachmentContent, EncryptedCredentialHistoryEntry, EncryptedVault, VaultFileKey,
+};
 use crate::secrets::vaults::vault::vault_cipher::VaultCipher;
+use crate::secrets::vaults::vault::vault_manifest::VaultManifest;
+use crate::secrets::vaults::vault::vault_member::{MemberKey, VaultMember};
 use crate::secrets::vaults::vault_export::{ExportMode, ExportedVault};
+use crate::secrets::vaults::vault_key::VaultKey;
 use crate::secrets::vaults::vault_wrapper::normalized_key;
 
 type ItemId = Uuid;
 type CredentialId = Uuid;
+type AttachmentId = Uuid;
+
+// number of previous values kept per credential
+const MAX_CREDENTIAL_HISTORY: usize = 10;
 
 /// Vault contains decrypted metadata for all items and credentials in a
 /// vault, as well as the encrypted secrets for each credential. It is used to
@@ -37,6 +53,13 @@ pub struct Vault {
     item_index: BTreeMap<String, ItemId>,
     item_credential_index: BTreeMap<(String, String), (ItemId, CredentialId)>,
     secrets: BTreeMap<CredentialId, EncryptedBlob<String>>,
+    history: BTreeMap<CredentialId, Vec<EncryptedCredentialHistoryEntry>>,
+    attachments: BTreeMap<AttachmentId, AttachmentContent>,
+    aliases: BTreeMap<String, VaultItemAlias>,
+    // directory of the vault file, see attachment.rs
+    vault_dir: Option<PathBuf>,
+    // see VaultManifest
+    revision: u64,
 
     // Cached encrypted metadata blobs (keyed by item or cred UUID, which are
     // globally unique). Dropped when metadata changes so that into_encrypted()
@@ -45,7 +68,7 @@ pub struct Vault {
 }
 
 impl Vault {
-    pub fn new(name: Option<String>, user_encryption_key: ManagedKey) -> Result<Self, Error> {
+    pub fn new(name: Option<String>, user_encryption_key: &dyn VaultKey) -> Result<Self, Error> {
         let vault_id = Uuid::new_v4();
         let actual_file_key = Aes256Gcm::generate_key(OsRng);
 
@@ -69,6 +92,11 @@ impl Vault {
             item_index: BTreeMap::new(),
             item_credential_index: BTreeMap::new(),
             secrets: BTreeMap::new(),
+            history: BTreeMap::new(),
+            attachments: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            vault_dir: None,
+            revision: 0,
             metadata_blobs: BTreeMap::new(),
         })
     }
@@ -76,10 +104,19 @@ impl Vault {
     // This converts an EncryptedVault into a Vault by decrypting all
     // metadata and organizing data into the appropriate fields.
     pub fn from_encrypted(
-        user_encryption_key: ManagedKey,
+        user_encryption_key: &dyn VaultKey,
+        enc_vault: EncryptedVault,
+    ) -> Result<Self, Error> {
+        let vault_cipher = enc_vault.decrypt_file_key(user_encryption_key)?;
+        Self::from_encrypted_with_cipher(vault_cipher, enc_vault)
+    }
+
+    // Same as from_encrypted, for callers that already decrypted the file key
+    // (e.g. with an age identity).
+    pub fn from_encrypted_with_cipher(
+        vault_cipher: VaultCipher,
         enc_vault: EncryptedVault,
     ) -> Result<Self, Error> {
-        let vault_cipher = enc_vault.decrypt_file_key(&user_encryption_key)?;
         let mut vault = Vault {
             id: enc_vault.id,
             name: enc_vault.name.clone(),
@@ -89,16 +126,42 @@ impl Vault {
             item_index: BTreeMap::new(),
             item_credential_index: BTreeMap::new(),
             secrets: BTreeMap::new(),
+            history: BTreeMap::new(),
+            attachments: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            vault_dir: None,
+            revision: 0,
             metadata_blobs: BTreeMap::new(),
         };
 
+        match &enc_vault.manifest {
+            Some(encrypted_manifest) => {
+                let manifest = vault
+                    .cipher
+                    .decrypt_manifest(encrypted_manifest)
+                    .map_err(|_| {
+                        Error::VaultManifestMismatch("manifest could not be decrypted".to_string())
+                    })?;
+                manifest
+                    .expose_secret()
+                    .verify(&enc_vault)
+                    .map_err(Error::VaultManifestMismatch)?;
+                vault.revision = manifest.expose_secret().revision;
+            },
+            None => log::debug!("Vault {} has no manifest", enc_vault.id),
+        }
+
         for (item_id, encrypted_item) in enc_vault.items {
-            let (item_title, item_key) = vault
+            let (item_title, item_key, item_tags) = vault
                 .cipher
                 .decrypt_item_metadata(item_id, &encrypted_item.metadata)
                 .map(|m| {
                     let metadata = m.expose_secret();
-                    (metadata.title.clone(), metadata.key.clone())
+                    (
+                        metadata.title.clone(),
+                        metadata.key.clone(),
+                        metadata.tags.clone(),
+                    )
                 })?;
 
             vault
@@ -109,16 +172,23 @@ impl Vault {
                 id: item_id,
                 title: item_title,
                 key: item_key.clone(),
+                tags: item_tags,
                 credentials: BTreeMap::new(),
+                attachments: BTreeMap::new(),
             };
 
             for (cred_id, encrypted_cred) in encrypted_item.credentials {
-                let (cred_title, cred_key) = vault
+                let (cred_title, cred_key, cred_kind, cred_expires_at) = vault
                     .cipher
                     .decrypt_cred_metadata(item_id, cred_id, &encrypted_cred.metadata)
                     .map(|m| {
                         let metadata = m.expose_secret();
-                        (metadata.title.clone(), metadata.key.clone())
+                        (
+                            metadata.title.clone(),
+                            metadata.key.clone(),
+                            metadata.kind.unwrap_or_default(),
+                            metadata.expires_at,
+                        )
                     })?;
 
                 vault
@@ -131,6 +201,8 @@ impl Vault {
                         id: cred_id,
                         title: cred_title,
                         key: cred_key.clone(),
+                        kind: cred_kind,
+                        expires_at: cred_expires_at,
                     },
                 );
 
@@ -140,21 +212,71 @@ impl Vault {
                     .insert((item_key.clone(), cred_key.clone()), (item_id, cred_id));
 
                 vault.secrets.insert(cred_id, encrypted_cred.value);
+                if !encrypted_cred.history.is_empty() {
+                    vault.history.insert(cred_id, encrypted_cred.history);
+                }
+            }
+
+            for (attachment_id, encrypted_attachment) in encrypted_item.attachments {
+                let (file_name, attachment_key) = vault
+                    .cipher
+                    .decrypt_attachment_metadata(
+                        item_id,
+                        attachment_id,
+                        &encrypted_attachment.metadata,
+                    )
+                    .map(|m| {
+                        let metadata = m.expose_secret();
+                        (metadata.title.clone(), metadata.key.clone())
+                    })?;
+                vault
+                    .metadata_blobs
+                    .insert(attachment_id, encrypted_attachment.metadata);
+                item_overview.attachments.insert(
+                    attachment_id,
+                    VaultItemAttachmentOverview {
+                        id: attachment_id,
+                        key: attachment_key,
+                        file_name,
+                    },
+                );
+                vault
+                    .attachments
+                    .insert(attachment_id, encrypted_attachment.content.into());
             }
 
             vault.item_index.insert(item_key, item_id);
             vault.items.insert(item_id, item_overview);
         }
 
+        for (alias_id, encrypted_alias) in enc_vault.aliases {
+            let alias = vault
+                .cipher
+                .decrypt_item_metadata(alias_id, &encrypted_alias)
+                .map(|m| {
+                    let metadata = m.expose_secret();
+                    VaultItemAlias {
+                        id: alias_id,
+                        key: metadata.key.clone(),
+                        target: metadata.alias_of.clone().unwrap_or_default(),
+                    }
+                })?;
+            vault.metadata_blobs.insert(alias_id, encrypted_alias);
+            vault.aliases.insert(alias.key.clone(), alias);
+        }
+
         Ok(vault)
     }
 
     pub fn into_encrypted(&self) -> Result<EncryptedVault, Error> {
         let mut vault = EncryptedVault {
+            version: VAULT_FORMAT_VERSION,
             id: self.id,
             name: self.name.clone(),
             file_key: self.file_key.clone(),
             items: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            manifest: None,
         };
 
         for item_overview in self.items.values() {
@@ -172,30 +294,273 @@ impl Vault {
                         .get(cred_id)
                         .ok_or_else(|| Error::InvalidCredentialKey(cred_id.to_string()))?
                         .clone(),
+                    self.history.get(cred_id).cloned().unwrap_or_default(),
+                )?;
+            }
+
+            for (attachment_id, attachment) in &item_overview.attachments {
+                let content = self
+                    .attachments
+                    .get(attachment_id)
+                    .ok_or_else(|| Error::InvalidAttachmentKey(attachment_id.to_string()))?;
+                vault.add_attachment(
+                    &self.cipher,
+                    self.metadata_blobs.get(attachment_id),
+                    item_overview.id,
+                    attachment,
+                    content.to_encrypted(),
                 )?;
             }
         }
 
+        for alias in self.aliases.values() {
+            let encrypted_alias = match self.metadata_blobs.get(&alias.id) {
+                Some(existing) => existing.clone(),
+                None => {
+                    let metadata = VaultFieldMetadata {
+                        alias_of: Some(alias.target.clone()),
+                        ..VaultFieldMetadata::try_new(&alias.key, &alias.key)?
+                    };
+                    self.cipher.encrypt_item_metadata(alias.id, &metadata)?
+                },
+            };
+            vault.aliases.insert(alias.id, encrypted_alias);
+        }
+
+        let manifest = VaultManifest::for_vault(self.revision, &vault);
+        vault.manifest = Some(self.cipher.encrypt_manifest(&manifest)?);
         Ok(vault)
     }
 
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Move to the next revision before saving. Starts after `seen` (the last
+    /// revision seen on this device) so a vault unlocked despite being rolled
+    /// back doesn't stay behind, see VaultWrapper::unlock.
+    pub fn bump_revision(&mut self, seen: u64) {
+        self.revision = self.revision.max(seen) + 1;
+    }
+
     pub fn into_export(
         &self,
         key: Option<String>,
         export_mode: ExportMode,
     ) -> Result<ExportedVault, Error> {
-        let encrypted_vault = self.into_encrypted()?;
+        let mut encrypted_vault = self.into_encrypted()?;
+        // sidecar files don't travel with the export, so their contents are
+        // stored inline
+        for item in encrypted_vault.items.values_mut() {
+            for (attachment_id, attachment) in item.attachments.iter_mut() {
+                if let EncryptedAttachmentContent::Sidecar(_) = attachment.content {
+                    attachment.content = EncryptedAttachmentContent::Inline(
+                        self.read_attachment_blob(*attachment_id)?,
+                    );
+                }
+            }
+        }
         let age_file_key = self.cipher.wrap_file_key_for_export(export_mode)?;
         Ok(ExportedVault {
+            version: EXPORT_FORMAT_VERSION,
             id: encrypted_vault.id,
             exported_at: OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc()),
             name: encrypted_vault.name,
             default_key: key,
             age_file_key,
             items: encrypted_vault.items,
+            aliases: encrypted_vault.aliases,
         })
     }
 
+    pub fn file_key(&self) -> &VaultFileKey {
+        &self.file_key
+    }
+
+    /// Convert a personal vault to a vault with members, with owner_key (the
+    /// public key of the key that currently wraps the file key) as the first
+    /// member. Does nothing if the vault already has members.
+    pub fn convert_to_members(&mut self, owner_key: &MemberKey) {
+        if let VaultFileKey::Personal(wrapped_key) = &self.file_key {
+            self.file_key = VaultFileKey::Members(vec![VaultMember {
+                public_key: owner_key.to_string(),
+                wrapped_key: wrapped_key.clone(),
+            }]);
+        }
+    }
+
+    /// Share the vault with the holder of member_key by wrapping the file key
+    /// for their public key.
+    pub fn add_member(&mut self, member_key: &MemberKey) -> Result<(), Error> {
+        let member = self.cipher.wrap_file_key_for_member(member_key)?;
+        let (VaultFileKey::Members(members) | VaultFileKey::MembersFile { members, .. }) =
+            &mut self.file_key
+        else {
+            return Err(Error::InvalidVaultMember(
+                "vault has no members, convert it first".to_string(),
+            ));
+        };
+        if members.iter().any(|m| m.public_key == member.public_key) {
+            return Err(Error::InvalidVaultMember(format!(
+                "{} is already a member",
+                member.public_key
+            )));
+        }
+        members.push(member);
+        Ok(())
+    }
+
+    /// Remove a member and rotate the file key, so that the removed member
+    /// cannot decrypt future changes with a copy of the old file key.
+    pub fn remove_member(&mut self, member_key: &MemberKey) -> Result<(), Error> {
+        let public_key = member_key.to_string();
+        let (VaultFileKey::Members(members) | VaultFileKey::MembersFile { members, .. }) =
+            &mut self.file_key
+        else {
+            return Err(Error::VaultMemberNotFound(public_key));
+        };
+        let Some(pos) = members.iter().position(|m| m.public_key == public_key) else {
+            return Err(Error::VaultMemberNotFound(public_key));
+        };
+        if members.len() == 1 {
+            return Err(Error::InvalidVaultMember(
+                "cannot remove the last member of a vault".to_string(),
+            ));
+        }
+        members.remove(pos);
+        self.rotate_file_key(None)
+    }
+
+    /// Store the members in the vault file (None) or in a separate members
+    /// file (relative to the vault file).
+    pub fn set_members_file(&mut self, path: Option<PathBuf>) -> Result<(), Error> {
+        let members = match &mut self.file_key {
+            VaultFileKey::Personal(_) => {
+                return Err(Error::InvalidVaultMember(
+                    "vault has no members, convert it first".to_string(),
+                ));
+            },
+            VaultFileKey::Members(members) | VaultFileKey::MembersFile { members, .. } => {
+                std::mem::take(members)
+            },
+        };
+        self.file_key = match path {
+            Some(path) => VaultFileKey::MembersFile { path, members },
+            None => VaultFileKey::Members(members),
+        };
+        Ok(())
+    }
+
+    /// Generate a new file key, re-encrypt all secrets with it and re-wrap it
+    /// for every key holder. Metadata is re-encrypted with the new key in
+    /// into_encrypted. The file key of a personal vault is re-wrapped with
+    /// user_encryption_key, which is required in that case.
+    pub fn rotate_file_key(
+        &mut self,
+        user_encryption_key: Option<&dyn VaultKey>,
+    ) -> Result<(), Error> {
+        let new_file_key = Aes256Gcm::generate_key(OsRng);
+        let new_cipher = VaultCipher::new_with_bytes(&new_file_key, self.id);
+
+        let new_members = self
+            .file_key
+            .members()
+            .iter()
+            .map(|member| new_cipher.wrap_file_key_for_member(&member.member_key()?))
+            .collect::<Result<Vec<_>, _>>()?;
+        let new_personal_key = match (&self.file_key, user_encryption_key) {
+            (VaultFileKey::Personal(_), Some(user_encryption_key)) => Some(
+                user_encryption_key
+                    .encrypt(&new_file_key)
+                    .ok_or(Error::VaultFileKeyEncryptionError)?
+                    .into_bytes(),
+            ),
+            (VaultFileKey::Personal(_), None) => return Err(Error::VaultFileKeyEncryptionError),
+            _ => None,
+        };
+
+        let mut new_secrets = BTreeMap::new();
+        for item in self.items.values() {
+            for cred_id in item.credentials.keys() {
+                let Some(encrypted_secret) = self.secrets.get(cred_id) else {
+                    continue;
+                };
+                let plaintext =
+                    self.cipher
+                        .decrypt_cred_value(item.id, *cred_id, encrypted_secret)?;
+                let encrypted_secret =
+                    new_cipher.encrypt_cred_value(item.id, *cred_id, plaintext.expose_secret())?;
+                new_secrets.insert(*cred_id, encrypted_secret);
+            }
+        }
+
+        let mut new_history = BTreeMap::new();
+        for item in self.items.values() {
+            for cred_id in item.credentials.keys() {
+                let Some(entries) = self.history.get(cred_id) else {
+                    continue;
+                };
+                let mut new_entries = Vec::with_capacity(entries.len());
+                for entry in entries {
+                    let plaintext = self.cipher.decrypt_cred_history_value(
+                        item.id,
+                        *cred_id,
+                        entry.version,
+                        &entry.value,
+                    )?;
+                    new_entries.push(EncryptedCredentialHistoryEntry {
+                        value: new_cipher.encrypt_cred_history_value(
+                            item.id,
+                            *cred_id,
+                            entry.version,
+                            plaintext.expose_secret(),
+                        )?,
+                        ..entry.clone()
+                    });
+                }
+                new_history.insert(*cred_id, new_entries);
+            }
+        }
+
+        let mut new_attachments = BTreeMap::new();
+        for item in self.items.values() {
+            for attachment_id in item.attachments.keys() {
+                let Some(content) = self.attachments.get(attachment_id) else {
+                    continue;
+                };
+                let plaintext = self.read_attachment(item.id, *attachment_id)?;
+                let blob = new_cipher.encrypt_attachment(
+                    item.id,
+                    *attachment_id,
+                    plaintext.expose_secret(),
+                )?;
+                new_attachments.insert(
+                    *attachment_id,
+                    self.new_attachment_content(blob, content.is_sidecar()),
+                );
+            }
+        }
+
+        match &mut self.file_key {
+            VaultFileKey::Personal(wrapped_key) => {
+                *wrapped_key = new_personal_key.expect("personal vault key was wrapped above");
+            },
+            VaultFileKey::Members(members) | VaultFileKey::MembersFile { members, .. } => {
+                *members = new_members;
+            },
+        }
+        self.cipher = new_cipher;
+        self.secrets = new_secrets;
+        self.history = new_history;
+        self.attachments = new_attachments;
+        self.metadata_blobs.clear();
+        Ok(())
+    }
+
     pub fn list_items(&self) -> Vec<&VaultItemOverview> {
         self.items.values().collect()
     }
@@ -229,6 +594,9 @@ impl Vault {
 
         let item_id = item_overview.id;
         let item_key = item_overview.key.clone(); // normalized key
+        if let Some(alias) = self.aliases.remove(&item_key) {
+            self.metadata_blobs.remove(&alias.id);
+        }
 
         // update maps
         self.items.insert(item_id, item_overview);
@@ -240,6 +608,255 @@ impl Vault {
         Ok(item_overview_ref)
     }
 
+    /// Replace the tags of an item. Tags are normalized like keys, sorted and
+    /// de-duplicated.
+    pub fn set_item_tags(&mut self, item_key: &str, tags: &[String]) -> Result<(), Error> {
+        let mut normalized_tags = tags
+            .iter()
+            .map(|tag| normalized_key(tag).ok_or_else(|| Error::InvalidTag(tag.to_string())))
+            .collect::<Result<Vec<_>, _>>()?;
+        normalized_tags.sort();
+        normalized_tags.dedup();
+
+        let item_id = *self.get_item_id(item_key)?;
+        let item = self
+            .items
+            .get_mut(&item_id)
+            .ok_or_else(|| Error::InvalidItemKey(item_key.to_string()))?;
+        item.tags = normalized_tags;
+        self.metadata_blobs.remove(&item_id);
+        Ok(())
+    }
+
+    /// Change the key of an item. Credential ids are unchanged, so values and
+    /// history don't need to be re-encrypted.
+    pub fn rename_item(&mut self, item_key: &str, new_item_key: &str) -> Result<(), Error> {
+        let new_item_key = normalized_key(new_item_key)
+            .ok_or_else(|| Error::InvalidItemKey(new_item_key.to_string()))?;
+        if self.item_index.contains_key(&new_item_key) {
+            return Err(Error::DuplicateItemKey(new_item_key));
+        }
+        let item_id = *self.get_item_id(item_key)?;
+        let item = self
+            .items
+            .get_mut(&item_id)
+            .ok_or_else(|| Error::InvalidItemKey(item_key.to_string()))?;
+        let old_item_key = std::mem::replace(&mut item.key, new_item_key.clone());
+
+        self.item_index.remove(&old_item_key);
+        self.item_index.insert(new_item_key.clone(), item_id);
+        for cred in item.credentials.values() {
+            let ids = self
+                .item_credential_index
+                .remove(&(old_item_key.clone(), cred.key.clone()))
+                .unwrap_or((item_id, cred.id));
+            self.item_credential_index
+                .insert((new_item_key.clone(), cred.key.clone()), ids);
+        }
+        if let Some(alias) = self.aliases.remove(&new_item_key) {
+            self.metadata_blobs.remove(&alias.id);
+        }
+        self.metadata_blobs.remove(&item_id);
+        Ok(())
+    }
+
+    pub fn rename_item_credential(
+        &mut self,
+        item_key: &str,
+        cred_key: &str,
+        new_cred_key: &str,
+    ) -> Result<(), Error> {
+        let new_cred_key = normalized_key(new_cred_key)
+            .ok_or_else(|| Error::InvalidCredentialKey(new_cred_key.to_string()))?;
+        let item = self.get_item(item_key)?;
+        let (item_id, item_key) = (item.id, item.key.clone());
+        if self
+            .item_credential_index
+            .contains_key(&(item_key.clone(), new_cred_key.clone()))
+        {
+            return Err(Error::DuplicateCredentialKey(format!(
+                "{item_key}/{new_cred_key}"
+            )));
+        }
+        let cred_id = self.get_item_credential(&item_key, cred_key)?.id;
+        let cred = self
+            .items
+            .get_mut(&item_id)
+            .and_then(|item| item.credentials.get_mut(&cred_id))
+            .ok_or_else(|| Error::InvalidCredentialKey(cred_key.to_string()))?;
+        let old_cred_key = std::mem::replace(&mut cred.key, new_cred_key.clone());
+
+        self.item_credential_index
+            .remove(&(item_key.clone(), old_cred_key));
+        self.item_credential_index
+            .insert((item_key, new_cred_key), (item_id, cred_id));
+        self.metadata_blobs.remove(&cred_id);
+        Ok(())
+    }
+
+    /// Decrypt an item with all its credential values and history, to be
+    /// re-encrypted under another key or vault with import_item.
+    pub fn export_item(&self, item_key: &str) -> Result<ItemTransfer, Error> {
+        let item = self.get_item(item_key)?;
+        let mut credentials = Vec::with_capacity(item.credentials.len());
+        for (cred_id, cred) in &item.credentials {
+            let value = self
+                .secrets
+                .get(cred_id)
+                .map(|encrypted| self.cipher.decrypt_cred_value(item.id, *cred_id, encrypted))
+                .transpose()?;
+            let history = self
+                .history
+                .get(cred_id)
+                .map_or(&[][..], |entries| entries)
+                .iter()
+                .map(|entry| {
+                    self.cipher
+                        .decrypt_cred_history_value(item.id, *cred_id, entry.version, &entry.value)
+                        .map(|value| (entry.version, entry.replaced_at, value))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            credentials.push(CredentialTransfer {
+                title: cred.title.clone(),
+                key: cred.key.clone(),
+                kind: cred.kind,
+                expires_at: cred.expires_at,
+                value,
+                history,
+            });
+        }
+        let attachments = item
+            .attachments
+            .iter()
+            .map(|(attachment_id, attachment)| {
+                Ok(AttachmentTransfer {
+                    key: attachment.key.clone(),
+                    file_name: attachment.file_name.clone(),
+                    data: self.read_attachment(item.id, *attachment_id)?,
+                    sidecar: self
+                        .attachments
+                        .get(attachment_id)
+                        .is_some_and(AttachmentContent::is_sidecar),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(ItemTransfer {
+            title: item.title.clone(),
+            tags: item.tags.clone(),
+            credentials,
+            attachments,
+        })
+    }
+
+    /// Add an item exported with export_item under a new key. The item and its
+    /// credentials get new ids, and values and history are encrypted with this
+    /// vault's file key, since the vault, item and credential ids are bound
+    /// into the additional authenticated data.
+    pub fn import_item(
+        &mut self,
+        item_key: &str,
+        transfer: ItemTransfer,
+    ) -> Result<&VaultItemOverview, Error> {
+        let normalized_item_key =
+            normalized_key(item_key).ok_or_else(|| Error::InvalidItemKey(item_key.to_string()))?;
+        if self.item_index.contains_key(&normalized_item_key) {
+            return Err(Error::DuplicateItemKey(normalized_item_key));
+        }
+
+        let mut item = VaultItemOverview {
+            tags: transfer.tags,
+            ..VaultItemOverview::try_new(&transfer.title, &normalized_item_key)?
+        };
+        for cred in transfer.credentials {
+            let cred_overview = VaultItemCredentialOverview {
+                kind: cred.kind,
+                expires_at: cred.expires_at,
+                ..VaultItemCredentialOverview::try_new(&cred.title, &cred.key)?
+            };
+            let cred_id = cred_overview.id;
+            if let Some(value) = cred.value {
+                let encrypted_secret =
+                    self.cipher
+                        .encrypt_cred_value(item.id, cred_id, value.expose_secret())?;
+                self.secrets.insert(cred_id, encrypted_secret);
+            }
+            if !cred.history.is_empty() {
+                let entries = cred
+                    .history
+                    .into_iter()
+                    .map(|(version, replaced_at, value)| {
+                        Ok(EncryptedCredentialHistoryEntry {
+                            version,
+                            replaced_at,
+                            value: self.cipher.encrypt_cred_history_value(
+                                item.id,
+                                cred_id,
+                                version,
+                                value.expose_secret(),
+                            )?,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, Error>>()?;
+                self.history.insert(cred_id, entries);
+            }
+            self.item_credential_index.insert(
+                (normalized_item_key.clone(), cred_overview.key.clone()),
+                (item.id, cred_id),
+            );
+            item.credentials.insert(cred_id, cred_overview);
+        }
+        for attachment in transfer.attachments {
+            let attachment_overview =
+                VaultItemAttachmentOverview::try_new(&attachment.file_name, &attachment.key)?;
+            let blob = self.cipher.encrypt_attachment(
+                item.id,
+                attachment_overview.id,
+                attachment.data.expose_secret(),
+            )?;
+            self.attachments.insert(
+                attachment_overview.id,
+                self.new_attachment_content(blob, attachment.sidecar),
+            );
+            item.attachments
+                .insert(attachment_overview.id, attachment_overview);
+        }
+
+        let item_id = item.id;
+        if let Some(alias) = self.aliases.remove(&normalized_item_key) {
+            self.metadata_blobs.remove(&alias.id);
+        }
+        self.item_index.insert(normalized_item_key, item_id);
+        self.items.insert(item_id, item);
+        Ok(self.items.get(&item_id).expect("just inserted"))
+    }
+
+    /// Leave a forwarding alias from item_key (which must no longer be an item)
+    /// to the target reference, e.g. axo://other-vault/item.
+    pub fn add_item_alias(&mut self, item_key: &str, target: &str) -> Result<(), Error> {
+        let alias_key =
+            normalized_key(item_key).ok_or_else(|| Error::InvalidItemKey(item_key.to_string()))?;
+        if self.item_index.contains_key(&alias_key) {
+            return Err(Error::DuplicateItemKey(alias_key));
+        }
+        let alias = VaultItemAlias {
+            id: Uuid::new_v4(),
+            key: alias_key.clone(),
+            target: target.to_string(),
+        };
+        if let Some(previous) = self.aliases.insert(alias_key, alias) {
+            self.metadata_blobs.remove(&previous.id);
+        }
+        Ok(())
+    }
+
+    pub fn get_item_alias(&self, item_key: &str) -> Option<&VaultItemAlias> {
+        self.aliases.get(item_key)
+    }
+
+    pub fn list_item_aliases(&self) -> Vec<&VaultItemAlias> {
+        self.aliases.values().collect()
+    }
+
     pub fn delete_item(&mut self, item_key: &str) -> Result<(), Error> {
         let item_id = *self.get_item_id(item_key)?;
 
@@ -249,6 +866,11 @@ impl Vault {
                 let composite_key = (item_key.to_string(), cred.key.clone());
                 self.item_credential_index.remove(&composite_key);
                 self.secrets.remove(&cred.id);
+                self.history.remove(&cred.id);
+            }
+            for attachment_id in item.attachments.keys() {
+                self.attachments.remove(attachment_id);
+                self.metadata_blobs.remove(attachment_id);
             }
         }
 
@@ -284,16 +906,20 @@ impl Vault {
         item_key: &str,
         cred_key: &str,
         cred_title: &str,
+        cred_kind: Option<CredentialKind>,
         cred_value: SecretString,
     ) -> Result<&VaultItemCredentialOverview, Error> {
-        // get existing cred by key or create new one
+        // get existing cred by key or create new one. the kind is kept if not
+        // given
         let cred_overview = match self.get_item_credential(item_key, cred_key) {
             Ok(existing) => VaultItemCredentialOverview {
                 title: cred_title.to_string(),
+                kind: cred_kind.unwrap_or(existing.kind),
                 ..existing.clone()
             },
-            Err(Error::InvalidCredentialKey(_)) => {
-                VaultItemCredentialOverview::try_new(cred_title, cred_key)?
+            Err(Error::InvalidCredentialKey(_)) => VaultItemCredentialOverview {
+                kind: cred_kind.unwrap_or_default(),
+                ..VaultItemCredentialOverview::try_new(cred_title, cred_key)?
             },
             Err(e) => return Err(e),
         };
@@ -301,23 +927,39 @@ impl Vault {
         let cred_key = cred_overview.key.clone(); // normalized key
 
         let item_id = *self.get_item_id(item_key)?;
-        let item = self
-            .items
-            .get_mut(&item_id)
-            .ok_or_else(|| Error::InvalidItemKey(item_key.to_string()))?;
 
         // update secret if secret is non-empty
         let secret = cred_value.expose_secret();
         if !secret.is_empty() {
+            cred_overview.kind.validate(secret)?;
             let encrypted_secret = self.cipher.encrypt_cred_value(item_id, cred_id, secret)?;
-            self.secrets.insert(cred_id, encrypted_secret);
-        } else if !self.secrets.contains_key(&cred_id) {
+            if let Some(previous_secret) = self.secrets.insert(cred_id, encrypted_secret) {
+                let previous_secret =
+                    self.cipher
+                        .decrypt_cred_value(item_id, cred_id, &previous_secret)?;
+                if previous_secret.expose_secret() != secret {
+                    self.push_history(item_id, cred_id, previous_secret.expose_secret())?;
+                }
+            }
+        } else if let Some(existing_secret) = self.secrets.get(&cred_id) {
+            // the kind may have changed, so check the existing value still fits
+            let existing_secret =
+                self.cipher
+                    .decrypt_cred_value(item_id, cred_id, existing_secret)?;
+            cred_overview
+                .kind
+                .validate(existing_secret.expose_secret())?;
+        } else {
             // if secret is empty and credential doesn't already exist, throw error (to
             // prevent creating credentials with empty secrets by mistake)
             return Err(Error::InvalidEmptyCredentialValue);
         }
 
         // add or update credential in item.credentials
+        let item = self
+            .items
+            .get_mut(&item_id)
+            .ok_or_else(|| Error::InvalidItemKey(item_key.to_string()))?;
         item.credentials.insert(cred_id, cred_overview);
 
         // update indices. note: if credential already exists, the following should just
@@ -348,6 +990,7 @@ impl Vault {
         // remove from indices and secrets map
         self.item_credential_index.remove(&composite_key);
         self.secrets.remove(&cred_id);
+        self.history.remove(&cred_id);
         self.metadata_blobs.remove(&cred_id); // for simplicity, always attempt this
 
         Ok(())
@@ -369,6 +1012,117 @@ impl Vault {
             .decrypt_cred_value(item_id, cred_id, encrypted_secret)?;
         Ok(Some(plaintext))
     }
+
+    /// Set or clear the expiry of a credential.
+    pub fn set_item_credential_expiry(
+        &mut self,
+        item_key: &str,
+        cred_key: &str,
+        expires_at: Option<OffsetDateTime>,
+    ) -> Result<(), Error> {
+        let item_id = self.get_item(item_key)?.id;
+        let cred_id = self.get_item_credential(item_key, cred_key)?.id;
+        let cred = self
+            .items
+            .get_mut(&item_id)
+            .and_then(|item| item.credentials.get_mut(&cred_id))
+            .ok_or_else(|| Error::InvalidCredentialKey(cred_key.to_string()))?;
+        cred.expires_at = expires_at;
+        self.metadata_blobs.remove(&cred_id);
+        Ok(())
+    }
+
+    /// Replace the value of an existing credential without recording the
+    /// previous value in its history, for bookkeeping updates such as HOTP
+    /// counters.
+    pub fn replace_item_credential_secret(
+        &mut self,
+        item_key: &str,
+        cred_key: &str,
+        cred_value: SecretString,
+    ) -> Result<(), Error> {
+        let item_id = self.get_item(item_key)?.id;
+        let cred_id = self.get_item_credential(item_key, cred_key)?.id;
+        let encrypted_secret =
+            self.cipher
+                .encrypt_cred_value(item_id, cred_id, cred_value.expose_secret())?;
+        self.secrets.insert(cred_id, encrypted_secret);
+        Ok(())
+    }
+
+    // Move the previous value of a credential into its history, dropping the
+    // oldest entries beyond MAX_CREDENTIAL_HISTORY.
+    fn push_history(
+        &mut self,
+        item_id: ItemId,
+        cred_id: CredentialId,
+        previous_value: &str,
+    ) -> Result<(), Error> {
+        let entries = self.history.entry(cred_id).or_default();
+        let version = entries.last().map_or(1, |entry| entry.version + 1);
+        entries.push(EncryptedCredentialHistoryEntry {
+            version,
+            replaced_at: OffsetDateTime::now_utc(),
+            value: self.cipher.encrypt_cred_history_value(
+                item_id,
+                cred_id,
+                version,
+                previous_value,
+            )?,
+        });
+        if entries.len() > MAX_CREDENTIAL_HISTORY {
+            entries.drain(..entries.len() - MAX_CREDENTIAL_HISTORY);
+        }
+        Ok(())
+    }
+
+    /// Previous values of a credential, oldest first.
+    pub fn get_item_credential_history(
+        &self,
+        item_key: &str,
+        cred_key: &str,
+    ) -> Result<&[EncryptedCredentialHistoryEntry], Error> {
+        let cred_id = self.get_item_credential(item_key, cred_key)?.id;
+        Ok(self.history.get(&cred_id).map_or(&[], |entries| entries))
+    }
+
+    pub fn get_item_credential_history_secret(
+        &self,
+        item_key: &str,
+        cred_key: &str,
+        version: u32,
+    ) -> Result<SecretBox<String>, Error> {
+        let item_id = self.get_item(item_key)?.id;
+        let cred_id = self.get_item_credential(item_key, cred_key)?.id;
+        let entry = self
+            .get_item_credential_history(item_key, cred_key)?
+            .iter()
+            .find(|entry| entry.version == version)
+            .ok_or(Error::InvalidCredentialVersion(version))?;
+        self.cipher
+            .decrypt_cred_history_value(item_id, cred_id, version, &entry.value)
+    }
+
+    /// Restore a previous value of a credential. The current value is moved
+    /// into the history, so a rollback can itself be rolled back.
+    pub fn rollback_item_credential(
+        &mut self,
+        item_key: &str,
+        cred_key: &str,
+        version: u32,
+    ) -> Result<(), Error> {
+        let previous_value =
+            self.get_item_credential_history_secret(item_key, cred_key, version)?;
+        let cred_title = self.get_item_credential(item_key, cred_key)?.title.clone();
+        self.add_or_update_item_credential(
+            item_key,
+            cred_key,
+            &cred_title,
+            None,
+            SecretString::from(previous_value.expose_secret().as_str()),
+        )?;
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
@@ -376,7 +1130,9 @@ pub struct VaultItemOverview {
     pub id: Uuid,
     pub title: String,
     pub key: String,
+    pub tags: Vec<String>,
     pub credentials: BTreeMap<Uuid, VaultItemCredentialOverview>,
+    pub attachments: BTreeMap<Uuid, VaultItemAttachmentOverview>,
 }
 
 impl VaultItemOverview {
@@ -386,7 +1142,9 @@ impl VaultItemOverview {
             id: Uuid::new_v4(),
             title: title.to_string(),
             key: item_key,
+            tags: Vec::new(),
             credentials: BTreeMap::new(),
+            attachments: BTreeMap::new(),
         })
     }
 }
@@ -396,6 +1154,8 @@ pub struct VaultItemCredentialOverview {
     pub id: Uuid,
     pub title: String,
     pub key: String,
+    pub kind: CredentialKind,
+    pub expires_at: Option<OffsetDateTime>,
 }
 
 impl VaultItemCredentialOverview {
@@ -406,14 +1166,71 @@ impl VaultItemCredentialOverview {
             id: Uuid::new_v4(),
             title: title.to_string(),
             key: cred_key,
+            kind: CredentialKind::default(),
+            expires_at: None,
         })
     }
 }
 
+/// A forwarding alias left in place of an item that was moved to another vault
+/// or renamed, so that existing references keep resolving. The target is a
+/// reference to the item, e.g. axo://other-vault/item.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VaultItemAlias {
+    pub id: Uuid,
+    pub key: String,
+    pub target: String,
+}
+
+/// A decrypted copy of an item with its credential values and history, used to
+/// copy or move an item between vaults, see Vault::export_item and
+/// Vault::import_item.
+pub struct ItemTransfer {
+    pub title: String,
+    pub tags: Vec<String>,
+    pub credentials: Vec<CredentialTransfer>,
+    pub attachments: Vec<AttachmentTransfer>,
+}
+
+pub struct AttachmentTransfer {
+    pub key: String,
+    pub file_name: String,
+    pub data: SecretBox<Vec<u8>>,
+    pub sidecar: bool,
+}
+
+pub struct CredentialTransfer {
+    pub title: String,
+    pub key: String,
+    pub kind: CredentialKind,
+    pub expires_at: Option<OffsetDateTime>,
+    pub value: Option<SecretBox<String>>,
+    // (version, replaced_at, value), oldest first
+    pub history: Vec<(u32, OffsetDateTime, SecretBox<String>)>,
+}
+
 #[derive(Serialize, Deserialize, Zeroize)]
 pub struct VaultFieldMetadata {
     pub title: String,
     pub key: String,
+    // only set for credentials; None for items and for credentials written
+    // before kinds were added
+    #[zeroize(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<CredentialKind>,
+    #[zeroize(skip)]
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "time::serde::rfc3339::option"
+    )]
+    pub expires_at: Option<OffsetDateTime>,
+    // only set for items
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // only set for aliases, see VaultItemAlias
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias_of: Option<String>,
 }
 
 impl VaultFieldMetadata {
@@ -422,6 +1239,561 @@ impl VaultFieldMetadata {
         Ok(Self {
             title: title.to_string(),
             key: cred_key,
+            kind: None,
+            expires_at: None,
+            tags: Vec::new(),
+            alias_of: None,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(super) fn make_shared_vault(identity: &age::x25519::Identity) -> Vault {
+        let raw_key = Aes256Gcm::generate_key(OsRng);
+        let member = VaultMember::wrap(&MemberKey::Age(identity.to_public()), &raw_key).unwrap();
+        let enc_vault = EncryptedVault {
+            version: VAULT_FORMAT_VERSION,
+            id: Uuid::new_v4(),
+            name: Some("shared vault".to_string()),
+            file_key: VaultFileKey::Members(vec![member]),
+            items: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            manifest: None,
+        };
+        let cipher = enc_vault
+            .decrypt_file_key_with_age_identity(identity)
+            .unwrap();
+        Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap()
+    }
+
+    #[test]
+    fn test_add_and_remove_member() {
+        let alice = age::x25519::Identity::generate();
+        let bob = age::x25519::Identity::generate();
+        let alice_key = MemberKey::Age(alice.to_public());
+        let bob_key = MemberKey::Age(bob.to_public());
+
+        let mut vault = make_shared_vault(&alice);
+        vault.add_or_update_item("my-item", "My Item").unwrap();
+        vault
+            .add_or_update_item_credential(
+                "my-item",
+                "password",
+                "Password",
+                None,
+                "hunter2".into(),
+            )
+            .unwrap();
+        let item_id = vault.get_item("my-item").unwrap().id;
+        let cred_id = vault.get_item_credential("my-item", "password").unwrap().id;
+
+        // bob can decrypt the vault once added, but only once
+        vault.add_member(&bob_key).unwrap();
+        let result = vault.add_member(&bob_key);
+        assert!(matches!(result, Err(Error::InvalidVaultMember(_))));
+        let bob_cipher = vault
+            .into_encrypted()
+            .unwrap()
+            .decrypt_file_key_with_age_identity(&bob)
+            .unwrap();
+
+        // after removal, bob can neither unwrap the new file key nor decrypt
+        // secrets with the old one
+        vault.remove_member(&bob_key).unwrap();
+        let enc_vault = vault.into_encrypted().unwrap();
+        assert!(enc_vault.decrypt_file_key_with_age_identity(&bob).is_err());
+        let enc_secret = &enc_vault.items[&item_id].credentials[&cred_id].value;
+        assert!(
+            bob_cipher
+                .decrypt_cred_value(item_id, cred_id, enc_secret)
+                .is_err()
+        );
+
+        // alice can still decrypt everything
+        let cipher = enc_vault
+            .decrypt_file_key_with_age_identity(&alice)
+            .unwrap();
+        let mut vault = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+        let secret = vault
+            .get_item_credential_secret("my-item", "password")
+            .unwrap()
+            .unwrap();
+        assert_eq!(secret.expose_secret(), "hunter2");
+
+        // the last member cannot be removed
+        let result = vault.remove_member(&alice_key);
+        assert!(matches!(result, Err(Error::InvalidVaultMember(_))));
+    }
+
+    #[test]
+    fn test_credential_history_and_rollback() {
+        let alice = age::x25519::Identity::generate();
+        let mut vault = make_shared_vault(&alice);
+        vault.add_or_update_item("my-item", "My Item").unwrap();
+        for value in ["v1", "v2", "v2", "v3"] {
+            vault
+                .add_or_update_item_credential(
+                    "my-item",
+                    "password",
+                    "Password",
+                    None,
+                    value.into(),
+                )
+                .unwrap();
+        }
+
+        // unchanged values are not recorded
+        let history = vault
+            .get_item_credential_history("my-item", "password")
+            .unwrap();
+        assert_eq!(
+            history.iter().map(|e| e.version).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        let secret = vault
+            .get_item_credential_history_secret("my-item", "password", 1)
+            .unwrap();
+        assert_eq!(secret.expose_secret(), "v1");
+
+        // a history entry cannot be decrypted as the current value
+        let item_id = vault.get_item("my-item").unwrap().id;
+        let cred_id = vault.get_item_credential("my-item", "password").unwrap().id;
+        let enc_vault = vault.into_encrypted().unwrap();
+        let enc_cred = &enc_vault.items[&item_id].credentials[&cred_id];
+        let cipher = enc_vault
+            .decrypt_file_key_with_age_identity(&alice)
+            .unwrap();
+        assert!(
+            cipher
+                .decrypt_cred_value(item_id, cred_id, &enc_cred.history[0].value)
+                .is_err()
+        );
+
+        // rolling back moves the current value into history
+        let mut vault = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+        vault
+            .rollback_item_credential("my-item", "password", 1)
+            .unwrap();
+        let secret = vault
+            .get_item_credential_secret("my-item", "password")
+            .unwrap()
+            .unwrap();
+        assert_eq!(secret.expose_secret(), "v1");
+        let secret = vault
+            .get_item_credential_history_secret("my-item", "password", 3)
+            .unwrap();
+        assert_eq!(secret.expose_secret(), "v3");
+
+        let result = vault.rollback_item_credential("my-item", "password", 42);
+        assert!(matches!(result, Err(Error::InvalidCredentialVersion(42))));
+    }
+
+    #[test]
+    fn test_credential_history_is_bounded() {
+        let alice = age::x25519::Identity::generate();
+        let mut vault = make_shared_vault(&alice);
+        vault.add_or_update_item("my-item", "My Item").unwrap();
+        for i in 0..=MAX_CREDENTIAL_HISTORY + 5 {
+            vault
+                .add_or_update_item_credential(
+                    "my-item",
+                    "password",
+                    "Password",
+                    None,
+                    format!("value-{i}").into(),
+                )
+                .unwrap();
+        }
+
+        let history = vault
+            .get_item_credential_history("my-item", "password")
+            .unwrap();
+        assert_eq!(history.len(), MAX_CREDENTIAL_HISTORY);
+        assert_eq!(history[0].version, 6);
+        let secret = vault
+            .get_item_credential_history_secret("my-item", "password", 6)
+            .unwrap();
+        assert_eq!(secret.expose_secret(), "value-5");
+    }
+
+    #[test]
+    fn test_rotate_file_key() {
+        let alice = age::x25519::Identity::generate();
+        let mut vault = make_shared_vault(&alice);
+        vault.add_or_update_item("my-item", "My Item").unwrap();
+        for value in ["v1", "v2"] {
+            vault
+                .add_or_update_item_credential(
+                    "my-item",
+                    "password",
+                    "Password",
+                    None,
+                    value.into(),
+                )
+                .unwrap();
+        }
+        let item_id = vault.get_item("my-item").unwrap().id;
+        let cred_id = vault.get_item_credential("my-item", "password").unwrap().id;
+        let old_cipher = vault
+            .into_encrypted()
+            .unwrap()
+            .decrypt_file_key_with_age_identity(&alice)
+            .unwrap();
+
+        vault.rotate_file_key(None).unwrap();
+
+        // the old key cannot decrypt metadata, values or history
+        let enc_vault = vault.into_encrypted().unwrap();
+        let enc_item = &enc_vault.items[&item_id];
+        let enc_cred = &enc_item.credentials[&cred_id];
+        assert!(
+            old_cipher
+                .decrypt_item_metadata(item_id, &enc_item.metadata)
+                .is_err()
+        );
+        assert!(
+            old_cipher
+                .decrypt_cred_metadata(item_id, cred_id, &enc_cred.metadata)
+                .is_err()
+        );
+        assert!(
+            old_cipher
+                .decrypt_cred_value(item_id, cred_id, &enc_cred.value)
+                .is_err()
+        );
+        assert!(
+            old_cipher
+                .decrypt_cred_history_value(item_id, cred_id, 1, &enc_cred.history[0].value)
+                .is_err()
+        );
+
+        // the re-wrapped key can
+        let cipher = enc_vault
+            .decrypt_file_key_with_age_identity(&alice)
+            .unwrap();
+        let vault = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+        let secret = vault
+            .get_item_credential_secret("my-item", "password")
+            .unwrap()
+            .unwrap();
+        assert_eq!(secret.expose_secret(), "v2");
+        let secret = vault
+            .get_item_credential_history_secret("my-item", "password", 1)
+            .unwrap();
+        assert_eq!(secret.expose_secret(), "v1");
+    }
+
+    #[test]
+    fn test_credential_kind() {
+        let alice = age::x25519::Identity::generate();
+        let mut vault = make_shared_vault(&alice);
+        vault.add_or_update_item("my-item", "My Item").unwrap();
+        vault
+            .add_or_update_item_credential(
+                "my-item",
+                "website",
+                "Website",
+                Some(CredentialKind::Url),
+                "https://example.com".into(),
+            )
+            .unwrap();
+
+        // values are validated against the kind, which is kept on update
+        let result = vault.add_or_update_item_credential(
+            "my-item",
+            "website",
+            "Website",
+            None,
+            "not a url".into(),
+        );
+        assert!(matches!(
+            result,
+            Err(Error::InvalidCredentialValue(CredentialKind::Url, _))
+        ));
+
+        // changing the kind checks the existing value
+        let result = vault.add_or_update_item_credential(
+            "my-item",
+            "website",
+            "Website",
+            Some(CredentialKind::TotpSeed),
+            "".into(),
+        );
+        assert!(matches!(
+            result,
+            Err(Error::InvalidCredentialValue(CredentialKind::TotpSeed, _))
+        ));
+
+        // the kind is stored in the encrypted metadata
+        let item_id = vault.get_item("my-item").unwrap().id;
+        let cred_id = vault.get_item_credential("my-item", "website").unwrap().id;
+        let enc_vault = vault.into_encrypted().unwrap();
+        let cipher = enc_vault
+            .decrypt_file_key_with_age_identity(&alice)
+            .unwrap();
+        let vault = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+        let cred = vault.get_item_credential("my-item", "website").unwrap();
+        assert_eq!(cred.kind, CredentialKind::Url);
+
+        // metadata written without a kind loads as the default kind
+        let mut enc_vault = vault.into_encrypted().unwrap();
+        let cipher = enc_vault
+            .decrypt_file_key_with_age_identity(&alice)
+            .unwrap();
+        let legacy_metadata = VaultFieldMetadata::try_new("Website", "website").unwrap();
+        enc_vault
+            .items
+            .get_mut(&item_id)
+            .unwrap()
+            .credentials
+            .get_mut(&cred_id)
+            .unwrap()
+            .metadata = cipher
+            .encrypt_cred_metadata(item_id, cred_id, &legacy_metadata)
+            .unwrap();
+        let vault = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+        let cred = vault.get_item_credential("my-item", "website").unwrap();
+        assert_eq!(cred.kind, CredentialKind::Password);
+    }
+
+    #[test]
+    fn test_credential_expiry() {
+        let alice = age::x25519::Identity::generate();
+        let mut vault = make_shared_vault(&alice);
+        vault.add_or_update_item("my-item", "My Item").unwrap();
+        vault
+            .add_or_update_item_credential("my-item", "token", "Token", None, "abc".into())
+            .unwrap();
+        assert_eq!(
+            vault
+                .get_item_credential("my-item", "token")
+                .unwrap()
+                .expires_at,
+            None
+        );
+
+        let expires_at = OffsetDateTime::from_unix_timestamp(1_900_000_000).unwrap();
+        vault
+            .set_item_credential_expiry("my-item", "token", Some(expires_at))
+            .unwrap();
+
+        // the expiry is stored in the encrypted metadata and survives updates
+        let enc_vault = vault.into_encrypted().unwrap();
+        let cipher = enc_vault
+            .decrypt_file_key_with_age_identity(&alice)
+            .unwrap();
+        let mut vault = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+        vault
+            .add_or_update_item_credential("my-item", "token", "Token", None, "def".into())
+            .unwrap();
+        let cred = vault.get_item_credential("my-item", "token").unwrap();
+        assert_eq!(cred.expires_at, Some(expires_at));
+
+        vault
+            .set_item_credential_expiry("my-item", "token", None)
+            .unwrap();
+        let enc_vault = vault.into_encrypted().unwrap();
+        let cipher = enc_vault
+            .decrypt_file_key_with_age_identity(&alice)
+            .unwrap();
+        let vault = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+        let cred = vault.get_item_credential("my-item", "token").unwrap();
+        assert_eq!(cred.expires_at, None);
+    }
+
+    #[test]
+    fn test_item_tags() {
+        let alice = age::x25519::Identity::generate();
+        let mut vault = make_shared_vault(&alice);
+        vault.add_or_update_item("stripe", "Stripe").unwrap();
+        vault
+            .set_item_tags(
+                "stripe",
+                &[
+                    "Staging".to_string(),
+                    "payments".to_string(),
+                    "staging".to_string(),
+                ],
+            )
+            .unwrap();
+        assert_eq!(
+            vault.get_item("stripe").unwrap().tags,
+            ["payments", "staging"]
+        );
+        assert!(matches!(
+            vault.set_item_tags("stripe", &["not a tag!".to_string()]),
+            Err(Error::InvalidTag(_))
+        ));
+
+        // tags are stored in the encrypted item metadata and kept on update
+        let enc_vault = vault.into_encrypted().unwrap();
+        let cipher = enc_vault
+            .decrypt_file_key_with_age_identity(&alice)
+            .unwrap();
+        let mut vault = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+        vault.add_or_update_item("stripe", "Stripe API").unwrap();
+        assert_eq!(
+            vault.get_item("stripe").unwrap().tags,
+            ["payments", "staging"]
+        );
+    }
+
+    #[test]
+    fn test_rename_item_and_credential() {
+        let alice = age::x25519::Identity::generate();
+        let mut vault = make_shared_vault(&alice);
+        vault.add_or_update_item("stripe", "Stripe").unwrap();
+        vault.add_or_update_item("github", "GitHub").unwrap();
+        vault
+            .add_or_update_item_credential("stripe", "key", "Key", None, "sk_123".into())
+            .unwrap();
+
+        assert!(matches!(
+            vault.rename_item("stripe", "github"),
+            Err(Error::DuplicateItemKey(_))
+        ));
+        vault.rename_item("stripe", "stripe-live").unwrap();
+        vault
+            .rename_item_credential("stripe-live", "key", "secret-key")
+            .unwrap();
+        vault
+            .add_item_alias("stripe", "axo://shared/stripe-live")
+            .unwrap();
+        assert!(vault.get_item("stripe").is_err());
+
+        // values are still readable, and keys and aliases are persisted
+        let enc_vault = vault.into_encrypted().unwrap();
+        let cipher = enc_vault
+            .decrypt_file_key_with_age_identity(&alice)
+            .unwrap();
+        let mut vault = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+        let secret = vault
+            .get_item_credential_secret("stripe-live", "secret-key")
+            .unwrap()
+            .unwrap();
+        assert_eq!(secret.expose_secret(), "sk_123");
+        assert_eq!(
+            vault.get_item_alias("stripe").unwrap().target,
+            "axo://shared/stripe-live"
+        );
+
+        // re-creating the item replaces the alias
+        vault.add_or_update_item("stripe", "Stripe").unwrap();
+        assert!(vault.get_item_alias("stripe").is_none());
+    }
+
+    #[test]
+    fn test_export_and_import_item() {
+        let alice = age::x25519::Identity::generate();
+        let mut source = make_shared_vault(&alice);
+        let mut target = make_shared_vault(&alice);
+        source.add_or_update_item("stripe", "Stripe").unwrap();
+        source
+            .set_item_tags("stripe", &["payments".to_string()])
+            .unwrap();
+        source
+            .add_or_update_item_credential(
+                "stripe",
+                "key",
+                "Key",
+                Some(CredentialKind::Password),
+                "v1".into(),
+            )
+            .unwrap();
+        source
+            .add_or_update_item_credential("stripe", "key", "Key", None, "v2".into())
+            .unwrap();
+
+        let transfer = source.export_item("stripe").unwrap();
+        target.import_item("stripe-copy", transfer).unwrap();
+
+        // values and history are re-encrypted for the target vault
+        let enc_vault = target.into_encrypted().unwrap();
+        let cipher = enc_vault
+            .decrypt_file_key_with_age_identity(&alice)
+            .unwrap();
+        let mut target = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+        let item = target.get_item("stripe-copy").unwrap();
+        assert_eq!(item.title, "Stripe");
+        assert_eq!(item.tags, ["payments"]);
+        assert_ne!(item.id, source.get_item("stripe").unwrap().id);
+        let secret = target
+            .get_item_credential_secret("stripe-copy", "key")
+            .unwrap()
+            .unwrap();
+        assert_eq!(secret.expose_secret(), "v2");
+        let previous = target
+            .get_item_credential_history_secret("stripe-copy", "key", 1)
+            .unwrap();
+        assert_eq!(previous.expose_secret(), "v1");
+
+        let transfer = source.export_item("stripe").unwrap();
+        assert!(matches!(
+            target.import_item("stripe-copy", transfer),
+            Err(Error::DuplicateItemKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_manifest_detects_tampering() {
+        let alice = age::x25519::Identity::generate();
+        let mut vault = make_shared_vault(&alice);
+        vault.add_or_update_item("aws", "AWS").unwrap();
+        vault.add_or_update_item("github", "GitHub").unwrap();
+        vault
+            .add_or_update_item_credential("github", "token", "Token", None, "ghp".into())
+            .unwrap();
+        vault.bump_revision(0);
+        vault.bump_revision(0);
+
+        let reload = |enc_vault: EncryptedVault| {
+            let cipher = enc_vault
+                .decrypt_file_key_with_age_identity(&alice)
+                .unwrap();
+            Vault::from_encrypted_with_cipher(cipher, enc_vault)
+        };
+
+        // an untouched file round trips with its revision
+        let reloaded = reload(vault.into_encrypted().unwrap()).unwrap();
+        assert_eq!(reloaded.revision(), 2);
+
+        // removing an item
+        let mut enc_vault = vault.into_encrypted().unwrap();
+        let aws_id = *vault.get_item_id("aws").unwrap();
+        enc_vault.items.remove(&aws_id);
+        assert!(matches!(
+            reload(enc_vault),
+            Err(Error::VaultManifestMismatch(_))
+        ));
+
+        // removing a credential
+        let mut enc_vault = vault.into_encrypted().unwrap();
+        let github_id = *vault.get_item_id("github").unwrap();
+        enc_vault
+            .items
+            .get_mut(&github_id)
+            .unwrap()
+            .credentials
+            .clear();
+        assert!(matches!(
+            reload(enc_vault),
+            Err(Error::VaultManifestMismatch(_))
+        ));
+
+        // swapping in the manifest of another vault
+        let mut enc_vault = vault.into_encrypted().unwrap();
+        enc_vault.manifest = make_shared_vault(&alice).into_encrypted().unwrap().manifest;
+        assert!(matches!(
+            reload(enc_vault),
+            Err(Error::VaultManifestMismatch(_))
+        ));
+
+        // files saved before manifests were added load at revision 0
+        let mut enc_vault = vault.into_encrypted().unwrap();
+        enc_vault.manifest = None;
+        assert_eq!(reload(enc_vault).unwrap().revision(), 0);
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use secrecy::SecretBox;
+use uuid::Uuid;
+
+use crate::core::atomic_file::write_atomic;
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::vault::Vault;
+use crate::secrets::vaults::vault::encrypted_blob::EncryptedBlob;
+use crate::secrets::vaults::vault::encrypted_vault::EncryptedAttachmentContent;
+use crate::secrets::vaults::vault_wrapper::normalized_key;
+
+// sidecar files are stored in attachments/<vault id>/ next to the vault file
+const SIDECAR_DIR: &str = "attachments";
+
+#[derive(Clone)]
+pub struct VaultItemAttachmentOverview {
+    pub id: Uuid,
+    pub key: String,
+    pub file_name: String,
+}
+
+impl VaultItemAttachmentOverview {
+    pub fn try_new(file_name: &str, key: &str) -> Result<Self, Error> {
+        let attachment_key =
+            normalized_key(key).ok_or_else(|| Error::InvalidAttachmentKey(key.to_string()))?;
+        Ok(Self {
+            id: Uuid::new_v4(),
+            key: attachment_key,
+            file_name: file_name.to_string(),
+        })
+    }
+}
+
+/// Encrypted contents of an attachment, see EncryptedAttachmentContent. A
+/// sidecar's contents are read from its file when needed; `unsaved` holds
+/// contents that still need to be written by save_sidecars.
+pub(super) enum AttachmentContent {
+    Inline(EncryptedBlob<Vec<u8>>),
+    Sidecar {
+        path: PathBuf,
+        unsaved: Option<EncryptedBlob<Vec<u8>>>,
+    },
+}
+
+impl From<EncryptedAttachmentContent> for AttachmentContent {
+    fn from(content: EncryptedAttachmentContent) -> Self {
+        match content {
+            EncryptedAttachmentContent::Inline(blob) => AttachmentContent::Inline(blob),
+            EncryptedAttachmentContent::Sidecar(path) => AttachmentContent::Sidecar {
+                path,
+                unsaved: None,
+            },
+        }
+    }
+}
+
+impl AttachmentContent {
+    pub(super) fn to_encrypted(&self) -> EncryptedAttachmentContent {
+        match self {
+            AttachmentContent::Inline(blob) => EncryptedAttachmentContent::Inline(blob.clone()),
+            AttachmentContent::Sidecar { path, .. } => {
+                EncryptedAttachmentContent::Sidecar(path.clone())
+            },
+        }
+    }
+
+    pub(super) fn is_sidecar(&self) -> bool {
+        matches!(self, AttachmentContent::Sidecar { .. })
+    }
+}
+
+impl Vault {
+    /// Directory of the vault file, which sidecar paths are relative to.
+    pub fn set_vault_dir(&mut self, vault_dir: &Path) {
+        self.vault_dir = Some(vault_dir.to_path_buf());
+    }
+
+    pub fn get_item_attachment(
+        &self,
+        item_key: &str,
+        attachment_key: &str,
+    ) -> Result<&VaultItemAttachmentOverview, Error> {
+        self.get_item(item_key)?
+            .attachments
+            .values()
+            .find(|attachment| attachment.key == attachment_key)
+            .ok_or_else(|| Error::InvalidAttachmentKey(format!("{item_key}/{attachment_key}")))
+    }
+
+    /// Add or replace an attachment. Its contents are stored inline in the
+    /// vault file, or in a sidecar file written when the vault is saved.
+    pub fn add_or_update_item_attachment(
+        &mut self,
+        item_key: &str,
+        attachment_key: &str,
+        file_name: &str,
+        data: &[u8],
+        sidecar: bool,
+    ) -> Result<&VaultItemAttachmentOverview, Error> {
+        let attachment = match self.get_item_attachment(item_key, attachment_key) {
+            Ok(existing) => VaultItemAttachmentOverview {
+                file_name: file_name.to_string(),
+                ..existing.clone()
+            },
+            Err(Error::InvalidAttachmentKey(_)) => {
+                VaultItemAttachmentOverview::try_new(file_name, attachment_key)?
+            },
+            Err(e) => return Err(e),
+        };
+        let attachment_id = attachment.id;
+        let item_id = *self.get_item_id(item_key)?;
+
+        let blob = self
+            .cipher
+            .encrypt_attachment(item_id, attachment_id, data)?;
+        self.attachments
+            .insert(attachment_id, self.new_attachment_content(blob, sidecar));
+        self.metadata_blobs.remove(&attachment_id);
+
+        let item = self
+            .items
+            .get_mut(&item_id)
+            .ok_or_else(|| Error::InvalidItemKey(item_key.to_string()))?;
+        item.attachments.insert(attachment_id, attachment);
+        Ok(item.attachments.get(&attachment_id).expect("just inserted"))
+    }
+
+    pub fn get_item_attachment_data(
+        &self,
+        item_key: &str,
+        attachment_key: &str,
+    ) -> Result<SecretBox<Vec<u8>>, Error> {
+        let item_id = *self.get_item_id(item_key)?;
+        let attachment_id = self.get_item_attachment(item_key, attachment_key)?.id;
+        self.read_attachment(item_id, attachment_id)
+    }
+
+    pub fn delete_item_attachment(
+        &mut self,
+        item_key: &str,
+        attachment_key: &str,
+    ) -> Result<(), Error> {
+        let item_id = *self.get_item_id(item_key)?;
+        let attachment_id = self.get_item_attachment(item_key, attachment_key)?.id;
+        if let Some(item) = self.items.get_mut(&item_id) {
+            item.attachments.remove(&attachment_id);
+        }
+        // the sidecar file, if any, is removed by prune_sidecars
+        self.attachments.remove(&attachment_id);
+        self.metadata_blobs.remove(&attachment_id);
+        Ok(())
+    }
+
+    /// Write the sidecar files of attachments added or re-encrypted since the
+    /// vault was loaded. Every write uses a new path, so the vault file on
+    /// disk stays consistent until it is replaced.
+    pub fn save_sidecars(&mut self) -> Result<(), Error> {
+        for content in self.attachments.values_mut() {
+            let AttachmentContent::Sidecar { path, unsaved } = content else {
+                continue;
+            };
+            let Some(blob) = unsaved else {
+                continue;
+            };
+            let vault_dir = self.vault_dir.as_deref().ok_or_else(unknown_vault_dir)?;
+            let sidecar_path = vault_dir.join(&*path);
+            if let Some(parent) = sidecar_path.parent() {
+                fs::create_dir_all(parent).map_err(Error::AttachmentWriteError)?;
+            }
+            write_atomic(&sidecar_path, &blob.to_bytes()).map_err(Error::AttachmentWriteError)?;
+            *unsaved = None;
+        }
+        Ok(())
+    }
+
+    /// Remove sidecar files that are no longer referenced, e.g. after an
+    /// attachment was replaced or deleted. Called after the vault file was
+    /// written.
+    pub fn prune_sidecars(&self) -> Result<(), Error> {
+        let Some(vault_dir) = self.vault_dir.as_deref() else {
+            return Ok(());
+        };
+        let sidecar_dir = vault_dir.join(SIDECAR_DIR).join(self.id.to_string());
+        let entries = match fs::read_dir(&sidecar_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(Error::AttachmentWriteError(e)),
+        };
+        let referenced = self
+            .attachments
+            .values()
+            .filter_map(|content| match content {
+                AttachmentContent::Sidecar { path, .. } => Some(vault_dir.join(path)),
+                AttachmentContent::Inline(_) => None,
+            })
+            .collect::<BTreeSet<_>>();
+        for entry in entries {
+            let path = entry.map_err(Error::AttachmentWriteError)?.path();
+            if !referenced.contains(&path) {
+                log::debug!("Removing unreferenced attachment {}", path.display());
+                fs::remove_file(&path).map_err(Error::AttachmentWriteError)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn new_attachment_content(
+        &self,
+        blob: EncryptedBlob<Vec<u8>>,
+        sidecar: bool,
+    ) -> AttachmentContent {
+        if !sidecar {
+            return AttachmentContent::Inline(blob);
+        }
+        AttachmentContent::Sidecar {
+            path: PathBuf::from(SIDECAR_DIR)
+                .join(self.id.to_string())
+                .join(Uuid::new_v4().to_string()),
+            unsaved: Some(blob),
+        }
+    }
+
+    pub(super) fn read_attachment(
+        &self,
+        item_id: Uuid,
+        attachment_id: Uuid,
+    ) -> Result<SecretBox<Vec<u8>>, Error> {
+        let blob = self.read_attachment_blob(attachment_id)?;
+        self.cipher
+            .decrypt_attachment(item_id, attachment_id, &blob)
+    }
+
+    pub(super) fn read_attachment_blob(
+        &self,
+        attachment_id: Uuid,
+    ) -> Result<EncryptedBlob<Vec<u8>>, Error> {
+        match self.attachments.get(&attachment_id) {
+            Some(AttachmentContent::Inline(blob))
+            | Some(AttachmentContent::Sidecar {
+                unsaved: Some(blob),
+                ..
+            }) => Ok(blob.clone()),
+            Some(AttachmentContent::Sidecar {
+                path,
+                unsaved: None,
+            }) => {
+                let vault_dir = self.vault_dir.as_deref().ok_or_else(unknown_vault_dir)?;
+                let bytes = fs::read(vault_dir.join(path)).map_err(Error::AttachmentReadError)?;
+                EncryptedBlob::from_bytes(&bytes)
+            },
+            None => Err(Error::InvalidAttachmentKey(attachment_id.to_string())),
+        }
+    }
+}
+
+fn unknown_vault_dir() -> Error {
+    Error::AttachmentReadError(io::Error::new(
+        io::ErrorKind::NotFound,
+        "sidecar attachments need the vault file's directory",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use secrecy::ExposeSecret;
+
+    use super::*;
+    use crate::secrets::vaults::vault::tests::make_shared_vault;
+
+    #[test]
+    fn test_inline_and_sidecar_attachments() {
+        let dir = tempfile::tempdir().unwrap();
+        let alice = age::x25519::Identity::generate();
+        let mut vault = make_shared_vault(&alice);
+        vault.set_vault_dir(dir.path());
+        vault.add_or_update_item("k8s", "Kubernetes").unwrap();
+
+        let kubeconfig = b"apiVersion: v1\nkind: Config\n".to_vec();
+        let p12 = vec![0u8, 159, 146, 150, 255];
+        vault
+            .add_or_update_item_attachment("k8s", "kubeconfig", "config", &kubeconfig, false)
+            .unwrap();
+        vault
+            .add_or_update_item_attachment("k8s", "bundle", "client.p12", &p12, true)
+            .unwrap();
+
+        vault.save_sidecars().unwrap();
+        let enc_vault = vault.into_encrypted().unwrap();
+        let cipher = enc_vault
+            .decrypt_file_key_with_age_identity(&alice)
+            .unwrap();
+        let mut vault = Vault::from_encrypted_with_cipher(cipher, enc_vault).unwrap();
+        vault.set_vault_dir(dir.path());
+
+        let data = vault.get_item_attachment_data("k8s", "kubeconfig").unwrap();
+        assert_eq!(data.expose_secret(), &kubeconfig);
+        let data = vault.get_item_attachment_data("k8s", "bundle").unwrap();
+        assert_eq!(data.expose_secret(), &p12);
+        assert_eq!(
+            vault
+                .get_item_attachment("k8s", "bundle")
+                .unwrap()
+                .file_name,
+            "client.p12"
+        );
+
+        // replacing a sidecar writes a new file and prunes the old one
+        let sidecar_dir = dir.path().join(SIDECAR_DIR).join(vault.id.to_string());
+        vault
+            .add_or_update_item_attachment("k8s", "bundle", "client.p12", b"new", true)
+            .unwrap();
+        vault.save_sidecars().unwrap();
+        assert_eq!(fs::read_dir(&sidecar_dir).unwrap().count(), 2);
+        vault.prune_sidecars().unwrap();
+        assert_eq!(fs::read_dir(&sidecar_dir).unwrap().count(), 1);
+
+        vault.delete_item_attachment("k8s", "bundle").unwrap();
+        vault.prune_sidecars().unwrap();
+        assert_eq!(fs::read_dir(&sidecar_dir).unwrap().count(), 0);
+        assert!(matches!(
+            vault.get_item_attachment_data("k8s", "bundle"),
+            Err(Error::InvalidAttachmentKey(_))
+        ));
+    }
+}
@@ -0,0 +1,164 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use typeshare::typeshare;
+use url::Url;
+
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::otp::OtpConfig;
+
+/// The kind of value stored in a credential, which determines how the value
+/// is validated and displayed. Credentials in vault files written before kinds
+/// were added have no kind in their metadata and load as the default,
+/// Password.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[typeshare]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialKind {
+    #[default]
+    Password,
+    Username,
+    Url,
+    Note,
+    TotpSeed,
+    SshKey,
+}
+
+impl CredentialKind {
+    pub const ALL: [CredentialKind; 6] = [
+        CredentialKind::Password,
+        CredentialKind::Username,
+        CredentialKind::Url,
+        CredentialKind::Note,
+        CredentialKind::TotpSeed,
+        CredentialKind::SshKey,
+    ];
+
+    /// Concealed values are only shown when explicitly read (e.g. `ap item
+    /// read`), never when listing or describing an item.
+    pub fn is_concealed(&self) -> bool {
+        match self {
+            CredentialKind::Password | CredentialKind::TotpSeed | CredentialKind::SshKey => true,
+            CredentialKind::Username | CredentialKind::Url | CredentialKind::Note => false,
+        }
+    }
+
+    pub fn validate(&self, value: &str) -> Result<(), Error> {
+        let invalid = |reason: &str| Error::InvalidCredentialValue(*self, reason.to_string());
+        match self {
+            CredentialKind::Password | CredentialKind::Note => Ok(()),
+            CredentialKind::Username => {
+                if value.contains(['\n', '\r']) {
+                    return Err(invalid("must be a single line"));
+                }
+                Ok(())
+            },
+            CredentialKind::Url => {
+                let url = Url::parse(value).map_err(|e| invalid(&e.to_string()))?;
+                if !url.has_host() {
+                    return Err(invalid("must have a host"));
+                }
+                Ok(())
+            },
+            CredentialKind::TotpSeed => match OtpConfig::parse(value) {
+                Ok(_) => Ok(()),
+                Err(Error::InvalidOtp(reason)) => Err(invalid(&reason)),
+                Err(e) => Err(e),
+            },
+            CredentialKind::SshKey => {
+                if ssh_key::PrivateKey::from_openssh(value).is_err()
+                    && ssh_key::PublicKey::from_openssh(value).is_err()
+                {
+                    return Err(invalid("must be an OpenSSH private or public key"));
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl fmt::Display for CredentialKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CredentialKind::Password => "password",
+            CredentialKind::Username => "username",
+            CredentialKind::Url => "url",
+            CredentialKind::Note => "note",
+            CredentialKind::TotpSeed => "totp_seed",
+            CredentialKind::SshKey => "ssh_key",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for CredentialKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase().replace('-', "_");
+        CredentialKind::ALL
+            .into_iter()
+            .find(|kind| kind.to_string() == s)
+            .ok_or_else(|| {
+                let kinds = CredentialKind::ALL.map(|kind| kind.to_string());
+                format!(
+                    "Invalid credential kind: {s} (expected one of {})",
+                    kinds.join(", ")
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let valid = [
+            (CredentialKind::Password, "hunter2\n"),
+            (CredentialKind::Username, "alice@example.com"),
+            (CredentialKind::Url, "https://example.com/login"),
+            (CredentialKind::Note, "line 1\nline 2"),
+            (CredentialKind::TotpSeed, "JBSW Y3DP EHPK 3PXP"),
+            (
+                CredentialKind::TotpSeed,
+                "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example",
+            ),
+            (
+                CredentialKind::SshKey,
+                "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIJdD7y3aLq454yWBdwLWbieU1ebz9/cu7/QEXn9OIeZJ",
+            ),
+        ];
+        for (kind, value) in valid {
+            assert!(kind.validate(value).is_ok(), "{kind} should accept {value}");
+        }
+
+        let invalid = [
+            (CredentialKind::Username, "alice\nbob"),
+            (CredentialKind::Url, "example.com"),
+            (CredentialKind::TotpSeed, "not base32!"),
+            (
+                CredentialKind::TotpSeed,
+                "otpauth://totp/Example:alice?issuer=Example",
+            ),
+            (CredentialKind::SshKey, "hunter2"),
+        ];
+        for (kind, value) in invalid {
+            assert!(
+                kind.validate(value).is_err(),
+                "{kind} should reject {value}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_str() {
+        for kind in CredentialKind::ALL {
+            assert_eq!(kind.to_string().parse::<CredentialKind>(), Ok(kind));
+        }
+        assert_eq!("TOTP-seed".parse(), Ok(CredentialKind::TotpSeed));
+        assert!("secret".parse::<CredentialKind>().is_err());
+    }
+}
@@ -62,13 +62,11 @@ impl<T> Clone for EncryptedBlob<T> {
     }
 }
 
-impl<T: Serialize + for<'de2> Deserialize<'de2> + Zeroize> EncryptedBlob<T> {
-    pub fn encrypt(value: &T, cipher: &Aes256Gcm, aad: Vec<String>) -> Result<Self, Error> {
+impl<T> EncryptedBlob<T> {
+    fn encrypt_raw(msg: &[u8], cipher: &Aes256Gcm, aad: Vec<String>) -> Result<Self, Error> {
         let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
         let payload = Payload {
-            msg: &serde_json::to_string(&value)
-                .map_err(|_| Error::VaultSecretEncryptionError)?
-                .into_bytes(),
+            msg,
             aad: &aad.join(":").into_bytes(),
         };
 
@@ -84,7 +82,7 @@ impl<T: Serialize + for<'de2> Deserialize<'de2> + Zeroize> EncryptedBlob<T> {
         })
     }
 
-    pub fn decrypt(&self, cipher: &Aes256Gcm, aad: Vec<String>) -> Result<SecretBox<T>, Error> {
+    fn decrypt_raw(&self, cipher: &Aes256Gcm, aad: Vec<String>) -> Result<Vec<u8>, Error> {
         #[allow(deprecated)]
         let nonce = Nonce::from_slice(&self.nonce);
         let plaintext = cipher
@@ -97,9 +95,61 @@ impl<T: Serialize + for<'de2> Deserialize<'de2> + Zeroize> EncryptedBlob<T> {
             )
             .inspect_err(|e| log::debug!("metadata decryption error: {e}"))
             .map_err(|_| Error::VaultSecretDecryptionError)?;
+        Ok(plaintext)
+    }
+
+    /// nonce || ciphertext, as stored in sidecar files and encrypted files
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut combined = self.nonce.clone();
+        combined.extend_from_slice(&self.ciphertext);
+        combined
+    }
+
+    pub fn from_bytes(combined: &[u8]) -> Result<Self, Error> {
+        if combined.len() < 12 {
+            return Err(Error::VaultSecretDecryptionError);
+        }
+        Ok(Self {
+            nonce: combined[..12].to_vec(),
+            ciphertext: combined[12..].to_vec(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T: Serialize + for<'de2> Deserialize<'de2> + Zeroize> EncryptedBlob<T> {
+    pub fn encrypt(value: &T, cipher: &Aes256Gcm, aad: Vec<String>) -> Result<Self, Error> {
+        let msg = serde_json::to_string(&value)
+            .map_err(|_| Error::VaultSecretEncryptionError)?
+            .into_bytes();
+        Self::encrypt_raw(&msg, cipher, aad)
+    }
 
+    pub fn decrypt(&self, cipher: &Aes256Gcm, aad: Vec<String>) -> Result<SecretBox<T>, Error> {
+        let plaintext = self.decrypt_raw(cipher, aad)?;
         serde_json::from_slice(&plaintext)
             .map(SecretBox::new)
             .map_err(|_| Error::VaultSecretDecryptionError)
     }
 }
+
+// Binary attachments are encrypted as is rather than as a JSON array of
+// bytes.
+impl EncryptedBlob<Vec<u8>> {
+    pub fn encrypt_bytes(
+        value: &[u8],
+        cipher: &Aes256Gcm,
+        aad: Vec<String>,
+    ) -> Result<Self, Error> {
+        Self::encrypt_raw(value, cipher, aad)
+    }
+
+    pub fn decrypt_bytes(
+        &self,
+        cipher: &Aes256Gcm,
+        aad: Vec<String>,
+    ) -> Result<SecretBox<Vec<u8>>, Error> {
+        self.decrypt_raw(cipher, aad)
+            .map(|plaintext| SecretBox::new(Box::new(plaintext)))
+    }
+}
@@ -5,30 +5,49 @@ use std::{fs, io};
 use serde::{Deserialize, Serialize};
 use serde_with::base64::Base64;
 use serde_with::serde_as;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
-use crate::secrets::keychain::managed_key::ManagedKey;
+use crate::core::atomic_file::content_digest;
 use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::migrations::migrate_vault;
 use crate::secrets::vaults::vault::encrypted_blob::EncryptedBlob;
 use crate::secrets::vaults::vault::vault_cipher::VaultCipher;
+use crate::secrets::vaults::vault::vault_manifest::VaultManifest;
+use crate::secrets::vaults::vault::vault_member::{VaultMember, VaultMembersFile};
 use crate::secrets::vaults::vault::{
-    VaultFieldMetadata, VaultItemCredentialOverview, VaultItemOverview,
+    VaultFieldMetadata, VaultItemAttachmentOverview, VaultItemCredentialOverview, VaultItemOverview,
 };
+use crate::secrets::vaults::vault_key::VaultKey;
 
 // EncryptedVault is the on disk representation of a vault, with metadata and
 // secrets encrypted. We don't persist this in memory; see Vault
 #[serde_as]
 #[derive(Serialize, Deserialize)]
 pub struct EncryptedVault {
+    pub version: u32, // see migrations.rs
     pub id: Uuid,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
     pub file_key: VaultFileKey,
     pub items: BTreeMap<Uuid, EncryptedVaultItem>,
+    // forwarding aliases left behind by moved or renamed items, see
+    // Vault::add_item_alias
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<Uuid, EncryptedBlob<VaultFieldMetadata>>,
+    // see VaultManifest; missing in vaults saved by older versions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<EncryptedBlob<VaultManifest>>,
 }
 
 impl EncryptedVault {
     pub fn load(vault_path: &Path) -> Result<Self, Error> {
+        Self::load_with_digest(vault_path).map(|(vault, _)| vault)
+    }
+
+    /// Load the vault along with a digest of the file contents, see
+    /// atomic_file::file_digest.
+    pub fn load_with_digest(vault_path: &Path) -> Result<(Self, u64), Error> {
         log::debug!("Reading vault from file: {}", vault_path.display());
         let vault_data = fs::read_to_string(vault_path).map_err(|e| {
             if e.kind() == io::ErrorKind::NotFound {
@@ -37,28 +56,56 @@ impl EncryptedVault {
                 Error::VaultReadError(e)
             }
         })?;
-        serde_json::from_str(&vault_data).map_err(Error::VaultDeserializationError)
+        let mut vault = Self::from_json(&vault_data)?;
+
+        // members are stored in a separate file, resolved relative to the vault file
+        if let VaultFileKey::MembersFile { path, members } = &mut vault.file_key {
+            let vault_dir = vault_path.parent().unwrap_or(Path::new("."));
+            *members =
+                VaultMembersFile::load(&vault_dir.join(path), vault.id, vault_data.as_bytes())?;
+        }
+        Ok((vault, content_digest(vault_data.as_bytes())))
     }
 
-    pub fn decrypt_file_key(&self, user_encryption_key: &ManagedKey) -> Result<VaultCipher, Error> {
-        let candidate_keys = match &self.file_key {
-            VaultFileKey::Personal(file_key_bytes) => vec![file_key_bytes],
-            VaultFileKey::Members(members) => {
-                members.iter().map(|member| &member.wrapped_key).collect()
-            },
-            VaultFileKey::MembersFile { .. } => {
-                todo!()
-            },
+    /// Parse a vault file, upgrading it to the current format version.
+    pub fn from_json(vault_data: &str) -> Result<Self, Error> {
+        let mut value: serde_json::Value =
+            serde_json::from_str(vault_data).map_err(Error::VaultDeserializationError)?;
+        migrate_vault(&mut value)?;
+        serde_json::from_value(value).map_err(Error::VaultDeserializationError)
+    }
+
+    pub fn decrypt_file_key(
+        &self,
+        user_encryption_key: &dyn VaultKey,
+    ) -> Result<VaultCipher, Error> {
+        let decrypted_key = match &self.file_key {
+            VaultFileKey::Personal(file_key_bytes) => user_encryption_key.decrypt(file_key_bytes),
+            // try all candidate keys
+            VaultFileKey::Members(members) | VaultFileKey::MembersFile { members, .. } => members
+                .iter()
+                .find_map(|member| member.unwrap_with_vault_key(user_encryption_key)),
         };
+        decrypted_key
+            .map(|key| VaultCipher::new_with_bytes(&key, self.id))
+            .ok_or(Error::VaultFileKeyDecryptionError)
+    }
 
-        // try all candidate keys
-        for file_key_bytes in candidate_keys {
-            if let Some(decrypted_key) = user_encryption_key.decrypt(file_key_bytes) {
-                let cipher = VaultCipher::new_with_bytes(&decrypted_key, self.id);
-                return Ok(cipher);
-            }
-        }
-        Err(Error::VaultFileKeyDecryptionError)
+    /// Decrypt the file key with an age identity. Only works for vaults shared
+    /// with an age recipient, see VaultMember.
+    pub fn decrypt_file_key_with_age_identity(
+        &self,
+        identity: &age::x25519::Identity,
+    ) -> Result<VaultCipher, Error> {
+        let decrypted_key = match &self.file_key {
+            VaultFileKey::Personal(_) => None,
+            VaultFileKey::Members(members) | VaultFileKey::MembersFile { members, .. } => members
+                .iter()
+                .find_map(|member| member.unwrap_with_age_identity(identity)),
+        };
+        decrypted_key
+            .map(|key| VaultCipher::new_with_bytes(&key, self.id))
+            .ok_or(Error::VaultFileKeyDecryptionError)
     }
 
     /// Add an item to the vault and return a reference to the newly added item.
@@ -74,11 +121,15 @@ impl EncryptedVault {
             metadata: match metadata {
                 Some(existing) => existing.clone(),
                 None => {
-                    let metadata = VaultFieldMetadata::try_new(&item.title, &item.key)?;
+                    let metadata = VaultFieldMetadata {
+                        tags: item.tags.clone(),
+                        ..VaultFieldMetadata::try_new(&item.title, &item.key)?
+                    };
                     vault_cipher.encrypt_item_metadata(item.id, &metadata)?
                 },
             },
             credentials: BTreeMap::new(),
+            attachments: BTreeMap::new(),
         };
         self.items.insert(item.id, encrypted_item);
         Ok(self.items.get(&item.id).expect("just inserted"))
@@ -94,6 +145,7 @@ impl EncryptedVault {
         item_id: Uuid,
         cred: &VaultItemCredentialOverview,
         secret: EncryptedBlob<String>,
+        history: Vec<EncryptedCredentialHistoryEntry>,
     ) -> Result<&EncryptedVaultItemCredential, Error> {
         let item = self
             .items
@@ -103,15 +155,48 @@ impl EncryptedVault {
             metadata: match metadata {
                 Some(existing) => existing.clone(),
                 None => {
-                    let metadata = VaultFieldMetadata::try_new(&cred.title, &cred.key)?;
+                    let metadata = VaultFieldMetadata {
+                        kind: Some(cred.kind),
+                        expires_at: cred.expires_at,
+                        ..VaultFieldMetadata::try_new(&cred.title, &cred.key)?
+                    };
                     vault_cipher.encrypt_cred_metadata(item_id, cred.id, &metadata)?
                 },
             },
             value: secret,
+            history,
         };
         item.credentials.insert(cred.id, encrypted_cred);
         Ok(item.credentials.get(&cred.id).expect("just inserted"))
     }
+
+    /// Add an attachment to an item, see add_credential.
+    pub fn add_attachment(
+        &mut self,
+        vault_cipher: &VaultCipher,
+        metadata: Option<&EncryptedBlob<VaultFieldMetadata>>,
+        item_id: Uuid,
+        attachment: &VaultItemAttachmentOverview,
+        content: EncryptedAttachmentContent,
+    ) -> Result<&EncryptedVaultItemAttachment, Error> {
+        let item = self
+            .items
+            .get_mut(&item_id)
+            .ok_or_else(|| Error::InvalidItemKey(item_id.to_string()))?;
+        let encrypted_attachment = EncryptedVaultItemAttachment {
+            metadata: match metadata {
+                Some(existing) => existing.clone(),
+                None => {
+                    let metadata =
+                        VaultFieldMetadata::try_new(&attachment.file_name, &attachment.key)?;
+                    vault_cipher.encrypt_attachment_metadata(item_id, attachment.id, &metadata)?
+                },
+            },
+            content,
+        };
+        item.attachments.insert(attachment.id, encrypted_attachment);
+        Ok(item.attachments.get(&attachment.id).expect("just inserted"))
+    }
 }
 
 #[serde_as]
@@ -120,25 +205,66 @@ impl EncryptedVault {
 pub enum VaultFileKey {
     Personal(#[serde_as(as = "Base64")] Vec<u8>),
     Members(Vec<VaultMember>),
-    MembersFile { path: PathBuf },
+    MembersFile {
+        path: PathBuf, // relative to the vault file's directory
+
+        // not stored in the vault file: read from and written to the members
+        // file, see EncryptedVault::load and VaultWrapper::save
+        #[serde(skip)]
+        members: Vec<VaultMember>,
+    },
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-pub struct VaultMember {
-    pub public_key: String,
-    pub wrapped_key: Vec<u8>, // file_key encrypted with this member's public key
+impl VaultFileKey {
+    pub fn members(&self) -> &[VaultMember] {
+        match self {
+            VaultFileKey::Personal(_) => &[],
+            VaultFileKey::Members(members) | VaultFileKey::MembersFile { members, .. } => members,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct EncryptedVaultItem {
     pub metadata: EncryptedBlob<VaultFieldMetadata>,
     pub credentials: BTreeMap<Uuid, EncryptedVaultItemCredential>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attachments: BTreeMap<Uuid, EncryptedVaultItemAttachment>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EncryptedVaultItemAttachment {
+    pub metadata: EncryptedBlob<VaultFieldMetadata>,
+    #[serde(flatten)]
+    pub content: EncryptedAttachmentContent,
+}
+
+/// Attachment contents are either stored inline in the vault file, or in a
+/// sidecar file (nonce || ciphertext) at a path relative to the vault file's
+/// directory. See VaultCipher::encrypt_attachment.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum EncryptedAttachmentContent {
+    Inline(EncryptedBlob<Vec<u8>>),
+    Sidecar(PathBuf),
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct EncryptedVaultItemCredential {
     pub metadata: EncryptedBlob<VaultFieldMetadata>,
     pub value: EncryptedBlob<String>,
+
+    // previous values, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<EncryptedCredentialHistoryEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct EncryptedCredentialHistoryEntry {
+    pub version: u32,
+    #[serde(with = "time::serde::rfc3339")]
+    pub replaced_at: OffsetDateTime,
+    pub value: EncryptedBlob<String>, // see VaultCipher::encrypt_cred_history_value
 }
 
 #[cfg(test)]
@@ -146,13 +272,18 @@ mod tests {
     use secrecy::ExposeSecret;
 
     use super::*;
+    use crate::secrets::vaults::migrations::VAULT_FORMAT_VERSION;
+    use crate::secrets::vaults::vault::vault_member::MemberKey;
 
     fn make_vault() -> EncryptedVault {
         EncryptedVault {
+            version: VAULT_FORMAT_VERSION,
             id: Uuid::new_v4(),
             name: Some("test vault".to_string()),
             file_key: VaultFileKey::Personal(vec![0u8; 32]),
             items: BTreeMap::new(),
+            aliases: BTreeMap::new(),
+            manifest: None,
         }
     }
 
@@ -204,7 +335,7 @@ mod tests {
             .unwrap();
 
         vault
-            .add_credential(&cipher, None, item.id, &cred, secret)
+            .add_credential(&cipher, None, item.id, &cred, secret, vec![])
             .unwrap();
 
         // Verify the vault has exactly one item with exactly one credential
@@ -238,7 +369,115 @@ mod tests {
             .encrypt_cred_value(Uuid::new_v4(), Uuid::new_v4(), "tok")
             .unwrap();
 
-        let result = vault.add_credential(&cipher, None, Uuid::new_v4(), &cred, secret);
+        let result = vault.add_credential(&cipher, None, Uuid::new_v4(), &cred, secret, vec![]);
         assert!(matches!(result, Err(Error::InvalidItemKey(_))));
     }
+
+    #[test]
+    fn test_decrypt_file_key_with_age_member() {
+        let identity = age::x25519::Identity::generate();
+        let raw_key: Vec<u8> = (0..32).collect();
+        let member = VaultMember::wrap(&MemberKey::Age(identity.to_public()), &raw_key).unwrap();
+
+        let mut vault = make_vault();
+        vault.file_key = VaultFileKey::Members(vec![member]);
+
+        // the unwrapped key decrypts what the original key encrypted
+        let item_id = Uuid::new_v4();
+        let cred_id = Uuid::new_v4();
+        let original_cipher = VaultCipher::new_with_bytes(&raw_key, vault.id);
+        let secret = original_cipher
+            .encrypt_cred_value(item_id, cred_id, "secret123")
+            .unwrap();
+        let cipher = vault.decrypt_file_key_with_age_identity(&identity).unwrap();
+        let secret_value = cipher
+            .decrypt_cred_value(item_id, cred_id, &secret)
+            .unwrap();
+        assert_eq!(secret_value.expose_secret(), "secret123");
+
+        // identities that are not members cannot decrypt the file key
+        let other = age::x25519::Identity::generate();
+        let result = vault.decrypt_file_key_with_age_identity(&other);
+        assert!(matches!(result, Err(Error::VaultFileKeyDecryptionError)));
+    }
+
+    #[test]
+    fn test_load_members_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity = age::x25519::Identity::generate();
+        let raw_key: Vec<u8> = (0..32).collect();
+        let member = VaultMember::wrap(&MemberKey::Age(identity.to_public()), &raw_key).unwrap();
+
+        let mut vault = make_vault();
+        VaultMembersFile::save(&dir.path().join("members.json"), vault.id, &[member]).unwrap();
+        vault.file_key = VaultFileKey::MembersFile {
+            path: PathBuf::from("members.json"),
+            members: vec![],
+        };
+        let manifest = VaultManifest::for_vault(0, &vault);
+        vault.manifest = Some(
+            VaultCipher::new(vault.id)
+                .encrypt_manifest(&manifest)
+                .unwrap(),
+        );
+        let vault_path = dir.path().join("vault.json");
+        fs::write(&vault_path, serde_json::to_string(&vault).unwrap()).unwrap();
+
+        // members are only stored in the members file
+        let vault_json = fs::read_to_string(&vault_path).unwrap();
+        assert!(vault_json.contains(r#""file_key":{"path":"members.json"}"#));
+
+        let loaded = EncryptedVault::load(&vault_path).unwrap();
+        assert_eq!(loaded.file_key.members().len(), 1);
+        assert!(loaded.decrypt_file_key_with_age_identity(&identity).is_ok());
+
+        // members file of another vault is rejected
+        VaultMembersFile::save(&dir.path().join("members.json"), Uuid::new_v4(), &[]).unwrap();
+        let result = EncryptedVault::load(&vault_path);
+        assert!(matches!(result, Err(Error::InvalidVaultMember(_))));
+    }
+
+    #[test]
+    fn test_load_pending_members_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let identity = age::x25519::Identity::generate();
+        let raw_key: Vec<u8> = (0..32).collect();
+        let member = VaultMember::wrap(&MemberKey::Age(identity.to_public()), &raw_key).unwrap();
+
+        let mut vault = make_vault();
+        vault.file_key = VaultFileKey::MembersFile {
+            path: PathBuf::from("members.json"),
+            members: vec![],
+        };
+        let manifest = VaultManifest::for_vault(0, &vault);
+        vault.manifest = Some(
+            VaultCipher::new(vault.id)
+                .encrypt_manifest(&manifest)
+                .unwrap(),
+        );
+        let vault_path = dir.path().join("vault.json");
+        let vault_json = serde_json::to_string(&vault).unwrap();
+        fs::write(&vault_path, &vault_json).unwrap();
+
+        // a save that was interrupted before the vault file was replaced
+        // leaves the members that open the vault file on disk
+        let members_path = dir.path().join("members.json");
+        VaultMembersFile::save_pending(
+            &members_path,
+            vault.id,
+            &[],
+            vault_json.as_bytes(),
+            vec![member],
+        )
+        .unwrap();
+        let loaded = EncryptedVault::load(&vault_path).unwrap();
+        assert_eq!(loaded.file_key.members().len(), 1);
+        assert!(loaded.decrypt_file_key_with_age_identity(&identity).is_ok());
+
+        // once the vault file is replaced, the new members apply
+        vault.name = Some("replaced".to_string());
+        fs::write(&vault_path, serde_json::to_string(&vault).unwrap()).unwrap();
+        let loaded = EncryptedVault::load(&vault_path).unwrap();
+        assert!(loaded.file_key.members().is_empty());
+    }
 }
@@ -5,6 +5,8 @@ use uuid::Uuid;
 use crate::secrets::vaults::errors::Error;
 use crate::secrets::vaults::vault::VaultFieldMetadata;
 use crate::secrets::vaults::vault::encrypted_blob::EncryptedBlob;
+use crate::secrets::vaults::vault::vault_manifest::VaultManifest;
+use crate::secrets::vaults::vault::vault_member::{MemberKey, VaultMember};
 use crate::secrets::vaults::vault_export::ExportMode;
 
 pub struct VaultCipher {
@@ -132,7 +134,144 @@ impl VaultCipher {
         )
     }
 
+    // History entries use the value AAD with an additional version component,
+    // so that an old value cannot be swapped in as the current value (or as
+    // another version).
+    pub fn encrypt_cred_history_value(
+        &self,
+        item_id: Uuid,
+        cred_id: Uuid,
+        version: u32,
+        value: &str,
+    ) -> Result<EncryptedBlob<String>, Error> {
+        EncryptedBlob::encrypt(
+            &value.to_string(),
+            &self.cipher,
+            vec![
+                self.vault_id.to_string(),
+                item_id.to_string(),
+                cred_id.to_string(),
+                "value".to_string(),
+                version.to_string(),
+            ],
+        )
+    }
+
+    pub fn decrypt_cred_history_value(
+        &self,
+        item_id: Uuid,
+        cred_id: Uuid,
+        version: u32,
+        blob: &EncryptedBlob<String>,
+    ) -> Result<SecretBox<String>, Error> {
+        blob.decrypt(
+            &self.cipher,
+            vec![
+                self.vault_id.to_string(),
+                item_id.to_string(),
+                cred_id.to_string(),
+                "value".to_string(),
+                version.to_string(),
+            ],
+        )
+    }
+
+    pub fn encrypt_manifest(
+        &self,
+        manifest: &VaultManifest,
+    ) -> Result<EncryptedBlob<VaultManifest>, Error> {
+        EncryptedBlob::encrypt(
+            manifest,
+            &self.cipher,
+            vec![self.vault_id.to_string(), "manifest".to_string()],
+        )
+    }
+
+    pub fn decrypt_manifest(
+        &self,
+        blob: &EncryptedBlob<VaultManifest>,
+    ) -> Result<SecretBox<VaultManifest>, Error> {
+        blob.decrypt(
+            &self.cipher,
+            vec![self.vault_id.to_string(), "manifest".to_string()],
+        )
+    }
+
+    pub fn encrypt_attachment_metadata(
+        &self,
+        item_id: Uuid,
+        attachment_id: Uuid,
+        value: &VaultFieldMetadata,
+    ) -> Result<EncryptedBlob<VaultFieldMetadata>, Error> {
+        EncryptedBlob::encrypt(
+            value,
+            &self.cipher,
+            vec![
+                self.vault_id.to_string(),
+                item_id.to_string(),
+                attachment_id.to_string(),
+                "attachment-metadata".to_string(),
+            ],
+        )
+    }
+
+    pub fn decrypt_attachment_metadata(
+        &self,
+        item_id: Uuid,
+        attachment_id: Uuid,
+        blob: &EncryptedBlob<VaultFieldMetadata>,
+    ) -> Result<SecretBox<VaultFieldMetadata>, Error> {
+        blob.decrypt(
+            &self.cipher,
+            vec![
+                self.vault_id.to_string(),
+                item_id.to_string(),
+                attachment_id.to_string(),
+                "attachment-metadata".to_string(),
+            ],
+        )
+    }
+
+    pub fn encrypt_attachment(
+        &self,
+        item_id: Uuid,
+        attachment_id: Uuid,
+        value: &[u8],
+    ) -> Result<EncryptedBlob<Vec<u8>>, Error> {
+        EncryptedBlob::encrypt_bytes(
+            value,
+            &self.cipher,
+            vec![
+                self.vault_id.to_string(),
+                item_id.to_string(),
+                attachment_id.to_string(),
+                "attachment".to_string(),
+            ],
+        )
+    }
+
+    pub fn decrypt_attachment(
+        &self,
+        item_id: Uuid,
+        attachment_id: Uuid,
+        blob: &EncryptedBlob<Vec<u8>>,
+    ) -> Result<SecretBox<Vec<u8>>, Error> {
+        blob.decrypt_bytes(
+            &self.cipher,
+            vec![
+                self.vault_id.to_string(),
+                item_id.to_string(),
+                attachment_id.to_string(),
+                "attachment".to_string(),
+            ],
+        )
+    }
+
     pub fn wrap_file_key_for_export(&self, export_mode: ExportMode) -> Result<String, Error> {
         export_mode.wrap_file_key(self.cipher_bytes.expose_secret())
     }
+
+    pub fn wrap_file_key_for_member(&self, member_key: &MemberKey) -> Result<VaultMember, Error> {
+        VaultMember::wrap(member_key, self.cipher_bytes.expose_secret())
+    }
 }
@@ -0,0 +1,78 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use zeroize::Zeroize;
+
+use crate::secrets::vaults::vault::encrypted_vault::EncryptedVault;
+
+/// Authenticated list of everything a vault file contains, stored encrypted
+/// with the file key in `manifest`. Blobs are only authenticated one by one, so
+/// without the manifest an item could be removed from the file (or the file
+/// replaced with an older copy) without anyone noticing. The revision is
+/// increased on every save, see VaultWrapper::save.
+#[derive(Serialize, Deserialize, Zeroize, Debug, Default, PartialEq)]
+pub struct VaultManifest {
+    pub revision: u64,
+    // item id -> ids of the item's credentials and attachments
+    #[zeroize(skip)]
+    pub items: BTreeMap<Uuid, BTreeSet<Uuid>>,
+    #[zeroize(skip)]
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub aliases: BTreeSet<Uuid>,
+}
+
+impl VaultManifest {
+    pub fn for_vault(revision: u64, vault: &EncryptedVault) -> Self {
+        let items = vault
+            .items
+            .iter()
+            .map(|(item_id, item)| {
+                let fields = item
+                    .credentials
+                    .keys()
+                    .chain(item.attachments.keys())
+                    .copied()
+                    .collect();
+                (*item_id, fields)
+            })
+            .collect();
+        Self {
+            revision,
+            items,
+            aliases: vault.aliases.keys().copied().collect(),
+        }
+    }
+
+    /// Describe how the vault differs from this manifest, if at all.
+    pub fn verify(&self, vault: &EncryptedVault) -> Result<(), String> {
+        let actual = Self::for_vault(self.revision, vault);
+        let mut problems = Vec::new();
+        for (item_id, fields) in &self.items {
+            match actual.items.get(item_id) {
+                None => problems.push(format!("item {item_id} is missing")),
+                Some(actual_fields) => {
+                    for field_id in fields.difference(actual_fields) {
+                        problems.push(format!("{item_id}/{field_id} is missing"));
+                    }
+                    for field_id in actual_fields.difference(fields) {
+                        problems.push(format!("{item_id}/{field_id} was added"));
+                    }
+                },
+            }
+        }
+        for item_id in actual.items.keys() {
+            if !self.items.contains_key(item_id) {
+                problems.push(format!("item {item_id} was added"));
+            }
+        }
+        if actual.aliases != self.aliases {
+            problems.push("aliases were changed".to_string());
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join(", "))
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::{fmt, fs, io};
+
+use serde::{Deserialize, Serialize};
+use serde_with::base64::Base64;
+use serde_with::serde_as;
+use sha2::{Digest, Sha256};
+use ssh_key::public::KeyData;
+use uuid::Uuid;
+
+use crate::core::atomic_file::write_atomic;
+use crate::secrets::keychain::managed_key::ManagedKey;
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::vault_export::{ExportMode, ImportIdentity};
+use crate::secrets::vaults::vault_key::VaultKey;
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone)]
+pub struct VaultMember {
+    pub public_key: String,
+    #[serde_as(as = "Base64")]
+    pub wrapped_key: Vec<u8>, // file_key encrypted with this member's public key
+}
+
+impl VaultMember {
+    /// Wrap the raw file key for the holder of the given public key.
+    pub fn wrap(member_key: &MemberKey, raw_key: &[u8]) -> Result<Self, Error> {
+        let wrapped_key = match member_key {
+            MemberKey::SecureEnclave(key_data) => {
+                ManagedKey::encrypt_for_public_key(key_data, raw_key)
+                    .ok_or(Error::VaultFileKeyEncryptionError)?
+                    .into_bytes()
+            },
+            MemberKey::Age(recipient) => ExportMode::Recipient(recipient.to_string())
+                .wrap_file_key(raw_key)
+                .inspect_err(|e| log::debug!("failed to wrap file key for {recipient}: {e}"))
+                .map_err(|_| Error::VaultFileKeyEncryptionError)?
+                .into_bytes(),
+        };
+        Ok(Self {
+            public_key: member_key.to_string(),
+            wrapped_key,
+        })
+    }
+
+    pub fn member_key(&self) -> Result<MemberKey, Error> {
+        self.public_key.parse()
+    }
+
+    /// Unwrap the file key with the local vault key. Returns None if this
+    /// member's key is not a P-256 key or cannot be decrypted with the given
+    /// key.
+    pub fn unwrap_with_vault_key(&self, user_encryption_key: &dyn VaultKey) -> Option<Vec<u8>> {
+        match self.member_key() {
+            Ok(MemberKey::SecureEnclave(_)) => user_encryption_key.decrypt(&self.wrapped_key),
+            _ => None,
+        }
+    }
+
+    /// Unwrap the file key with an age identity. Returns None if this member's
+    /// key is not an age recipient or cannot be decrypted with the given
+    /// identity.
+    pub fn unwrap_with_age_identity(&self, identity: &age::x25519::Identity) -> Option<Vec<u8>> {
+        match self.member_key() {
+            Ok(MemberKey::Age(recipient)) if recipient == identity.to_public() => {
+                let armored = std::str::from_utf8(&self.wrapped_key).ok()?;
+                ImportIdentity::Identity(identity.clone())
+                    .unwrap_file_key(armored)
+                    .inspect_err(|e| log::debug!("failed to unwrap age member key: {e}"))
+                    .ok()
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Public key of a vault member, either a Secure Enclave P-256 key (in OpenSSH
+/// format, e.g. "ecdsa-sha2-nistp256 AAAA...") or an age x25519 recipient
+/// ("age1...").
+#[derive(Clone, PartialEq)]
+pub enum MemberKey {
+    SecureEnclave(KeyData),
+    Age(age::x25519::Recipient),
+}
+
+impl FromStr for MemberKey {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.starts_with("age1") {
+            return s
+                .parse::<age::x25519::Recipient>()
+                .map(MemberKey::Age)
+                .map_err(|e| Error::InvalidVaultMember(format!("{s}: {e}")));
+        }
+
+        let public_key = ssh_key::PublicKey::from_openssh(s)
+            .map_err(|e| Error::InvalidVaultMember(format!("{s}: {e}")))?;
+        match public_key.key_data() {
+            key_data @ KeyData::Ecdsa(_) => Ok(MemberKey::SecureEnclave(key_data.clone())),
+            _ => Err(Error::InvalidVaultMember(format!(
+                "{s}: only ecdsa-sha2-nistp256 and age keys are supported"
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for MemberKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemberKey::SecureEnclave(key_data) => {
+                // canonical form without comment, so members can be compared by string
+                let openssh = ssh_key::PublicKey::new(key_data.clone(), "")
+                    .to_openssh()
+                    .map_err(|_| fmt::Error)?;
+                write!(f, "{}", openssh.trim())
+            },
+            MemberKey::Age(recipient) => write!(f, "{recipient}"),
+        }
+    }
+}
+
+/// Members stored in a file next to the vault (see VaultFileKey::MembersFile),
+/// so that membership can be managed separately from the vault contents.
+#[derive(Serialize, Deserialize)]
+pub struct VaultMembersFile {
+    pub vault_id: Uuid,
+    pub members: Vec<VaultMember>,
+    // set while the vault file is being replaced, see VaultWrapper::save
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<PreviousMembers>,
+}
+
+/// The members whose wrapped keys open the vault file with the given SHA-256.
+/// A save that rotates the file key writes the members file before the vault
+/// file, so until the vault file is replaced its old members must be kept.
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+pub struct PreviousMembers {
+    #[serde_as(as = "Base64")]
+    pub vault_sha256: Vec<u8>,
+    pub members: Vec<VaultMember>,
+}
+
+impl VaultMembersFile {
+    /// Load the members for the vault file with contents `vault_data`.
+    pub fn load(path: &Path, vault_id: Uuid, vault_data: &[u8]) -> Result<Vec<VaultMember>, Error> {
+        let data = fs::read_to_string(path).map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                Error::VaultNotFound(format!("Members file not found: {}", path.display()))
+            } else {
+                Error::VaultReadError(e)
+            }
+        })?;
+        let members_file: VaultMembersFile =
+            serde_json::from_str(&data).map_err(Error::VaultDeserializationError)?;
+        if members_file.vault_id != vault_id {
+            return Err(Error::InvalidVaultMember(format!(
+                "Members file {} belongs to vault {}",
+                path.display(),
+                members_file.vault_id
+            )));
+        }
+        match members_file.previous {
+            Some(previous) if previous.vault_sha256 == Sha256::digest(vault_data).as_slice() => {
+                Ok(previous.members)
+            },
+            _ => Ok(members_file.members),
+        }
+    }
+
+    pub fn save(path: &Path, vault_id: Uuid, members: &[VaultMember]) -> Result<(), Error> {
+        Self {
+            vault_id,
+            members: members.to_vec(),
+            previous: None,
+        }
+        .write(path)
+    }
+
+    /// Save the members ahead of replacing the vault file (with contents
+    /// `vault_data`), keeping the members that open it until it is replaced.
+    pub fn save_pending(
+        path: &Path,
+        vault_id: Uuid,
+        members: &[VaultMember],
+        vault_data: &[u8],
+        previous_members: Vec<VaultMember>,
+    ) -> Result<(), Error> {
+        Self {
+            vault_id,
+            members: members.to_vec(),
+            previous: Some(PreviousMembers {
+                vault_sha256: Sha256::digest(vault_data).to_vec(),
+                members: previous_members,
+            }),
+        }
+        .write(path)
+    }
+
+    fn write(&self, path: &Path) -> Result<(), Error> {
+        let json = serde_json::to_string_pretty(self).map_err(Error::VaultSerializationError)?;
+        write_atomic(path, json.as_bytes()).map_err(Error::VaultWriteError)
+    }
+}
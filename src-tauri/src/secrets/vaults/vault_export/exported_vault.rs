@@ -4,12 +4,17 @@ use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 use uuid::Uuid;
 
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::migrations::migrate_export;
+use crate::secrets::vaults::vault::VaultFieldMetadata;
+use crate::secrets::vaults::vault::encrypted_blob::EncryptedBlob;
 use crate::secrets::vaults::vault::encrypted_vault::EncryptedVaultItem;
 
 /// Vault export format. Uses age-encrypted file key instead of
 /// Secure Enclave-encrypted key
 #[derive(Serialize, Deserialize)]
 pub struct ExportedVault {
+    pub version: u32, // see migrations.rs
     pub id: Uuid,
 
     #[serde(with = "time::serde::rfc3339", default = "OffsetDateTime::now_utc")]
@@ -23,4 +28,18 @@ pub struct ExportedVault {
 
     pub age_file_key: String, // age ASCII-armored encrypted AES-256 key
     pub items: BTreeMap<Uuid, EncryptedVaultItem>,
+
+    // see EncryptedVault::aliases; missing in exports from older versions
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<Uuid, EncryptedBlob<VaultFieldMetadata>>,
+}
+
+impl ExportedVault {
+    /// Parse an export file, upgrading it to the current format version.
+    pub fn from_json(data: &str) -> Result<Self, Error> {
+        let mut value: serde_json::Value =
+            serde_json::from_str(data).map_err(Error::VaultDeserializationError)?;
+        migrate_export(&mut value)?;
+        serde_json::from_value(value).map_err(Error::VaultDeserializationError)
+    }
 }
@@ -41,3 +41,23 @@ impl ImportIdentity {
         Ok(raw_key)
     }
 }
+
+/// Parse the contents of an age identity file, which must contain exactly one
+/// secret key ("AGE-SECRET-KEY-1..."). Other lines, e.g. comments, are ignored.
+pub fn parse_age_identity(contents: &str) -> Result<age::x25519::Identity, String> {
+    let key_lines = contents
+        .lines()
+        .map(str::trim)
+        .filter(|l| l.starts_with("AGE-SECRET-KEY-1"))
+        .collect::<Vec<_>>();
+
+    let [identity_str] = key_lines[..] else {
+        return Err(format!(
+            "should contain exactly one secret key, found {}",
+            key_lines.len()
+        ));
+    };
+    identity_str
+        .parse::<age::x25519::Identity>()
+        .map_err(|e| format!("contains an invalid age identity: {e}"))
+}
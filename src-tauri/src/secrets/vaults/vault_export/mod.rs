@@ -10,16 +10,22 @@ use std::io::{self};
 use std::path::Path;
 
 use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::migrations::VAULT_FORMAT_VERSION;
+use crate::secrets::vaults::vault::Vault;
 use crate::secrets::vaults::vault::encrypted_vault::{EncryptedVault, VaultFileKey};
+use crate::secrets::vaults::vault::vault_cipher::VaultCipher;
 pub use crate::secrets::vaults::vault_export::export_mode::ExportMode;
 pub use crate::secrets::vaults::vault_export::exported_vault::ExportedVault;
-pub use crate::secrets::vaults::vault_export::import_identity::ImportIdentity;
+pub use crate::secrets::vaults::vault_export::import_identity::{
+    ImportIdentity, parse_age_identity,
+};
+use crate::secrets::vaults::vault_key::VaultKey;
 use crate::secrets::vaults::vault_wrapper::{
-    VaultWrapper, get_vault_encryption_key, normalized_key,
+    DEFAULT_VAULT, VaultWrapper, get_or_create_vault_encryption_key, normalized_key,
 };
 
 /// Import a vault from an export file. Decrypts the age-wrapped file key,
-/// re-wraps it with the local Secure Enclave key, and saves it in the usual
+/// re-wraps it with the local vault key, and saves it in the usual
 /// vault directory as a normal vault.
 pub fn import_vault<P: AsRef<Path>, Q: AsRef<Path>>(
     import_path: P,
@@ -27,20 +33,24 @@ pub fn import_vault<P: AsRef<Path>, Q: AsRef<Path>>(
     vault_dir: Q,
     vault_key: Option<String>,
 ) -> Result<VaultWrapper, Error> {
-    let import_path = import_path.as_ref();
-    let vault_dir = vault_dir.as_ref();
-
-    // read and parse the exported vault
-    let data = fs::read_to_string(import_path).map_err(|e| {
-        if e.kind() == io::ErrorKind::NotFound {
-            Error::VaultNotFound(import_path.display().to_string())
-        } else {
-            Error::VaultReadError(e)
-        }
-    })?;
+    let user_encryption_key = get_or_create_vault_encryption_key()?;
+    import_vault_with_key(
+        import_path.as_ref(),
+        identity,
+        vault_dir.as_ref(),
+        vault_key,
+        user_encryption_key.as_ref(),
+    )
+}
 
-    let exported: ExportedVault =
-        serde_json::from_str(&data).map_err(Error::VaultDeserializationError)?;
+fn import_vault_with_key(
+    import_path: &Path,
+    identity: ImportIdentity,
+    vault_dir: &Path,
+    vault_key: Option<String>,
+    user_encryption_key: &dyn VaultKey,
+) -> Result<VaultWrapper, Error> {
+    let exported = read_export(import_path)?;
 
     // resolve vault key from provided key or default
     let vault_key = vault_key
@@ -57,29 +67,85 @@ pub fn import_vault<P: AsRef<Path>, Q: AsRef<Path>>(
     // decrypt the age-wrapped file key
     let raw_key = identity.unwrap_file_key(&exported.age_file_key)?;
 
-    // re-wrap with the local Secure Enclave key
-    let managed_key = get_vault_encryption_key()?;
+    // re-wrap with the local vault key
     let enc_file_key = VaultFileKey::Personal(
-        managed_key
+        user_encryption_key
             .encrypt(&raw_key)
             .ok_or(Error::VaultFileKeyEncryptionError)?
             .into_bytes(),
     );
 
-    // build a normal EncryptedVault and save it
+    // decrypt it as a normal vault, and save it like one, so that it gets a
+    // manifest and a revision above any this device has seen of the vault
     let encrypted_vault = EncryptedVault {
+        version: VAULT_FORMAT_VERSION,
         id: exported.id,
         name: exported.name,
         file_key: enc_file_key,
         items: exported.items,
+        aliases: exported.aliases,
+        manifest: None,
     };
+    let vault = Vault::from_encrypted_with_cipher(
+        VaultCipher::new_with_bytes(&raw_key, exported.id),
+        encrypted_vault,
+    )?;
+    let mut vw = VaultWrapper::from_new_vault(vault_dir, &vault_key, vault)?;
+    if vw.path.exists() {
+        return Err(Error::InvalidVaultKey(format!(
+            "A vault with key '{vault_key}' already exists"
+        )));
+    }
+    vw.save()?;
+    Ok(vw)
+}
 
-    let vault_path = vault_dir.join(format!("{vault_key}.json"));
-    let json =
-        serde_json::to_string_pretty(&encrypted_vault).map_err(Error::VaultSerializationError)?;
+/// Decrypt an export file in memory without importing it, e.g. to compare it
+/// with a vault. The returned vault is unlocked but can't be saved, since its
+/// path is the export file.
+pub fn open_export<P: AsRef<Path>>(
+    import_path: P,
+    identity: &ImportIdentity,
+) -> Result<VaultWrapper, Error> {
+    let import_path = import_path.as_ref();
+    let exported = read_export(import_path)?;
+    let raw_key = identity.unwrap_file_key(&exported.age_file_key)?;
+    let encrypted_vault = EncryptedVault {
+        version: VAULT_FORMAT_VERSION,
+        id: exported.id,
+        name: exported.name,
+        // not used: the file key was unwrapped from the export
+        file_key: VaultFileKey::Personal(Vec::new()),
+        items: exported.items,
+        aliases: exported.aliases,
+        manifest: None,
+    };
+    let vault = Vault::from_encrypted_with_cipher(
+        VaultCipher::new_with_bytes(&raw_key, exported.id),
+        encrypted_vault,
+    )?;
+    let vault_key = exported
+        .default_key
+        .unwrap_or_else(|| DEFAULT_VAULT.to_string());
+    Ok(VaultWrapper::from_export(vault_key, import_path, vault))
+}
 
-    fs::create_dir_all(vault_dir).map_err(Error::VaultDirCreateError)?;
-    fs::write(&vault_path, json).map_err(Error::VaultWriteError)?;
+/// Whether path is an export file (as opposed to a vault file), judging by
+/// its age-wrapped file key.
+pub fn is_export_file(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|data| serde_json::from_str::<serde_json::Value>(&data).ok())
+        .is_some_and(|value| value.get("age_file_key").is_some())
+}
 
-    VaultWrapper::load_from_path(Some(vault_key), &vault_path)
+fn read_export(import_path: &Path) -> Result<ExportedVault, Error> {
+    let data = fs::read_to_string(import_path).map_err(|e| {
+        if e.kind() == io::ErrorKind::NotFound {
+            Error::VaultNotFound(import_path.display().to_string())
+        } else {
+            Error::VaultReadError(e)
+        }
+    })?;
+    ExportedVault::from_json(&data)
 }
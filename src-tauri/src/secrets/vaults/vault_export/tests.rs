@@ -1,5 +1,13 @@
+use secrecy::ExposeSecret;
+
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::test_vaults::{TestVaults, use_test_data_dir};
+use crate::secrets::vaults::vault::Vault;
 use crate::secrets::vaults::vault_export::export_mode::ExportMode;
-use crate::secrets::vaults::vault_export::import_identity::ImportIdentity;
+use crate::secrets::vaults::vault_export::import_identity::{ImportIdentity, parse_age_identity};
+use crate::secrets::vaults::vault_export::{import_vault_with_key, is_export_file, open_export};
+use crate::secrets::vaults::vault_key::SoftwareKey;
+use crate::secrets::vaults::vault_wrapper::VaultWrapper;
 
 #[test]
 fn test_passphrase_round_trip() {
@@ -37,3 +45,94 @@ fn test_recipient_round_trip() {
     let unwrapped = import_id.unwrap_file_key(&wrapped).unwrap();
     assert_eq!(raw_key, unwrapped);
 }
+
+#[test]
+fn test_parse_age_identity() {
+    let identity = age::x25519::Identity::generate();
+    let contents = format!(
+        "# created: 2026-01-01T00:00:00Z\n# public key: {}\n{}\n",
+        identity.to_public(),
+        identity.to_string().expose_secret()
+    );
+    let parsed = parse_age_identity(&contents).unwrap();
+    assert_eq!(parsed.to_public(), identity.to_public());
+
+    assert!(parse_age_identity("# no keys").is_err());
+    assert!(parse_age_identity(&format!("{contents}{contents}")).is_err());
+}
+
+#[test]
+fn test_open_export() {
+    use_test_data_dir();
+    let mut vault = Vault::new(None, &SoftwareKey::generate()).unwrap();
+    vault.add_or_update_item("github", "GitHub").unwrap();
+    vault
+        .add_or_update_item_credential("github", "token", "Token", None, "ghp_123".into())
+        .unwrap();
+
+    let identity = age::x25519::Identity::generate();
+    let export_mode = ExportMode::Recipient(identity.to_public().to_string());
+    let exported = vault
+        .into_export(Some("team".to_string()), export_mode)
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let export_path = dir.path().join("team.axovault");
+    std::fs::write(&export_path, serde_json::to_string(&exported).unwrap()).unwrap();
+    assert!(is_export_file(&export_path));
+
+    let mut vw = open_export(&export_path, &ImportIdentity::Identity(identity)).unwrap();
+    assert_eq!(vw.key, "team");
+    let secret = vw.get_secret("github", "token").unwrap().unwrap();
+    assert_eq!(secret.expose_secret(), "ghp_123");
+    // the export file is never overwritten
+    assert!(vw.save().is_err());
+    assert!(is_export_file(&export_path));
+}
+
+#[test]
+fn test_import_round_trip() {
+    let test_vaults = TestVaults::new(&[("team", "github", "token", "ghp_123")]);
+    let mut vaults = test_vaults.unlocked_manager();
+    let vw = vaults.get_vault_mut("team").unwrap();
+    vw.add_item_alias("old-github", "axo://team/github")
+        .unwrap();
+    // a few revisions this device has seen of the vault
+    for _ in 0..3 {
+        vw.save().unwrap();
+    }
+
+    let identity = age::x25519::Identity::generate();
+    let dir = tempfile::tempdir().unwrap();
+    let export_path = dir.path().join("team.axovault");
+    vw.export(
+        &export_path,
+        ExportMode::Recipient(identity.to_public().to_string()),
+    )
+    .unwrap();
+
+    let import = |vault_dir: &std::path::Path| {
+        import_vault_with_key(
+            &export_path,
+            ImportIdentity::Identity(identity.clone()),
+            vault_dir,
+            Some("imported".to_string()),
+            test_vaults.key.as_ref(),
+        )
+    };
+    // importing the vault again (e.g. on the same device) isn't a rollback
+    for _ in 0..2 {
+        let import_dir = tempfile::tempdir().unwrap();
+        import(import_dir.path()).unwrap();
+
+        let mut imported = VaultWrapper::load(import_dir.path(), Some("imported".into())).unwrap();
+        imported.unlock_with_key(test_vaults.key.as_ref()).unwrap();
+        let secret = imported.get_secret("github", "token").unwrap().unwrap();
+        assert_eq!(secret.expose_secret(), "ghp_123");
+        let alias = imported.get_item_alias("old-github").unwrap().unwrap();
+        assert_eq!(alias.target, "axo://team/github");
+
+        // an existing vault is never overwritten
+        let result = import(import_dir.path());
+        assert!(matches!(result, Err(Error::InvalidVaultKey(_))));
+    }
+}
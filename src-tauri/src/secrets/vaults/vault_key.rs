@@ -0,0 +1,36 @@
+mod software_key;
+
+pub use software_key::SoftwareKey;
+use ssh_key::public::KeyData;
+
+use crate::secrets::keychain::errors::KeychainError;
+use crate::secrets::keychain::managed_key::ManagedKey;
+
+/// The local key that vault file keys are wrapped with, for personal vaults
+/// (VaultFileKey::Personal) and as a vault member. This is the Secure Enclave
+/// ManagedKey, or a SoftwareKey stored in a passphrase protected key file where
+/// there is no Secure Enclave (e.g. Linux CI runners). Both are P-256 keys
+/// using the same ECIES variant, so either can be a vault member.
+pub trait VaultKey: Send {
+    /// Encrypt data to this key, returning base64 ciphertext.
+    fn encrypt(&self, plaintext: &[u8]) -> Option<String>;
+
+    /// Decrypt base64 ciphertext returned by encrypt.
+    fn decrypt(&self, b64_ciphertext: &[u8]) -> Option<Vec<u8>>;
+
+    fn public_key(&self) -> Result<KeyData, KeychainError>;
+}
+
+impl VaultKey for ManagedKey {
+    fn encrypt(&self, plaintext: &[u8]) -> Option<String> {
+        ManagedKey::encrypt(self, plaintext)
+    }
+
+    fn decrypt(&self, b64_ciphertext: &[u8]) -> Option<Vec<u8>> {
+        ManagedKey::decrypt(self, b64_ciphertext)
+    }
+
+    fn public_key(&self) -> Result<KeyData, KeychainError> {
+        ManagedKey::public_key(self)
+    }
+}
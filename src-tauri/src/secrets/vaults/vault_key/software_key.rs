@@ -0,0 +1,276 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use aes_gcm::aead::consts::U16;
+use aes_gcm::aead::{Aead, OsRng};
+use aes_gcm::aes::Aes128;
+use aes_gcm::{AesGcm, KeyInit, Nonce};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD_NO_PAD as b64;
+use inquire::Password;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use secrecy::SecretString;
+use sha2::{Digest, Sha256};
+use ssh_key::public::{EcdsaPublicKey, KeyData};
+use zeroize::Zeroizing;
+
+use crate::secrets::keychain::errors::KeychainError;
+use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::vault_export::{ExportMode, ImportIdentity};
+use crate::secrets::vaults::vault_key::VaultKey;
+
+// passphrase of the key file, prompted for if not set
+const KEY_FILE_PASSPHRASE_ENV: &str = "AXO_VAULT_KEY_PASSPHRASE";
+
+// uncompressed SEC1 point (04 || X || Y)
+const P256_POINT_LEN: usize = 65;
+const AES_GCM_TAG_LEN: usize = 16;
+
+// AES-128-GCM with a 16 byte IV, as used by the Secure Enclave's ECIES
+type Aes128Gcm16 = AesGcm<Aes128, U16>;
+
+/// P-256 vault key kept in software, in a key file encrypted with a passphrase
+/// (age scrypt). Encrypts like the Secure Enclave's
+/// kSecKeyAlgorithmECIESEncryptionCofactorVariableIVX963SHA256AESGCM, so
+/// vaults shared with its public key can be unwrapped with either.
+pub struct SoftwareKey {
+    secret_key: SecretKey,
+}
+
+impl SoftwareKey {
+    pub fn generate() -> Self {
+        Self {
+            secret_key: SecretKey::random(&mut OsRng),
+        }
+    }
+
+    /// Load the key file at path, or create a new key there if it doesn't
+    /// exist yet. The passphrase is read from AXO_VAULT_KEY_PASSPHRASE or
+    /// prompted for.
+    pub fn load_or_create(path: &Path) -> Result<Self, Error> {
+        if path.exists() {
+            return Self::open(path);
+        }
+        log::debug!("Creating new vault key file at {}", path.display());
+        let passphrase = read_passphrase(path, true)?;
+        let key = Self::generate();
+        key.save(path, passphrase)?;
+        Ok(key)
+    }
+
+    /// Load the existing key file at path, with the passphrase read from
+    /// AXO_VAULT_KEY_PASSPHRASE or prompted for. A missing key file is an
+    /// error, so a mistyped path doesn't quietly create a new key.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        if !path.exists() {
+            return Err(Error::VaultKeyFileReadError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} does not exist", path.display()),
+            )));
+        }
+        let passphrase = read_passphrase(path, false)?;
+        Self::load(path, passphrase)
+    }
+
+    pub fn load(path: &Path, passphrase: SecretString) -> Result<Self, Error> {
+        let armored = fs::read_to_string(path).map_err(Error::VaultKeyFileReadError)?;
+        let secret_bytes = Zeroizing::new(
+            ImportIdentity::Passphrase(passphrase)
+                .unwrap_file_key(&armored)
+                .map_err(|e| Error::InvalidVaultKeyFile(e.to_string()))?,
+        );
+        let secret_key = SecretKey::from_slice(&secret_bytes)
+            .map_err(|_| Error::InvalidVaultKeyFile("invalid P-256 key".to_string()))?;
+        Ok(Self { secret_key })
+    }
+
+    /// Write the key to a new file (mode 0600), encrypted with passphrase.
+    pub fn save(&self, path: &Path, passphrase: SecretString) -> Result<(), Error> {
+        let secret_bytes = Zeroizing::new(self.secret_key.to_bytes());
+        let armored = ExportMode::Passphrase(passphrase)
+            .wrap_file_key(&secret_bytes)
+            .map_err(|e| Error::InvalidVaultKeyFile(e.to_string()))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(Error::VaultKeyFileWriteError)?;
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)
+            .map_err(Error::VaultKeyFileWriteError)?;
+        file.write_all(armored.as_bytes())
+            .map_err(Error::VaultKeyFileWriteError)
+    }
+}
+
+impl VaultKey for SoftwareKey {
+    fn encrypt(&self, plaintext: &[u8]) -> Option<String> {
+        ecies_encrypt(&self.secret_key.public_key(), plaintext).map(|enc| b64.encode(enc))
+    }
+
+    fn decrypt(&self, b64_ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let ciphertext = b64.decode(b64_ciphertext).ok()?;
+        ecies_decrypt(&self.secret_key, &ciphertext)
+    }
+
+    fn public_key(&self) -> Result<KeyData, KeychainError> {
+        let point = self.secret_key.public_key().to_encoded_point(false);
+        EcdsaPublicKey::from_sec1_bytes(point.as_bytes())
+            .map(KeyData::Ecdsa)
+            .map_err(|e| KeychainError::PublicKeyUnavailable(e.to_string()))
+    }
+}
+
+fn read_passphrase(path: &Path, new_key: bool) -> Result<SecretString, Error> {
+    if let Ok(passphrase) = std::env::var(KEY_FILE_PASSPHRASE_ENV) {
+        return Ok(passphrase.into());
+    }
+    if !io::stdin().is_terminal() {
+        return Err(Error::InvalidVaultKeyFile(format!(
+            "{KEY_FILE_PASSPHRASE_ENV} must be set to use {} without a terminal",
+            path.display()
+        )));
+    }
+    let prompt = if new_key {
+        format!("New passphrase for vault key {}:", path.display())
+    } else {
+        format!("Passphrase for vault key {}:", path.display())
+    };
+    let mut password = Password::new(&prompt);
+    if !new_key {
+        password = password.without_confirmation();
+    }
+    password
+        .prompt()
+        .map(SecretString::from)
+        .map_err(|e| Error::InvalidVaultKeyFile(format!("Failed to read passphrase: {e}")))
+}
+
+/// ECIES to a P-256 public key: ECDH with an ephemeral key, ANSI X9.63 KDF
+/// (SHA-256, with the ephemeral public key as shared info) deriving an AES-128
+/// key and a 16 byte IV, and AES-GCM. Returns ephemeral public key ||
+/// ciphertext || tag.
+fn ecies_encrypt(public_key: &PublicKey, plaintext: &[u8]) -> Option<Vec<u8>> {
+    let ephemeral_key = SecretKey::random(&mut OsRng);
+    let ephemeral_point = ephemeral_key.public_key().to_encoded_point(false);
+    let (cipher, iv) = derive_cipher(&ephemeral_key, public_key, ephemeral_point.as_bytes())?;
+    #[allow(deprecated)]
+    let ciphertext = cipher
+        .encrypt(Nonce::<U16>::from_slice(&iv[..]), plaintext)
+        .inspect_err(|e| log::debug!("ECIES encryption error: {e}"))
+        .ok()?;
+    let mut output = ephemeral_point.as_bytes().to_vec();
+    output.extend(ciphertext);
+    Some(output)
+}
+
+fn ecies_decrypt(secret_key: &SecretKey, data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() < P256_POINT_LEN + AES_GCM_TAG_LEN {
+        return None;
+    }
+    let (ephemeral_point, ciphertext) = data.split_at(P256_POINT_LEN);
+    let ephemeral_public_key = PublicKey::from_sec1_bytes(ephemeral_point).ok()?;
+    let (cipher, iv) = derive_cipher(secret_key, &ephemeral_public_key, ephemeral_point)?;
+    #[allow(deprecated)]
+    cipher
+        .decrypt(Nonce::<U16>::from_slice(&iv[..]), ciphertext)
+        .inspect_err(|e| log::debug!("ECIES decryption error: {e}"))
+        .ok()
+}
+
+fn derive_cipher(
+    secret_key: &SecretKey,
+    public_key: &PublicKey,
+    ephemeral_point: &[u8],
+) -> Option<(Aes128Gcm16, Zeroizing<Vec<u8>>)> {
+    // P-256 has cofactor 1, so cofactor ECDH is plain ECDH
+    let shared_point = (public_key.to_projective() * *secret_key.to_nonzero_scalar()).to_affine();
+    let shared_secret = Zeroizing::new(shared_point.to_encoded_point(false).x()?.to_vec());
+    let key_material = x963_kdf(&shared_secret, ephemeral_point, 32);
+    let (key, iv) = key_material.split_at(16);
+    let cipher = Aes128Gcm16::new_from_slice(key).ok()?;
+    Some((cipher, Zeroizing::new(iv.to_vec())))
+}
+
+// ANSI X9.63 KDF: SHA-256(Z || counter || shared info), counter starting at 1
+fn x963_kdf(shared_secret: &[u8], shared_info: &[u8], len: usize) -> Zeroizing<Vec<u8>> {
+    let mut output = Zeroizing::new(Vec::with_capacity(len));
+    let mut counter: u32 = 1;
+    while output.len() < len {
+        let digest = Sha256::new()
+            .chain_update(shared_secret)
+            .chain_update(counter.to_be_bytes())
+            .chain_update(shared_info)
+            .finalize();
+        output.extend_from_slice(&digest);
+        counter += 1;
+    }
+    output.truncate(len);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_software_key_round_trip() {
+        let key = SoftwareKey::generate();
+        let file_key = [42u8; 32];
+        let wrapped = key.encrypt(&file_key).unwrap();
+        assert_eq!(key.decrypt(wrapped.as_bytes()).unwrap(), file_key);
+
+        // wrapped to the public key by someone else, e.g. when sharing a vault
+        let public_key =
+            PublicKey::from_sec1_bytes(key.public_key().unwrap().ecdsa().unwrap().as_sec1_bytes())
+                .unwrap();
+        let wrapped = b64.encode(ecies_encrypt(&public_key, &file_key).unwrap());
+        assert_eq!(key.decrypt(wrapped.as_bytes()).unwrap(), file_key);
+
+        let other = SoftwareKey::generate();
+        assert!(other.decrypt(wrapped.as_bytes()).is_none());
+    }
+
+    #[test]
+    fn test_software_key_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vault-key.age");
+        let key = SoftwareKey::generate();
+        key.save(&path, "correct horse".into()).unwrap();
+
+        let loaded = SoftwareKey::load(&path, "correct horse".into()).unwrap();
+        assert_eq!(loaded.public_key().unwrap(), key.public_key().unwrap());
+        assert!(matches!(
+            SoftwareKey::load(&path, "wrong".into()),
+            Err(Error::InvalidVaultKeyFile(_))
+        ));
+        // never overwrites an existing key
+        assert!(key.save(&path, "correct horse".into()).is_err());
+        // nor creates one when opening a missing key file
+        let missing = dir.path().join("missing.age");
+        assert!(matches!(
+            SoftwareKey::open(&missing),
+            Err(Error::VaultKeyFileReadError(_))
+        ));
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn test_x963_kdf() {
+        // ANSI X9.63 test vector (SHA-256, 128 bit output), from NIST CAVS
+        let shared_secret = hex_decode("96c05619d56c328ab95fe84b18264b08725b85e33fd34f08");
+        let key = x963_kdf(&shared_secret, &[], 16);
+        assert_eq!(&key[..], hex_decode("443024c3dae66b95e6f5670601558f71"));
+    }
+
+    fn hex_decode(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+}
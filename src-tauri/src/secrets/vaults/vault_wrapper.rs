@@ -2,22 +2,45 @@ use std::path::{Path, PathBuf};
 use std::sync::LazyLock;
 use std::{fs, io};
 
-use secrecy::{SecretBox, SecretString};
-use url::Url;
+use secrecy::{ExposeSecret, SecretBox, SecretString};
+use time::OffsetDateTime;
 
+use crate::core::atomic_file::{FileLock, content_digest, file_digest, write_atomic};
 use crate::core::auth::{AuthContext, AuthMethod, run_on_auth_thread};
+use crate::core::config::APP_CONFIG;
 use crate::core::provenance::Provenance;
 use crate::secrets::keychain::keychain_query::KeychainQuery;
 use crate::secrets::keychain::managed_key::{KeyClass, ManagedKey, ManagedKeyQuery};
 use crate::secrets::vaults::errors::Error;
-use crate::secrets::vaults::vault::encrypted_vault::EncryptedVault;
-use crate::secrets::vaults::vault::{Vault, VaultItemCredentialOverview, VaultItemOverview};
-use crate::secrets::vaults::vault_export::ExportMode;
+use crate::secrets::vaults::importers::ImportedItem;
+use crate::secrets::vaults::otp::{OtpCode, OtpConfig};
+use crate::secrets::vaults::vault::attachment::VaultItemAttachmentOverview;
+use crate::secrets::vaults::vault::credential_kind::CredentialKind;
+use crate::secrets::vaults::vault::encrypted_vault::{
+    EncryptedCredentialHistoryEntry, EncryptedVault, VaultFileKey,
+};
+use crate::secrets::vaults::vault::vault_cipher::VaultCipher;
+use crate::secrets::vaults::vault::vault_member::{MemberKey, VaultMember, VaultMembersFile};
+use crate::secrets::vaults::vault::{
+    ItemTransfer, Vault, VaultItemAlias, VaultItemCredentialOverview, VaultItemOverview,
+};
+use crate::secrets::vaults::vault_export::{ExportMode, parse_age_identity};
+use crate::secrets::vaults::vault_key::{SoftwareKey, VaultKey};
 
 pub const DEFAULT_VAULT: &str = "default";
 
 const VAULT_ENCRYPTION_KEY_LABEL: &str = "vault-encryption-key";
 
+// path of a software vault key file, see get_vault_encryption_key
+const VAULT_KEY_FILE_ENV: &str = "AXO_VAULT_KEY_FILE";
+
+pub const ALLOW_VAULT_ROLLBACK_ENV: &str = "AXO_ALLOW_VAULT_ROLLBACK";
+
+// age identity for vaults shared with an age recipient, see
+// age_identity_from_env
+const AGE_IDENTITY_ENV: &str = "AXO_AGE_IDENTITY";
+const AGE_IDENTITY_FILE_ENV: &str = "AXO_AGE_IDENTITY_FILE";
+
 enum VaultState {
     Locked { name: Option<String> },
     Unlocked { vault: Vault },
@@ -28,6 +51,51 @@ pub struct VaultWrapper {
     pub key: String,
     pub path: PathBuf,
     state: VaultState,
+    // digest of the vault file as last read or written, to detect changes made
+    // by other processes (the app and the CLI, or concurrent CLI commands)
+    file_digest: Option<u64>,
+    // held from unlock_for_update until the vault is saved
+    file_lock: Option<FileLock>,
+}
+
+// refuses vaults older than the last revision seen on this device (unless
+// AXO_ALLOW_VAULT_ROLLBACK is set), and records newer revisions
+fn check_vault_revision(vault_key: &str, vault: &Vault) -> Result<(), Error> {
+    let mut config = APP_CONFIG.lock().unwrap();
+    let seen = config.last_seen_vault_revision(vault.id());
+    let revision = vault.revision();
+    if revision < seen {
+        if std::env::var_os(ALLOW_VAULT_ROLLBACK_ENV).is_none() {
+            return Err(Error::VaultRolledBack { revision, seen });
+        }
+        log::warn!("Vault {vault_key} rolled back from revision {seen} to {revision}");
+    } else if revision > seen
+        && let Err(e) = config.record_vault_revision(vault.id(), revision)
+    {
+        log::warn!("Failed to record vault revision: {e}");
+    }
+    Ok(())
+}
+
+/// Age identity from AXO_AGE_IDENTITY ("AGE-SECRET-KEY-1...") or the identity
+/// file at AXO_AGE_IDENTITY_FILE. Vaults shared with its recipient are unlocked
+/// with it instead of the local vault key, e.g. on CI runners.
+pub(crate) fn age_identity_from_env() -> Result<Option<age::x25519::Identity>, Error> {
+    let contents = if let Ok(identity) = std::env::var(AGE_IDENTITY_ENV) {
+        identity
+    } else if let Some(path) = std::env::var_os(AGE_IDENTITY_FILE_ENV) {
+        fs::read_to_string(&path).map_err(|e| {
+            Error::InvalidAgeIdentity(format!(
+                "failed to read {}: {e}",
+                Path::new(&path).display()
+            ))
+        })?
+    } else {
+        return Ok(None);
+    };
+    parse_age_identity(&contents)
+        .map(Some)
+        .map_err(Error::InvalidAgeIdentity)
 }
 
 fn vault_file_path(vault_dir: &Path, vault_key: &str) -> Result<PathBuf, Error> {
@@ -41,25 +109,36 @@ impl VaultWrapper {
         name: Option<String>,
         vault_dir: &Path,
         vault_key: &str,
-        user_encryption_key: ManagedKey,
+        user_encryption_key: &dyn VaultKey,
     ) -> Result<Self, Error> {
         log::debug!("Creating new vault...");
         let vault_key = normalized_key(vault_key)
             .ok_or_else(|| Error::InvalidVaultKey(vault_key.to_string()))?;
 
-        let vault_path = vault_file_path(vault_dir, &vault_key)?;
-        let vault_overview = Vault::new(name, user_encryption_key)?;
-        let vault_wrapper = Self {
-            key: vault_key.to_string(),
-            path: vault_path,
-            state: VaultState::Unlocked {
-                vault: vault_overview,
-            },
-        };
+        let vault = Vault::new(name, user_encryption_key)?;
+        let mut vault_wrapper = Self::from_new_vault(vault_dir, &vault_key, vault)?;
         vault_wrapper.save()?;
         Ok(vault_wrapper)
     }
 
+    /// An unlocked vault that has no vault file yet, to be saved in vault_dir
+    /// (e.g. one imported from an export file).
+    pub(crate) fn from_new_vault(
+        vault_dir: &Path,
+        vault_key: &str,
+        mut vault: Vault,
+    ) -> Result<Self, Error> {
+        let vault_path = vault_file_path(vault_dir, vault_key)?;
+        vault.set_vault_dir(vault_dir);
+        Ok(Self {
+            key: vault_key.to_string(),
+            path: vault_path,
+            state: VaultState::Unlocked { vault },
+            file_digest: None,
+            file_lock: None,
+        })
+    }
+
     pub fn load(vault_dir: &Path, vault_key: Option<String>) -> Result<Self, Error> {
         let vault_key = vault_key.unwrap_or(DEFAULT_VAULT.to_string());
         let vault_path = vault_file_path(vault_dir, &vault_key)?;
@@ -85,20 +164,108 @@ impl VaultWrapper {
             state: VaultState::Locked {
                 name: vault.name.clone(),
             },
+            file_digest: None,
+            file_lock: None,
         })
     }
 
+    /// An unlocked vault decrypted from the export file at export_path, see
+    /// vault_export::open_export. Saving it fails because the export file
+    /// was never read as a vault file.
+    pub(crate) fn from_export(vault_key: String, export_path: &Path, vault: Vault) -> Self {
+        Self {
+            key: vault_key,
+            path: export_path.to_path_buf(),
+            state: VaultState::Unlocked { vault },
+            file_digest: None,
+            file_lock: None,
+        }
+    }
+
     pub fn unlock(&mut self) -> Result<(), Error> {
+        self.unlock_with(None)
+    }
+
+    /// Like unlock, with the local vault key already fetched (fetching it may
+    /// prompt for a passphrase or authentication, see
+    /// get_vault_encryption_key).
+    pub fn unlock_with_key(&mut self, user_encryption_key: &dyn VaultKey) -> Result<(), Error> {
+        self.unlock_with(Some(user_encryption_key))
+    }
+
+    fn unlock_with(&mut self, user_encryption_key: Option<&dyn VaultKey>) -> Result<(), Error> {
         // note: does not check if the LAContext is still valid
-        let encrypted_vault = EncryptedVault::load(&self.path)?;
-        let managed_key = get_vault_encryption_key()?;
-        let vault = Vault::from_encrypted(managed_key, encrypted_vault)
+        let (encrypted_vault, file_digest) = EncryptedVault::load_with_digest(&self.path)?;
+        let age_identity = age_identity_from_env()?;
+        let vault_cipher = match self.age_file_cipher(&encrypted_vault, age_identity.as_ref()) {
+            Some(vault_cipher) => vault_cipher,
+            None => match user_encryption_key {
+                Some(user_encryption_key) => {
+                    encrypted_vault.decrypt_file_key(user_encryption_key)?
+                },
+                None => encrypted_vault.decrypt_file_key(get_vault_encryption_key()?.as_ref())?,
+            },
+        };
+        self.unlock_with_cipher(vault_cipher, encrypted_vault, file_digest)
+    }
+
+    // The vault's file key decrypted with the age identity, if it's a member.
+    // Unlocking falls back to the local vault key otherwise.
+    pub(crate) fn age_file_cipher(
+        &self,
+        encrypted_vault: &EncryptedVault,
+        age_identity: Option<&age::x25519::Identity>,
+    ) -> Option<VaultCipher> {
+        let identity = age_identity?;
+        encrypted_vault
+            .decrypt_file_key_with_age_identity(identity)
+            .inspect_err(|_| {
+                log::debug!(
+                    "age identity cannot unlock vault {}, using vault key",
+                    self.key
+                )
+            })
+            .ok()
+    }
+
+    // Last step of unlocking, decrypting the vault file read (with its digest)
+    // with the vault's file key. Doesn't need the vault key, so it can run on
+    // another thread, see VaultsManager::unlock_vaults_for.
+    pub(crate) fn unlock_with_cipher(
+        &mut self,
+        vault_cipher: VaultCipher,
+        encrypted_vault: EncryptedVault,
+        file_digest: u64,
+    ) -> Result<(), Error> {
+        let mut vault = Vault::from_encrypted_with_cipher(vault_cipher, encrypted_vault)
             .inspect_err(|e| log::debug!("failed to build vault: {e}"))
-            .map_err(|_| Error::VaultFileKeyDecryptionError)?;
+            .map_err(|e| match e {
+                Error::VaultManifestMismatch(_) => e,
+                _ => Error::VaultFileKeyDecryptionError,
+            })?;
+        if let Some(vault_dir) = self.path.parent() {
+            vault.set_vault_dir(vault_dir);
+        }
+        check_vault_revision(&self.key, &vault)?;
         self.state = VaultState::Unlocked { vault };
+        self.file_digest = Some(file_digest);
         Ok(())
     }
 
+    /// Unlock the vault while holding an advisory lock on the vault file, so
+    /// that other processes cannot save the vault until this one has saved
+    /// its changes (or is dropped).
+    pub fn unlock_for_update(&mut self) -> Result<(), Error> {
+        let file_lock = FileLock::acquire(&self.path).map_err(Error::VaultLockError)?;
+        self.unlock()?;
+        self.file_lock = Some(file_lock);
+        Ok(())
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        matches!(self.state, VaultState::Unlocked { .. })
+    }
+
     fn get_unlocked_vault(&self) -> Result<&Vault, Error> {
         match &self.state {
             VaultState::Unlocked { vault } => Ok(vault),
@@ -113,21 +280,77 @@ impl VaultWrapper {
         }
     }
 
-    pub fn save(&self) -> Result<(), Error> {
-        let Some(vault_dir) = self.path.parent() else {
+    /// Write the vault to disk atomically. Fails with VaultModifiedOnDisk if
+    /// the file was changed by another process since the vault was unlocked.
+    pub fn save(&mut self) -> Result<(), Error> {
+        let Some(vault_dir) = self.path.parent().map(Path::to_path_buf) else {
             return Err(Error::VaultDirCreateError(io::Error::new(
                 io::ErrorKind::NotFound,
                 "Vault directory not found",
             )));
         };
 
-        let vault = self.get_unlocked_vault()?;
+        let vault = self.get_unlocked_vault_mut()?;
+        let seen = APP_CONFIG
+            .lock()
+            .unwrap()
+            .last_seen_vault_revision(vault.id());
+        vault.bump_revision(seen);
+        let (vault_id, revision) = (vault.id(), vault.revision());
         let encrypted_vault = vault.into_encrypted()?;
         let vault_data = serde_json::to_string_pretty(&encrypted_vault)
             .map_err(Error::VaultSerializationError)?;
 
-        fs::create_dir_all(vault_dir).map_err(Error::VaultDirCreateError)?;
-        fs::write(self.path.clone(), vault_data).map_err(Error::VaultWriteError)?;
+        fs::create_dir_all(&vault_dir).map_err(Error::VaultDirCreateError)?;
+
+        // lock is released at the end of the save
+        let _file_lock = match self.file_lock.take() {
+            Some(file_lock) => file_lock,
+            None => FileLock::acquire(&self.path).map_err(Error::VaultLockError)?,
+        };
+        if file_digest(&self.path).map_err(Error::VaultReadError)? != self.file_digest {
+            return Err(Error::VaultModifiedOnDisk(self.path.display().to_string()));
+        }
+
+        let members_file = match &encrypted_vault.file_key {
+            VaultFileKey::MembersFile { path, members } => Some((vault_dir.join(path), members)),
+            _ => None,
+        };
+        // the members file is written after the vault file, but if the file key
+        // was rotated neither order is safe on its own: until the vault file is
+        // replaced, the members file keeps the members that open the old one
+        if let Some((members_path, members)) = &members_file
+            && let Ok(old_vault_data) = fs::read(&self.path)
+            && let Ok(old_members) =
+                VaultMembersFile::load(members_path, encrypted_vault.id, &old_vault_data)
+            && old_members
+                .iter()
+                .any(|old| !members.iter().any(|new| new.wrapped_key == old.wrapped_key))
+        {
+            VaultMembersFile::save_pending(
+                members_path,
+                encrypted_vault.id,
+                members,
+                &old_vault_data,
+                old_members,
+            )?;
+        }
+        self.get_unlocked_vault_mut()?.save_sidecars()?;
+        write_atomic(&self.path, vault_data.as_bytes()).map_err(Error::VaultWriteError)?;
+        self.file_digest = Some(content_digest(vault_data.as_bytes()));
+        if let Some((members_path, members)) = &members_file {
+            VaultMembersFile::save(members_path, encrypted_vault.id, members)?;
+        }
+        if let Err(e) = self.get_unlocked_vault()?.prune_sidecars() {
+            log::warn!("Failed to remove unreferenced attachments: {e}");
+        }
+        if let Err(e) = APP_CONFIG
+            .lock()
+            .unwrap()
+            .record_vault_revision(vault_id, revision)
+        {
+            log::warn!("Failed to record vault revision: {e}");
+        }
         Ok(())
     }
 
@@ -191,20 +414,132 @@ impl VaultWrapper {
         vault.add_or_update_item(item_key, item_title)
     }
 
+    pub fn set_item_tags(&mut self, item_key: &str, tags: &[String]) -> Result<(), Error> {
+        let vault = self.get_unlocked_vault_mut()?;
+        vault.set_item_tags(item_key, tags)
+    }
+
+    pub fn rename_item(&mut self, item_key: &str, new_item_key: &str) -> Result<(), Error> {
+        let vault = self.get_unlocked_vault_mut()?;
+        vault.rename_item(item_key, new_item_key)
+    }
+
+    pub fn rename_item_credential(
+        &mut self,
+        item_key: &str,
+        cred_key: &str,
+        new_cred_key: &str,
+    ) -> Result<(), Error> {
+        let vault = self.get_unlocked_vault_mut()?;
+        vault.rename_item_credential(item_key, cred_key, new_cred_key)
+    }
+
+    pub fn export_item(&self, item_key: &str) -> Result<ItemTransfer, Error> {
+        let vault = self.get_unlocked_vault()?;
+        vault.export_item(item_key)
+    }
+
+    pub fn import_item(&mut self, item_key: &str, transfer: ItemTransfer) -> Result<(), Error> {
+        let vault = self.get_unlocked_vault_mut()?;
+        vault.import_item(item_key, transfer)?;
+        Ok(())
+    }
+
+    pub fn add_attachment(
+        &mut self,
+        item_key: &str,
+        attachment_key: &str,
+        file_name: &str,
+        data: &[u8],
+        sidecar: bool,
+    ) -> Result<(), Error> {
+        let vault = self.get_unlocked_vault_mut()?;
+        vault.add_or_update_item_attachment(item_key, attachment_key, file_name, data, sidecar)?;
+        Ok(())
+    }
+
+    pub fn get_attachment_overview(
+        &self,
+        item_key: &str,
+        attachment_key: &str,
+    ) -> Result<Option<&VaultItemAttachmentOverview>, Error> {
+        let vault = self.get_unlocked_vault()?;
+        match vault.get_item_attachment(item_key, attachment_key) {
+            Ok(attachment) => Ok(Some(attachment)),
+            Err(Error::InvalidAttachmentKey(_)) | Err(Error::InvalidItemKey(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn get_attachment(
+        &self,
+        item_key: &str,
+        attachment_key: &str,
+    ) -> Result<SecretBox<Vec<u8>>, Error> {
+        let vault = self.get_unlocked_vault()?;
+        vault.get_item_attachment_data(item_key, attachment_key)
+    }
+
+    pub fn delete_attachment(&mut self, item_key: &str, attachment_key: &str) -> Result<(), Error> {
+        let vault = self.get_unlocked_vault_mut()?;
+        vault.delete_item_attachment(item_key, attachment_key)
+    }
+
+    pub fn add_item_alias(&mut self, item_key: &str, target: &str) -> Result<(), Error> {
+        let vault = self.get_unlocked_vault_mut()?;
+        vault.add_item_alias(item_key, target)
+    }
+
+    pub fn get_item_alias(&self, item_key: &str) -> Result<Option<&VaultItemAlias>, Error> {
+        let vault = self.get_unlocked_vault()?;
+        Ok(vault.get_item_alias(item_key))
+    }
+
+    pub fn list_item_aliases(&self) -> Result<Vec<&VaultItemAlias>, Error> {
+        let vault = self.get_unlocked_vault()?;
+        Ok(vault.list_item_aliases())
+    }
+
     pub fn delete_item(&mut self, item_key: &str) -> Result<(), Error> {
         let vault = self.get_unlocked_vault_mut()?;
         vault.delete_item(item_key)
     }
 
+    /// Add items read by an importer, see importers::plan_import.
+    pub fn import_items(&mut self, items: Vec<ImportedItem>) -> Result<(), Error> {
+        let vault = self.get_unlocked_vault_mut()?;
+        for ImportedItem { item, mut values } in items {
+            vault.add_or_update_item(&item.key, &item.title)?;
+            if !item.tags.is_empty() {
+                vault.set_item_tags(&item.key, &item.tags)?;
+            }
+            for cred in item.credentials.values() {
+                let Some(value) = values.remove(&cred.id) else {
+                    continue;
+                };
+                vault.add_or_update_item_credential(
+                    &item.key,
+                    &cred.key,
+                    &cred.title,
+                    Some(cred.kind),
+                    value,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn add_secret(
         &mut self,
         item_key: &str,
         cred_key: &str,
         cred_title: &str,
+        cred_kind: Option<CredentialKind>,
         cred_value: SecretString,
     ) -> Result<(), Error> {
         let vault = self.get_unlocked_vault_mut()?;
-        vault.add_or_update_item_credential(item_key, cred_key, cred_title, cred_value)?;
+        vault
+            .add_or_update_item_credential(item_key, cred_key, cred_title, cred_kind, cred_value)?;
         Ok(())
     }
 
@@ -217,18 +552,31 @@ impl VaultWrapper {
         vault.get_item_credential_secret(item_key, cred_key)
     }
 
-    pub fn get_secret_by_url(&self, url: Url) -> Result<Option<SecretBox<String>>, Error> {
-        let mut segments = url
-            .path_segments()
-            .ok_or(Error::InvalidVaultItemReference("invalid url".to_string()))?;
-        let item_key = segments.next().ok_or(Error::InvalidVaultItemReference(
-            "missing item key".to_string(),
-        ))?;
-        let credential_key = segments.next().ok_or(Error::InvalidVaultItemReference(
-            "missing credential key".to_string(),
-        ))?;
-        log::debug!("Parsed reference {item_key}/{credential_key}");
-        self.get_secret(item_key, credential_key)
+    pub fn set_secret_expiry(
+        &mut self,
+        item_key: &str,
+        cred_key: &str,
+        expires_at: Option<OffsetDateTime>,
+    ) -> Result<(), Error> {
+        let vault = self.get_unlocked_vault_mut()?;
+        vault.set_item_credential_expiry(item_key, cred_key, expires_at)
+    }
+
+    /// Generate a one-time password from a credential holding an otpauth://
+    /// URI or base32 seed. For HOTP, the advanced counter is stored back in
+    /// the credential, and the vault must be saved afterwards.
+    pub fn generate_otp(&mut self, item_key: &str, cred_key: &str) -> Result<OtpCode, Error> {
+        let secret = self
+            .get_secret(item_key, cred_key)?
+            .ok_or_else(|| Error::InvalidCredentialKey(format!("{item_key}/{cred_key}")))?;
+        let otp = OtpConfig::parse(secret.expose_secret())?;
+        let now = OffsetDateTime::now_utc().unix_timestamp() as u64;
+        let code = otp.generate(now);
+        if let Some(next_uri) = otp.next_counter_uri() {
+            let vault = self.get_unlocked_vault_mut()?;
+            vault.replace_item_credential_secret(item_key, cred_key, next_uri.into())?;
+        }
+        Ok(code)
     }
 
     pub fn get_secret_overview(
@@ -249,13 +597,84 @@ impl VaultWrapper {
         vault.delete_item_credential(item_key, cred_key)
     }
 
+    pub fn get_secret_history(
+        &self,
+        item_key: &str,
+        cred_key: &str,
+    ) -> Result<&[EncryptedCredentialHistoryEntry], Error> {
+        let vault = self.get_unlocked_vault()?;
+        vault.get_item_credential_history(item_key, cred_key)
+    }
+
+    pub fn get_secret_version(
+        &self,
+        item_key: &str,
+        cred_key: &str,
+        version: u32,
+    ) -> Result<SecretBox<String>, Error> {
+        let vault = self.get_unlocked_vault()?;
+        vault.get_item_credential_history_secret(item_key, cred_key, version)
+    }
+
+    pub fn rollback_secret(
+        &mut self,
+        item_key: &str,
+        cred_key: &str,
+        version: u32,
+    ) -> Result<(), Error> {
+        let vault = self.get_unlocked_vault_mut()?;
+        vault.rollback_item_credential(item_key, cred_key, version)
+    }
+
+    /// Rotate the vault's file key, see Vault::rotate_file_key.
+    pub fn rotate_file_key(&mut self) -> Result<(), Error> {
+        let vault = self.get_unlocked_vault_mut()?;
+        let user_encryption_key = match vault.file_key() {
+            VaultFileKey::Personal(_) => Some(get_vault_encryption_key()?),
+            _ => None,
+        };
+        vault.rotate_file_key(user_encryption_key.as_deref())
+    }
+
+    pub fn list_members(&self) -> Result<&[VaultMember], Error> {
+        let vault = self.get_unlocked_vault()?;
+        Ok(vault.file_key().members())
+    }
+
+    /// Share the vault with the holder of public_key. A personal vault is
+    /// converted to a vault with members first, with the local vault key as
+    /// the first member. If members_file is given, members are stored in that
+    /// file (relative to the vault file) instead of in the vault itself.
+    pub fn add_member(
+        &mut self,
+        public_key: &str,
+        members_file: Option<PathBuf>,
+    ) -> Result<(), Error> {
+        let member_key: MemberKey = public_key.parse()?;
+        let vault = self.get_unlocked_vault_mut()?;
+        if matches!(vault.file_key(), VaultFileKey::Personal(_)) {
+            vault.convert_to_members(&local_member_key()?);
+        }
+        vault.add_member(&member_key)?;
+        if members_file.is_some() {
+            vault.set_members_file(members_file)?;
+        }
+        Ok(())
+    }
+
+    pub fn remove_member(&mut self, public_key: &str) -> Result<(), Error> {
+        let member_key: MemberKey = public_key.parse()?;
+        let vault = self.get_unlocked_vault_mut()?;
+        vault.remove_member(&member_key)
+    }
+
     pub fn export(&self, path: &Path, export_mode: ExportMode) -> Result<(), Error> {
         let vault = self.get_unlocked_vault()?;
         let vault_key = (self.key != DEFAULT_VAULT).then(|| self.key.clone());
         let exported = vault.into_export(vault_key, export_mode)?;
         let json =
             serde_json::to_string_pretty(&exported).map_err(Error::VaultSerializationError)?;
-        fs::write(path, json).map_err(Error::VaultWriteError)?;
+        write_atomic(path, json.as_bytes()).map_err(Error::VaultWriteError)?;
         Ok(())
     }
 }
@@ -281,7 +700,52 @@ pub fn normalized_key(key: &str) -> Option<String> {
     }
 }
 
-pub fn get_vault_encryption_key() -> Result<ManagedKey, Error> {
+/// Public key of the local vault encryption key, as used to identify the
+/// local user in a vault's members.
+pub fn local_member_key() -> Result<MemberKey, Error> {
+    member_key(get_vault_encryption_key()?.as_ref())
+}
+
+/// Like local_member_key, but creates the software key file if it doesn't
+/// exist yet, for sharing the public key before any vault exists.
+pub fn create_local_member_key() -> Result<MemberKey, Error> {
+    member_key(get_or_create_vault_encryption_key()?.as_ref())
+}
+
+fn member_key(user_encryption_key: &dyn VaultKey) -> Result<MemberKey, Error> {
+    let public_key = user_encryption_key
+        .public_key()
+        .map_err(Error::KeyRetrievalFailed)?;
+    Ok(MemberKey::SecureEnclave(public_key))
+}
+
+/// The local vault key: the software key in the key file set by
+/// AXO_VAULT_KEY_FILE or the vault_key_file config option, or the Secure
+/// Enclave key otherwise. A missing key file is an error, see
+/// get_or_create_vault_encryption_key.
+pub fn get_vault_encryption_key() -> Result<Box<dyn VaultKey>, Error> {
+    match vault_key_file() {
+        Some(key_file) => Ok(Box::new(SoftwareKey::open(&key_file)?)),
+        None => Ok(Box::new(get_secure_enclave_key()?)),
+    }
+}
+
+/// Like get_vault_encryption_key, but creates the key file if it doesn't
+/// exist yet. Only used when creating a vault, or by ap vault member key.
+pub fn get_or_create_vault_encryption_key() -> Result<Box<dyn VaultKey>, Error> {
+    match vault_key_file() {
+        Some(key_file) => Ok(Box::new(SoftwareKey::load_or_create(&key_file)?)),
+        None => Ok(Box::new(get_secure_enclave_key()?)),
+    }
+}
+
+fn vault_key_file() -> Option<PathBuf> {
+    std::env::var_os(VAULT_KEY_FILE_ENV)
+        .map(PathBuf::from)
+        .or_else(|| APP_CONFIG.lock().unwrap().vault_key_file.clone())
+}
+
+fn get_secure_enclave_key() -> Result<ManagedKey, Error> {
     let reason = match Provenance::resolve_current_parent()
         .inspect(|provenance| log::debug!("get_vault_encryption_key: {provenance:#?}"))
         .and_then(|p| p.caller())
@@ -1,39 +1,97 @@
-use std::collections::{BTreeMap, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use secrecy::ExposeSecret;
+use time::OffsetDateTime;
 
 use crate::core::config::APP_CONFIG;
 use crate::core::dirs::vaults_dir;
+use crate::secrets::vaults::attachment_files::AttachmentFiles;
 use crate::secrets::vaults::errors::Error;
+use crate::secrets::vaults::reference::{ItemReference, ReferenceQuery, ReferenceSource};
+use crate::secrets::vaults::search::{SearchMatch, search_vaults};
+use crate::secrets::vaults::vault::encrypted_vault::EncryptedVault;
 use crate::secrets::vaults::vault_export::{ImportIdentity, import_vault};
-use crate::secrets::vaults::vault_wrapper::{VaultWrapper, get_vault_encryption_key};
+use crate::secrets::vaults::vault_key::VaultKey;
+use crate::secrets::vaults::vault_wrapper::{
+    DEFAULT_VAULT, VaultWrapper, age_identity_from_env, get_or_create_vault_encryption_key,
+    get_vault_encryption_key,
+};
+
+// limit on aliases followed when resolving a reference, in case of cycles
+const MAX_ALIAS_HOPS: usize = 8;
+
+// vault for references without one, e.g. axo:///item/credential
+const DEFAULT_VAULT_ENV: &str = "AXO_DEFAULT_VAULT";
 
 #[derive(Default)]
 pub struct VaultsManager {
     vaults_dir: PathBuf,
     vaults: HashMap<String, VaultWrapper>,
+    // only set for commands that can clean up decrypted attachments, see
+    // enable_attachment_files
+    attachment_files: Option<AttachmentFiles>,
+    // see set_default_vault
+    default_vault: Option<String>,
 }
 
 impl VaultsManager {
     pub fn new() -> Self {
-        let vaults_dir = vaults_dir();
+        Self::with_vaults_dir(&vaults_dir())
+    }
+
+    /// Like new, with the vaults in vaults_dir instead of the app's vaults
+    /// directory (external vaults from the app config are still included).
+    pub fn with_vaults_dir(vaults_dir: &Path) -> Self {
         Self {
-            vaults: Self::discover_vaults(&vaults_dir),
+            vaults: Self::discover_vaults(vaults_dir),
             vaults_dir: vaults_dir.to_owned(),
+            attachment_files: None,
+            default_vault: std::env::var(DEFAULT_VAULT_ENV)
+                .ok()
+                .filter(|vault_key| !vault_key.is_empty()),
         }
     }
 
+    /// Vault that references without one (axo:///item/credential) resolve
+    /// against. Defaults to AXO_DEFAULT_VAULT, or the default vault.
+    pub fn set_default_vault(&mut self, vault_key: String) {
+        self.default_vault = Some(vault_key);
+    }
+
+    pub fn default_vault(&self) -> &str {
+        self.default_vault.as_deref().unwrap_or(DEFAULT_VAULT)
+    }
+
+    /// Allow `?file` references, which resolve to the path of a temporary file
+    /// holding the decrypted attachment. The files are removed when the
+    /// AttachmentFiles returned by take_attachment_files (or this manager) is
+    /// dropped.
+    pub fn enable_attachment_files(&mut self) -> Result<(), Error> {
+        self.attachment_files =
+            Some(AttachmentFiles::create().map_err(Error::AttachmentWriteError)?);
+        Ok(())
+    }
+
+    pub fn take_attachment_files(&mut self) -> Option<AttachmentFiles> {
+        self.attachment_files.take()
+    }
+
     pub fn add_vault(
         &mut self,
         name: Option<String>,
         vault_key: &str,
     ) -> Result<&VaultWrapper, Error> {
-        let user_encryption_key = get_vault_encryption_key()?;
+        let user_encryption_key = get_or_create_vault_encryption_key()?;
 
-        let vw = VaultWrapper::new_vault(name, &self.vaults_dir, vault_key, user_encryption_key)?;
+        let mut vw = VaultWrapper::new_vault(
+            name,
+            &self.vaults_dir,
+            vault_key,
+            user_encryption_key.as_ref(),
+        )?;
         let vault_key = vw.key.clone(); // normalized key
 
         log::debug!("Vault created, saving new vault to disk...");
@@ -186,27 +244,482 @@ impl VaultsManager {
     }
 
     pub fn get_secret_by_url(&mut self, item_url: &str) -> Result<Option<String>, Error> {
-        let Ok(u) = url::Url::parse(item_url) else {
-            return Err(Error::InvalidVaultItemReference(item_url.to_string()));
+        let reference = item_url.parse::<ItemReference>()?;
+        self.get_secret_by_reference(&reference)
+    }
+
+    /// Resolve a reference (with a credential) and apply its query modifiers,
+    /// see ReferenceQuery::apply. Missing items and credentials resolve to
+    /// None (or the ?default).
+    pub fn get_secret_by_reference(
+        &mut self,
+        reference: &ItemReference,
+    ) -> Result<Option<String>, Error> {
+        let (vault_key, reference) = self.resolve_reference(reference)?;
+        let Some(cred_key) = reference.credential.as_deref() else {
+            return Err(Error::InvalidVaultItemReference(reference.to_string()));
         };
-        if u.scheme() != "axo" {
-            return Err(Error::InvalidVaultItemReference(item_url.to_string()));
-        }
-        let Some(vault_key) = u.host_str() else {
-            return Err(Error::InvalidVaultItemReference(item_url.to_string()));
+        let item_key = &reference.item;
+        let vault = self
+            .vaults
+            .get_mut(&vault_key)
+            .ok_or_else(|| Error::VaultNotFound(vault_key.clone()))?;
+        let value = match reference.query.source {
+            ReferenceSource::File => {
+                let Some(attachment_files) = &self.attachment_files else {
+                    return Err(Error::InvalidVaultItemReference(format!(
+                        "{reference}: ?file references are only supported by ap exec"
+                    )));
+                };
+                Self::get_attachment_file(vault, attachment_files, item_key, cred_key)
+                    .map(Some)
+                    .inspect_err(|e| log::error!("Error writing attachment {reference}: {e:?}"))
+                    .map_err(|e| Error::SecretRetrievalFailed(reference.to_string(), e.into()))?
+            },
+            ReferenceSource::Totp => Self::get_otp(vault, item_key, cred_key)
+                .map(Some)
+                .inspect_err(|e| log::error!("Error generating code for {reference}: {e:?}"))
+                .map_err(|e| Error::SecretRetrievalFailed(reference.to_string(), e.into()))?,
+            ReferenceSource::Value => {
+                let secret = match vault.get_secret_overview(item_key, cred_key) {
+                    Ok(Some(_)) => vault.get_secret(item_key, cred_key),
+                    other => other.map(|_| None),
+                };
+                match secret {
+                    Ok(secret) => secret.map(|s| s.expose_secret().to_string()),
+                    Err(e) => {
+                        log::error!("Error retrieving {reference}: {e:?}");
+                        return Err(Error::SecretRetrievalFailed(
+                            reference.to_string(),
+                            e.into(),
+                        ));
+                    },
+                }
+            },
         };
-        let Some(vault) = self.vaults.get_mut(vault_key) else {
-            return Err(Error::VaultNotFound(vault_key.to_string()));
+        reference.query.apply(value)
+    }
+
+    /// Keys of the credentials of the referenced item (which must not have a
+    /// credential), in order.
+    pub fn get_credential_keys(&mut self, reference: &ItemReference) -> Result<Vec<String>, Error> {
+        if reference.credential.is_some() || reference.query != ReferenceQuery::default() {
+            return Err(Error::InvalidVaultItemReference(reference.to_string()));
+        }
+        let (vault_key, reference) = self.resolve_reference(reference)?;
+        let vault = self
+            .vaults
+            .get(&vault_key)
+            .ok_or_else(|| Error::VaultNotFound(vault_key.clone()))?;
+        let item = vault
+            .get_item_overview(&reference.item)?
+            .ok_or_else(|| Error::InvalidItemKey(reference.item.clone()))?;
+        let mut keys = item
+            .credentials
+            .values()
+            .map(|cred| cred.key.clone())
+            .collect::<Vec<_>>();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// Unlock the vaults of the given references (the default vault for
+    /// references without one), each once. The local vault key is fetched
+    /// once for all of them, and the vault files are read and decrypted in
+    /// parallel. Returns the vaults that couldn't be unlocked, with the error,
+    /// or an error if the vault key or age identity couldn't be loaded.
+    pub fn unlock_vaults_for(
+        &mut self,
+        references: &[ItemReference],
+    ) -> Result<BTreeMap<String, Error>, Error> {
+        self.unlock_vaults_with_key(references, get_vault_encryption_key)
+    }
+
+    fn unlock_vaults_with_key(
+        &mut self,
+        references: &[ItemReference],
+        vault_encryption_key: impl FnOnce() -> Result<Box<dyn VaultKey>, Error>,
+    ) -> Result<BTreeMap<String, Error>, Error> {
+        let vault_keys = references
+            .iter()
+            .map(|reference| {
+                reference
+                    .vault
+                    .clone()
+                    .unwrap_or_else(|| self.default_vault().to_string())
+            })
+            .collect::<BTreeSet<_>>();
+        let mut errors = vault_keys
+            .iter()
+            .filter(|vault_key| !self.vaults.contains_key(*vault_key))
+            .map(|vault_key| (vault_key.clone(), Error::VaultNotFound(vault_key.clone())))
+            .collect::<BTreeMap<_, _>>();
+        let unlocking = self
+            .vaults
+            .iter_mut()
+            .filter(|(vault_key, vault)| vault_keys.contains(*vault_key) && !vault.is_unlocked())
+            .collect::<Vec<_>>();
+        if unlocking.is_empty() {
+            return Ok(errors);
+        }
+
+        let read = in_parallel(
+            unlocking
+                .iter()
+                .map(|(_, vault)| vault.path.as_path())
+                .collect(),
+            EncryptedVault::load_with_digest,
+        );
+        let mut decrypting = Vec::new();
+        for ((vault_key, vault), read) in unlocking.into_iter().zip(read) {
+            match read {
+                Ok((encrypted_vault, file_digest)) => {
+                    decrypting.push((vault_key, vault, encrypted_vault, file_digest))
+                },
+                Err(e) => {
+                    errors.insert(vault_key.clone(), e);
+                },
+            }
+        }
+
+        // file keys are decrypted here, since the vault key can't be shared
+        // across threads, and it's only fetched if the age identity (if any)
+        // can't decrypt them all
+        let age_identity = age_identity_from_env()?;
+        let age_ciphers = decrypting
+            .iter()
+            .map(|(_, vault, encrypted_vault, _)| {
+                vault.age_file_cipher(encrypted_vault, age_identity.as_ref())
+            })
+            .collect::<Vec<_>>();
+        let user_encryption_key = match age_ciphers.iter().any(Option::is_none) {
+            true => Some(vault_encryption_key()?),
+            false => None,
         };
-        vault.unlock().inspect_err(|e| {
-            log::error!("Error unlocking vault {vault_key}: {e:?}");
-        })?;
-        match vault.get_secret_by_url(u) {
-            Ok(secret) => Ok(secret.map(|s| s.expose_secret().to_string())),
-            Err(e) => {
-                log::error!("Error retrieving {item_url}: {e:?}");
-                Err(Error::SecretRetrievalFailed(item_url.to_string(), e.into()))
+        let mut unlocked = Vec::new();
+        for ((vault_key, vault, encrypted_vault, file_digest), age_cipher) in
+            decrypting.into_iter().zip(age_ciphers)
+        {
+            let vault_cipher = match (age_cipher, &user_encryption_key) {
+                (Some(vault_cipher), _) => Ok(vault_cipher),
+                (None, Some(user_encryption_key)) => {
+                    encrypted_vault.decrypt_file_key(user_encryption_key.as_ref())
+                },
+                (None, None) => Err(Error::VaultFileKeyDecryptionError),
+            };
+            match vault_cipher {
+                Ok(vault_cipher) => {
+                    unlocked.push((vault_key, vault, vault_cipher, encrypted_vault, file_digest))
+                },
+                Err(e) => {
+                    errors.insert(vault_key.clone(), e);
+                },
+            }
+        }
+
+        let results = in_parallel(
+            unlocked,
+            |(vault_key, vault, vault_cipher, encrypted_vault, file_digest)| {
+                (
+                    vault_key,
+                    vault.unlock_with_cipher(vault_cipher, encrypted_vault, file_digest),
+                )
             },
+        );
+        for (vault_key, result) in results {
+            if let Err(e) = result {
+                log::error!("Error unlocking vault {vault_key}: {e:?}");
+                errors.insert(vault_key.clone(), e);
+            }
+        }
+        Ok(errors)
+    }
+
+    // Unlocks the referenced vault (the default vault if it has none) unless
+    // it's already unlocked, and follows aliases, returning the vault key and
+    // the final reference.
+    fn resolve_reference(
+        &mut self,
+        reference: &ItemReference,
+    ) -> Result<(String, ItemReference), Error> {
+        let mut reference = reference.clone();
+        for _ in 0..=MAX_ALIAS_HOPS {
+            let vault_key = reference
+                .vault
+                .clone()
+                .unwrap_or_else(|| self.default_vault().to_string());
+            let Some(vault) = self.vaults.get_mut(&vault_key) else {
+                return Err(Error::VaultNotFound(vault_key));
+            };
+            if !vault.is_unlocked() {
+                vault.unlock().inspect_err(|e| {
+                    log::error!("Error unlocking vault {vault_key}: {e:?}");
+                })?;
+            }
+            match Self::forward_alias(vault, &reference) {
+                Some(forwarded) => {
+                    log::debug!("Following alias {reference} to {forwarded}");
+                    reference = forwarded;
+                },
+                None => return Ok((vault_key, reference)),
+            }
+        }
+        Err(Error::InvalidVaultItemReference(format!(
+            "too many aliases: {reference}"
+        )))
+    }
+
+    /// Search credentials across all unlocked vaults, see search_vaults.
+    pub fn search(&self, query: &str) -> Vec<SearchMatch> {
+        search_vaults(self.vaults.values(), query)
+    }
+
+    /// Expiry of the credential referenced by the url, if it has one. The
+    /// vault must already be unlocked, e.g. by get_secret_by_url.
+    pub fn get_expiry_by_url(&self, item_url: &str) -> Option<OffsetDateTime> {
+        let reference = item_url.parse::<ItemReference>().ok()?;
+        let vault_key = reference.vault.as_deref().unwrap_or(self.default_vault());
+        let vault = self.vaults.get(vault_key)?;
+        vault
+            .get_secret_overview(&reference.item, reference.credential.as_deref()?)
+            .ok()
+            .flatten()
+            .and_then(|cred| cred.expires_at)
+    }
+
+    // If the referenced item was moved or renamed with an alias (see
+    // Vault::add_item_alias), the reference rewritten to the alias target. The
+    // credential and query modifiers are kept.
+    fn forward_alias(vault: &VaultWrapper, reference: &ItemReference) -> Option<ItemReference> {
+        if vault
+            .get_item_overview(&reference.item)
+            .ok()
+            .flatten()
+            .is_some()
+        {
+            return None;
+        }
+        let alias = vault.get_item_alias(&reference.item).ok().flatten()?;
+        let target = alias
+            .target
+            .trim_end_matches('/')
+            .parse::<ItemReference>()
+            .inspect_err(|e| log::error!("Invalid alias target {}: {e}", alias.target))
+            .ok()?;
+        Some(ItemReference {
+            vault: target.vault.or_else(|| Some(vault.key.clone())),
+            item: target.item,
+            credential: reference.credential.clone(),
+            query: reference.query.clone(),
+        })
+    }
+
+    // axo://vault/item/attachment?file writes the attachment to a temporary
+    // file and resolves to its path
+    fn get_attachment_file(
+        vault: &VaultWrapper,
+        attachment_files: &AttachmentFiles,
+        item_key: &str,
+        attachment_key: &str,
+    ) -> Result<String, Error> {
+        let file_name = vault
+            .get_attachment_overview(item_key, attachment_key)?
+            .map(|attachment| attachment.file_name.clone())
+            .ok_or_else(|| Error::InvalidAttachmentKey(format!("{item_key}/{attachment_key}")))?;
+        let data = vault.get_attachment(item_key, attachment_key)?;
+        let path = attachment_files
+            .write(&file_name, data.expose_secret())
+            .map_err(Error::AttachmentWriteError)?;
+        Ok(path.to_string_lossy().to_string())
+    }
+
+    // axo://vault/item/cred?totp generates a one-time password from the
+    // credential, see VaultWrapper::generate_otp
+    fn get_otp(vault: &mut VaultWrapper, item_key: &str, cred_key: &str) -> Result<String, Error> {
+        let code = vault.generate_otp(item_key, cred_key)?;
+        if code.seconds_remaining.is_none() {
+            // HOTP counter was advanced
+            vault.save()?;
+        }
+        Ok(code.code)
+    }
+}
+
+// Runs f on each item on its own thread, returning the results in order.
+fn in_parallel<T: Send, R: Send>(items: Vec<T>, f: impl Fn(T) -> R + Sync) -> Vec<R> {
+    let f = &f;
+    std::thread::scope(|scope| {
+        let handles = items
+            .into_iter()
+            .map(|item| scope.spawn(move || f(item)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use criterion::{BatchSize, Criterion};
+    use ssh_key::public::KeyData;
+
+    use super::*;
+    use crate::core::interpolate::Interpolator;
+    use crate::secrets::keychain::errors::KeychainError;
+    use crate::secrets::vaults::reference::find_references;
+    use crate::secrets::vaults::test_vaults::TestVaults;
+    use crate::secrets::vaults::vault_key::SoftwareKey;
+
+    // counts the file keys it decrypts
+    struct CountingKey {
+        key: Arc<SoftwareKey>,
+        decrypted: Arc<AtomicUsize>,
+    }
+
+    impl VaultKey for CountingKey {
+        fn encrypt(&self, plaintext: &[u8]) -> Option<String> {
+            self.key.encrypt(plaintext)
+        }
+
+        fn decrypt(&self, b64_ciphertext: &[u8]) -> Option<Vec<u8>> {
+            self.decrypted.fetch_add(1, Ordering::SeqCst);
+            self.key.decrypt(b64_ciphertext)
+        }
+
+        fn public_key(&self) -> Result<KeyData, KeychainError> {
+            self.key.public_key()
+        }
+    }
+
+    #[test]
+    fn test_unlock_vaults_for() {
+        let test_vaults = TestVaults::new(&[
+            ("app", "database", "password", "app-secret"),
+            ("ops", "database", "password", "ops-secret"),
+            ("web", "database", "password", "web-secret"),
+        ]);
+        let mut vaults = test_vaults.manager();
+        vaults.set_default_vault("ops".to_string());
+        let references = [
+            "axo://app/database/password",
+            "axo://app/database/password?encoding=base64",
+            "axo:///database/password",
+            "axo://missing/database/password",
+        ]
+        .map(|reference| reference.parse::<ItemReference>().unwrap());
+
+        let decrypted = Arc::new(AtomicUsize::new(0));
+        let mut fetched = 0;
+        let errors = vaults
+            .unlock_vaults_with_key(&references, || {
+                fetched += 1;
+                Ok(Box::new(CountingKey {
+                    key: test_vaults.key.clone(),
+                    decrypted: decrypted.clone(),
+                }))
+            })
+            .unwrap();
+        // the key is fetched once, and decrypts each vault's file key once
+        assert_eq!(fetched, 1);
+        assert_eq!(decrypted.load(Ordering::SeqCst), 2);
+        assert_eq!(errors.keys().collect::<Vec<_>>(), ["missing"]);
+        assert!(vaults.get_vault("app").unwrap().is_unlocked());
+        assert!(vaults.get_vault("ops").unwrap().is_unlocked());
+        assert!(!vaults.get_vault("web").unwrap().is_unlocked());
+        assert_eq!(
+            vaults
+                .get_secret_by_url("axo:///database/password")
+                .unwrap(),
+            Some("ops-secret".to_string())
+        );
+
+        // vaults that are already unlocked don't need the key
+        let errors = vaults
+            .unlock_vaults_with_key(&references[..3], || panic!("vault key fetched"))
+            .unwrap();
+        assert!(errors.is_empty());
+    }
+
+    // unlocking the referenced vaults and interpolating a .env file with 40
+    // references against large synthetic vaults, run with:
+    // cargo test --release bench_interpolate -- --ignored --nocapture
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_interpolate() {
+        const VAULTS: usize = 4;
+        const ITEMS: usize = 500;
+        const CREDENTIALS: usize = 4;
+        const REFERENCES: usize = 40;
+
+        let values = (0..VAULTS * ITEMS * CREDENTIALS)
+            .map(|n| {
+                let (v, i, c) = (
+                    n / (ITEMS * CREDENTIALS),
+                    n / CREDENTIALS % ITEMS,
+                    n % CREDENTIALS,
+                );
+                [
+                    format!("vault-{v}"),
+                    format!("item-{i}"),
+                    format!("cred-{c}"),
+                    format!("secret-{v}-{i}-{c}"),
+                ]
+            })
+            .collect::<Vec<_>>();
+        let test_vaults = TestVaults::new(
+            &values
+                .iter()
+                .map(|[v, i, c, value]| (v.as_str(), i.as_str(), c.as_str(), value.as_str()))
+                .collect::<Vec<_>>(),
+        );
+
+        let mut criterion = Criterion::default().sample_size(10);
+        let mut group = criterion.benchmark_group("interpolate");
+        for vault_count in [1, VAULTS] {
+            let input = (0..REFERENCES)
+                .map(|n| {
+                    format!(
+                        "VAR_{n}=axo://vault-{}/item-{}/cred-{}\n",
+                        n % vault_count,
+                        n * 7 % ITEMS,
+                        n % CREDENTIALS
+                    )
+                })
+                .collect::<String>();
+            let references = find_references(&input)
+                .map(|reference| reference.parse::<ItemReference>().unwrap())
+                .collect::<Vec<_>>();
+            let name = format!("{REFERENCES} references, {vault_count} vaults");
+            group.bench_function(name, |b| {
+                b.iter_batched(
+                    || test_vaults.manager(),
+                    |mut vaults| {
+                        vaults
+                            .unlock_vaults_with_key(&references, || {
+                                Ok(Box::new(CountingKey {
+                                    key: test_vaults.key.clone(),
+                                    decrypted: Arc::default(),
+                                }))
+                            })
+                            .unwrap();
+                        let mut interpolator = Interpolator::new(&mut vaults);
+                        let output = interpolator.interpolate(&input);
+                        assert!(interpolator.failures().is_empty());
+                        output
+                    },
+                    BatchSize::PerIteration,
+                )
+            });
         }
+        group.finish();
     }
 }
@@ -34,11 +34,21 @@ export interface AddManagedSshKeyResponse {
   key: SshKeyEntry;
 }
 
+export enum CredentialKind {
+  Password = 'password',
+  Username = 'username',
+  Url = 'url',
+  Note = 'note',
+  TotpSeed = 'totp_seed',
+  SshKey = 'ssh_key',
+}
+
 export interface AddOrUpdateCredentialRequest {
   vault_key: string;
   item_key: string;
   credential_key: string;
   title: string;
+  kind?: CredentialKind;
   value: string;
 }
 
@@ -164,11 +174,14 @@ export interface UpdateVaultRequest {
 export interface VaultItemCredentialSchema {
   id: string;
   title: string;
+  kind: CredentialKind;
+  expires_at?: string;
 }
 
 export interface VaultItemSchema {
   id: string;
   title: string;
+  tags: string[];
   credentials: Record<string, VaultItemCredentialSchema>;
 }
 